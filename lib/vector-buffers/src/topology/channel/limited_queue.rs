@@ -85,7 +85,10 @@ impl<T: Bufferable> LimitedSender<T> {
         // We have to limit the number of permits we ask for to the overall limit since we're always
         // willing to store more items than the limit if the queue is entirely empty, because
         // otherwise we might deadlock ourselves by not being able to send a single item.
-        cmp::min(self.inner.limit, item.event_count()) as u32
+        //
+        // Likewise, we always ask for at least one permit, since even an item with no events still
+        // takes up a slot in the underlying queue, and we can't hold more items than it has slots.
+        cmp::min(self.inner.limit, cmp::max(item.event_count(), 1)) as u32
     }
 
     /// Gets the number of items that this channel could accept.
@@ -93,6 +96,32 @@ impl<T: Bufferable> LimitedSender<T> {
         self.inner.limiter.available_permits()
     }
 
+    /// Gets the total number of items that this channel can hold.
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Waits until the channel has room for an item of the given number of events, without sending
+    /// anything.
+    ///
+    /// Room is judged the same way as when sending, so an item that has room once this returns can
+    /// be sent without waiting, unless something else is sent first.
+    ///
+    /// # Errors
+    ///
+    /// If the receiver has disconnected (does not exist anymore), then `Err(())` is returned.
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn wait_for_room(&self, event_count: usize) -> Result<(), ()> {
+        let permits_required = cmp::min(self.inner.limit, cmp::max(event_count, 1)) as u32;
+        // The permits are handed back as soon as they're acquired, since only the wait matters.
+        self.inner
+            .limiter
+            .acquire_many(permits_required)
+            .await
+            .map(drop)
+            .map_err(drop)
+    }
+
     /// Sends an item into the channel.
     ///
     /// # Errors
@@ -284,6 +313,27 @@ mod tests {
         assert_eq!(Some(msg), assert_ready!(recv.poll()));
     }
 
+    #[test]
+    fn wait_for_room_waits_for_capacity_without_taking_it() {
+        let (mut tx, mut rx) = limited(1);
+        assert_eq!(Ok(()), assert_ready!(spawn(tx.send(42)).poll()));
+
+        // With the channel full, waiting for room blocks until the item is received:
+        let mut wait = spawn(tx.wait_for_room(1));
+        assert_pending!(wait.poll());
+        assert_eq!(Some(42), assert_ready!(spawn(rx.next()).poll()));
+        assert!(wait.is_woken());
+        assert_eq!(Ok(()), assert_ready!(wait.poll()));
+        drop(wait);
+
+        // And the room is still there afterwards:
+        assert_eq!(1, tx.available_capacity());
+
+        // Once the receiver is gone, there's never going to be room:
+        drop(rx);
+        assert_eq!(Err(()), assert_ready!(spawn(tx.wait_for_room(1)).poll()));
+    }
+
     #[test]
     fn sender_waits_for_more_capacity_when_none_available() {
         let (mut tx, mut rx) = limited(1);
@@ -536,6 +586,34 @@ mod tests {
         assert_eq!(1, rx.available_capacity());
     }
 
+    #[test]
+    fn empty_send_takes_up_a_slot() {
+        let (mut tx, mut rx) = limited(1);
+
+        assert_eq!(1, tx.available_capacity());
+
+        let msg1 = MultiEventRecord(0);
+        let msg2 = MultiEventRecord(0);
+
+        // Even though our item has no events, it should still consume a slot.
+        let mut send = spawn(async { tx.send(msg1).await });
+        assert_eq!(Ok(()), assert_ready!(send.poll()));
+        drop(send);
+
+        assert_eq!(0, tx.available_capacity());
+
+        // So our second send has to wait until the first item is received.
+        let mut send2 = spawn(async { tx.send(msg2).await });
+        assert_pending!(send2.poll());
+
+        let mut recv = spawn(async { rx.next().await });
+        assert_eq!(Some(msg1), assert_ready!(recv.poll()));
+        drop(recv);
+
+        assert!(send2.is_woken());
+        assert_eq!(Ok(()), assert_ready!(send2.poll()));
+    }
+
     #[test]
     fn oversized_send_allowed_when_partial_capacity() {
         let (mut tx, mut rx) = limited(2);
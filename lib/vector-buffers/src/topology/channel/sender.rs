@@ -3,7 +3,7 @@ use std::sync::Arc;
 use async_recursion::async_recursion;
 use tokio::sync::Mutex;
 
-use super::limited_queue::LimitedSender;
+use super::limited_queue::{LimitedSender, SendError, TrySendError};
 use crate::{
     buffer_usage_data::BufferUsageHandle,
     variants::{
@@ -48,9 +48,10 @@ impl<T> SenderAdapter<T>
 where
     T: Bufferable,
 {
-    pub(crate) async fn send(&mut self, item: T) -> Result<(), ()> {
+    /// Sends an item, handing it back if the channel is closed.
+    pub(crate) async fn send(&mut self, item: T) -> Result<(), T> {
         match self {
-            Self::InMemory(tx) => tx.send(item).await.map_err(|_| ()),
+            Self::InMemory(tx) => tx.send(item).await.map_err(|SendError(item)| item),
             Self::DiskV1(writer) => {
                 writer.send(item).await;
                 Ok(())
@@ -68,13 +69,10 @@ where
         }
     }
 
-    pub(crate) async fn try_send(&mut self, item: T) -> Result<Option<T>, ()> {
+    pub(crate) async fn try_send(&mut self, item: T) -> Option<T> {
         match self {
-            Self::InMemory(tx) => tx
-                .try_send(item)
-                .map(|()| None)
-                .or_else(|e| Ok(Some(e.into_inner()))),
-            Self::DiskV1(writer) => Ok(writer.try_send(item)),
+            Self::InMemory(tx) => tx.try_send(item).err().map(TrySendError::into_inner),
+            Self::DiskV1(writer) => writer.try_send(item),
             Self::DiskV2(writer) => {
                 let mut writer = writer.lock().await;
 
@@ -85,9 +83,9 @@ where
                                 // Can't really do much except panic here. :sweat:
                                 panic!("writer hit unrecoverable error during flush: {}", e);
                             }
-                            Ok(None)
+                            None
                         }
-                        Some(item) => Ok(Some(item)),
+                        Some(item) => Some(item),
                     },
                     Err(e) => {
                         // Can't really do much except panic here. :sweat:
@@ -124,6 +122,31 @@ where
             Self::DiskV1(_) | Self::DiskV2(_) => None,
         }
     }
+
+    /// Gets the number of items currently held by this sender's buffer, along with the total number
+    /// of items it can hold.
+    ///
+    /// Only in-memory buffers are able to report this, so `None` is returned for disk buffers.
+    pub fn occupancy(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::InMemory(tx) => {
+                let limit = tx.limit();
+                Some((limit.saturating_sub(tx.available_capacity()), limit))
+            }
+            Self::DiskV1(_) | Self::DiskV2(_) => None,
+        }
+    }
+
+    /// Waits until this sender's buffer has room for an item of the given number of events.
+    ///
+    /// Only in-memory buffers are able to report their occupancy, so disk buffers are assumed to
+    /// always have room.
+    pub(crate) async fn wait_for_room(&self, event_count: usize) -> Result<(), ()> {
+        match self {
+            Self::InMemory(tx) => tx.wait_for_room(event_count).await,
+            Self::DiskV1(_) | Self::DiskV2(_) => Ok(()),
+        }
+    }
 }
 
 /// A buffer sender.
@@ -191,6 +214,37 @@ impl<T: Bufferable> BufferSender<T> {
     pub fn with_instrumentation(&mut self, handle: BufferUsageHandle) {
         self.instrumentation = Some(handle);
     }
+
+    /// Gets the number of items currently held by the base buffer of this sender, along with the
+    /// total number of items it can hold.
+    ///
+    /// Returns `None` if the base buffer is unable to report its occupancy.
+    pub fn occupancy(&self) -> Option<(usize, usize)> {
+        self.base.occupancy()
+    }
+
+    /// Waits until the base buffer of this sender has room for an item of the given number of
+    /// events, as judged by `occupancy`, without sending anything.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer has been closed, an error is returned.
+    pub async fn wait_for_room(&self, event_count: usize) -> Result<(), ()> {
+        self.base.wait_for_room(event_count).await
+    }
+
+    /// Whether or not this sender drops items when its buffer is full, either directly or by way
+    /// of the last buffer it overflows into.
+    pub fn drops_when_full(&self) -> bool {
+        match self.when_full {
+            WhenFull::Block => false,
+            WhenFull::DropNewest => true,
+            WhenFull::Overflow => self
+                .overflow
+                .as_ref()
+                .map_or(false, |overflow| overflow.drops_when_full()),
+        }
+    }
 }
 
 impl<T: Bufferable> BufferSender<T> {
@@ -204,8 +258,29 @@ impl<T: Bufferable> BufferSender<T> {
         self.overflow.as_ref().map(AsRef::as_ref)
     }
 
-    #[async_recursion]
+    /// Sends an item to this buffer.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer has been closed, an error is returned.
     pub async fn send(&mut self, item: T) -> Result<(), ()> {
+        self.send_reporting_drop(item)
+            .await
+            .map(|_| ())
+            .map_err(|_| ())
+    }
+
+    /// Sends an item to this buffer, returning whether it was dropped because the buffer was full.
+    ///
+    /// Items are only ever dropped when the buffer, or the last buffer it overflows into, is
+    /// configured to drop the newest items when full.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer has been closed, the item is handed back as the error, so that it can be sent
+    /// elsewhere or retried without having been copied beforehand.
+    #[async_recursion]
+    pub async fn send_reporting_drop(&mut self, item: T) -> Result<bool, T> {
         let item_sizing = self
             .instrumentation
             .as_ref()
@@ -213,20 +288,22 @@ impl<T: Bufferable> BufferSender<T> {
 
         let mut sent_to_base = true;
         let mut was_dropped = false;
+        let mut dropped_by_overflow = false;
         match self.when_full {
             WhenFull::Block => self.base.send(item).await?,
             WhenFull::DropNewest => {
-                if self.base.try_send(item).await?.is_some() {
+                if self.base.try_send(item).await.is_some() {
                     was_dropped = true;
                 }
             }
             WhenFull::Overflow => {
-                if let Some(item) = self.base.try_send(item).await? {
+                if let Some(item) = self.base.try_send(item).await {
                     sent_to_base = false;
-                    self.overflow
+                    dropped_by_overflow = self
+                        .overflow
                         .as_mut()
                         .expect("overflow must exist")
-                        .send(item)
+                        .send_reporting_drop(item)
                         .await?;
                 }
             }
@@ -250,7 +327,7 @@ impl<T: Bufferable> BufferSender<T> {
             }
         }
 
-        Ok(())
+        Ok(was_dropped || dropped_by_overflow)
     }
 
     #[async_recursion]
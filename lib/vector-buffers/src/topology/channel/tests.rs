@@ -112,6 +112,7 @@ async fn test_sender_block() {
 async fn test_sender_drop_newest() {
     // Get a non-overflow buffer in "drop newest" mode with a capacity of 3.
     let (mut tx, rx, _) = build_buffer(3, WhenFull::DropNewest, None).await;
+    assert!(tx.drops_when_full());
 
     // We should be able to send three messages through unimpeded.
     assert_current_send_capacity(&mut tx, Some(3), None);
@@ -137,6 +138,7 @@ async fn test_sender_overflow_block() {
     // Get an overflow buffer, where the overflow buffer is in blocking mode, and both the base
     // and overflow buffers have a capacity of 2.
     let (mut tx, rx, _) = build_buffer(2, WhenFull::Overflow, Some(WhenFull::Block)).await;
+    assert!(!tx.drops_when_full());
 
     // We should be able to send four message through unimpeded -- two for the base sender, and
     // two for the overflow sender.
@@ -163,6 +165,7 @@ async fn test_sender_overflow_drop_newest() {
     // Get an overflow buffer, where the overflow buffer is in "drop newest" mode, and both the
     // base and overflow buffers have a capacity of 2.
     let (mut tx, rx, _) = build_buffer(2, WhenFull::Overflow, Some(WhenFull::DropNewest)).await;
+    assert!(tx.drops_when_full());
 
     // We should be able to send four message through unimpeded -- two for the base sender, and
     // two for the overflow sender.
@@ -2,7 +2,7 @@
 //! This module contains the definitions and wrapper types for handling
 //! arrays of type `Event`, in the various forms they may appear.
 
-use std::{iter, slice, vec};
+use std::{iter, mem, slice, vec};
 
 use futures::{stream, Stream};
 #[cfg(test)]
@@ -381,6 +381,31 @@ pub fn events_into_arrays(
     }
 }
 
+/// Merge the given arrays into a single array for each type of event.
+///
+/// Since each `EventArray` only holds a single type of event, arrays of different types can't be
+/// merged into one. Instead, this produces one merged array per type, in the order each type was
+/// first seen, with the events of each type kept in their original order.
+pub fn merge_arrays(arrays: impl IntoIterator<Item = EventArray>) -> Vec<EventArray> {
+    let mut merged: Vec<EventArray> = Vec::new();
+    for array in arrays {
+        let existing = merged
+            .iter_mut()
+            .find(|merged| mem::discriminant(*merged) == mem::discriminant(&array));
+        match (existing, array) {
+            (Some(EventArray::Logs(existing)), EventArray::Logs(array)) => existing.extend(array),
+            (Some(EventArray::Metrics(existing)), EventArray::Metrics(array)) => {
+                existing.extend(array);
+            }
+            (Some(EventArray::Traces(existing)), EventArray::Traces(array)) => {
+                existing.extend(array);
+            }
+            (_, array) => merged.push(array),
+        }
+    }
+    merged
+}
+
 /// Iterator type implementing `into_arrays`
 pub struct IntoEventArraysIter<I> {
     inner: iter::Fuse<I>,
@@ -1,8 +1,9 @@
 #![deny(missing_docs)]
 
-use std::sync::Arc;
+use std::{cmp::Ordering, sync::Arc};
 
 use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
 use vector_common::EventDataEq;
 
 use super::{BatchNotifier, EventFinalizer, EventFinalizers, EventStatus};
@@ -10,7 +11,10 @@ use crate::{schema, ByteSizeOf};
 
 /// The top-level metadata structure contained by both `struct Metric`
 /// and `struct LogEvent` types.
-#[derive(Clone, Debug, Deserialize, PartialEq, PartialOrd, Serialize)]
+///
+/// The delivery deadline, sequence number and path are left out of comparisons, since they
+/// describe how the event was routed rather than the event itself.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct EventMetadata {
     /// Used to store the datadog API from sources to sinks
     #[serde(default, skip)]
@@ -27,6 +31,23 @@ pub struct EventMetadata {
     /// TODO(Jean): must not skip serialization to track schemas across restarts.
     #[serde(default = "default_schema_definition", skip)]
     schema_definition: Arc<schema::Definition>,
+
+    /// The time by which the event should be delivered, if any, so that sinks can act on events
+    /// that are close to, or past, their deadline.
+    #[serde(default, skip)]
+    deadline: Option<Instant>,
+
+    /// The global sequence number of the array the event was sent in, if any, so that arrays sent
+    /// to several sinks can be put back in order downstream.
+    #[serde(default, skip)]
+    sequence: Option<u64>,
+
+    /// The names of the fanouts the event was sent through, in order, so that its path through
+    /// nested fanouts can be traced downstream.
+    ///
+    /// Each name is shared with the fanout, so stamping it doesn't allocate a copy for every event.
+    #[serde(default, skip)]
+    path: Vec<Arc<str>>,
 }
 
 impl EventMetadata {
@@ -49,6 +70,62 @@ impl EventMetadata {
     pub fn set_splunk_hec_token(&mut self, token: Option<Arc<str>>) {
         self.splunk_hec_token = token;
     }
+
+    /// Return the delivery deadline, if it exists
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Set the delivery deadline to passed value
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Return the global sequence number, if it exists
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    /// Set the global sequence number to passed value
+    pub fn set_sequence(&mut self, sequence: Option<u64>) {
+        self.sequence = sequence;
+    }
+
+    /// Return the names of the fanouts the event was sent through
+    pub fn path(&self) -> &[Arc<str>] {
+        &self.path
+    }
+
+    /// Append a fanout name to the path
+    pub fn push_path(&mut self, name: Arc<str>) {
+        self.path.push(name);
+    }
+}
+
+impl PartialEq for EventMetadata {
+    fn eq(&self, other: &Self) -> bool {
+        self.datadog_api_key == other.datadog_api_key
+            && self.splunk_hec_token == other.splunk_hec_token
+            && self.finalizers == other.finalizers
+            && self.schema_definition == other.schema_definition
+    }
+}
+
+impl PartialOrd for EventMetadata {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (
+            &self.datadog_api_key,
+            &self.splunk_hec_token,
+            &self.finalizers,
+            &self.schema_definition,
+        )
+            .partial_cmp(&(
+                &other.datadog_api_key,
+                &other.splunk_hec_token,
+                &other.finalizers,
+                &other.schema_definition,
+            ))
+    }
 }
 
 impl Default for EventMetadata {
@@ -58,6 +135,9 @@ impl Default for EventMetadata {
             splunk_hec_token: Default::default(),
             finalizers: Default::default(),
             schema_definition: default_schema_definition(),
+            deadline: None,
+            sequence: None,
+            path: Vec::new(),
         }
     }
 }
@@ -70,8 +150,9 @@ impl ByteSizeOf for EventMetadata {
     fn allocated_bytes(&self) -> usize {
         // NOTE we don't count the `str` here because it's allocated somewhere
         // else. We're just moving around the pointer, which is already captured
-        // by `ByteSizeOf::size_of`.
-        self.finalizers.allocated_bytes()
+        // by `ByteSizeOf::size_of`. Likewise, the names in the path are shared
+        // with the fanouts that stamped them, so only the pointers are counted.
+        self.finalizers.allocated_bytes() + self.path.len() * std::mem::size_of::<Arc<str>>()
     }
 }
 
@@ -115,6 +196,9 @@ impl EventMetadata {
     /// Merge the other `EventMetadata` into this.
     /// If a Datadog API key is not set in `self`, the one from `other` will be used.
     /// If a Splunk HEC token is not set in `self`, the one from `other` will be used.
+    /// If both have a deadline, the earlier of the two will be used.
+    /// If a sequence number is not set in `self`, the one from `other` will be used.
+    /// If a path is not set in `self`, the one from `other` will be used.
     pub fn merge(&mut self, other: Self) {
         self.finalizers.merge(other.finalizers);
         if self.datadog_api_key.is_none() {
@@ -123,6 +207,16 @@ impl EventMetadata {
         if self.splunk_hec_token.is_none() {
             self.splunk_hec_token = other.splunk_hec_token;
         }
+        self.deadline = match (self.deadline, other.deadline) {
+            (Some(deadline), Some(other)) => Some(deadline.min(other)),
+            (deadline, other) => deadline.or(other),
+        };
+        if self.sequence.is_none() {
+            self.sequence = other.sequence;
+        }
+        if self.path.is_empty() {
+            self.path = other.path;
+        }
     }
 
     /// Update the finalizer(s) status.
@@ -58,3 +58,52 @@ fn event_iteration_order() {
         ]
     );
 }
+
+#[test]
+fn merge_arrays_by_type() {
+    let metric = |name: &str| {
+        Metric::new(
+            name,
+            MetricKind::Incremental,
+            MetricValue::Counter { value: 1.0 },
+        )
+    };
+    let arrays = vec![
+        EventArray::Logs(vec![LogEvent::from("a"), LogEvent::from("b")]),
+        EventArray::Metrics(vec![metric("x")]),
+        EventArray::Logs(vec![LogEvent::from("c")]),
+        EventArray::Metrics(vec![metric("y"), metric("z")]),
+    ];
+
+    assert_eq!(
+        array::merge_arrays(arrays),
+        vec![
+            EventArray::Logs(vec![
+                LogEvent::from("a"),
+                LogEvent::from("b"),
+                LogEvent::from("c")
+            ]),
+            EventArray::Metrics(vec![metric("x"), metric("y"), metric("z")]),
+        ]
+    );
+    assert!(array::merge_arrays(Vec::new()).is_empty());
+}
+
+#[test]
+fn metadata_comparison_ignores_routing() {
+    let mut routed = EventMetadata::default();
+    routed.set_deadline(Some(tokio::time::Instant::now()));
+    routed.set_sequence(Some(1));
+    routed.push_path(Arc::from("fanout"));
+
+    // Where an event was routed doesn't make it a different event:
+    assert_eq!(routed, EventMetadata::default());
+    assert_eq!(
+        routed.partial_cmp(&EventMetadata::default()),
+        Some(std::cmp::Ordering::Equal)
+    );
+
+    // While the rest of its metadata still does:
+    routed.set_datadog_api_key(Some(Arc::from("key")));
+    assert_ne!(routed, EventMetadata::default());
+}
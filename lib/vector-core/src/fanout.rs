@@ -1,21 +1,250 @@
-use std::{collections::HashMap, fmt, task::Poll};
+use std::{
+    cmp::{self, Reverse},
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
 
-use futures::{Stream, StreamExt};
+use bytes::Bytes;
+use futures::{
+    future::{self, BoxFuture},
+    task::{self, ArcWake, AtomicWaker},
+    Stream, StreamExt,
+};
 use futures_util::{pending, poll};
 use indexmap::IndexMap;
-use tokio::sync::mpsc;
+use metrics::counter;
+use smallvec::{smallvec, SmallVec};
+use snafu::Snafu;
+use tokio::{
+    sync::{
+        mpsc::{
+            self,
+            error::{SendError, TryRecvError, TrySendError},
+        },
+        oneshot, Mutex, OwnedSemaphorePermit, Semaphore,
+    },
+    time::{self, sleep, sleep_until, Instant},
+};
 use tokio_util::sync::ReusableBoxFuture;
-use vector_buffers::topology::channel::BufferSender;
+use vector_buffers::{topology::channel::BufferSender, WhenFull};
 
-use crate::{config::ComponentKey, event::EventArray};
+use crate::{
+    config::{ComponentKey, DataType},
+    event::{BatchNotifier, BatchStatus, EventArray, EventContainer, EventFinalizer, EventStatus},
+};
 
 pub enum ControlMessage {
     Add(ComponentKey, BufferSender<EventArray>),
+    /// Removes the given output.
+    ///
+    /// Since control messages can race with one another, removing an output that isn't present is
+    /// only logged, and never fatal.
     Remove(ComponentKey),
     /// Will stop accepting events until Some with given id is replaced.
     Replace(ComponentKey, Option<BufferSender<EventArray>>),
+    /// Cancels a replacement started with `Replace(id, None)` by removing the output entirely.
+    CancelReplace(ComponentKey),
+    /// Adds a new sink as an output, as a member of the given group.
+    AddToGroup(ComponentKey, BufferSender<EventArray>, String),
+    /// Pauses every output in the given group.
+    ///
+    /// Paused outputs are skipped by sends, rather than waited on, until the group is resumed.
+    PauseGroup(String),
+    /// Resumes every output in the given group.
+    ResumeGroup(String),
+    /// Sets the priority of the given output.
+    SetPriority(ComponentKey, u8),
+    /// Adjusts the weight of the given output by the given delta.
+    ///
+    /// The resulting weight is clamped so that it never goes below zero.
+    AdjustWeight(ComponentKey, i32),
+    /// Reorders the outputs to match the order of the given keys.
+    ///
+    /// Any outputs not listed keep their relative order, after all of the listed outputs. Keys that
+    /// don't match an output, or that are listed more than once, are ignored.
+    Reorder(Vec<ComponentKey>),
+    /// Adds a new sink as a shadow of an existing output.
+    ///
+    /// A shadow receives a copy of every array sent to the output it shadows, but it never blocks
+    /// or otherwise affects sends to the rest of the fanout: if it is still busy with a previous
+    /// array, the next array is dropped for it instead, and any errors it returns are only reflected
+    /// in its own statistics.
+    AddShadow(ComponentKey, BufferSender<EventArray>, ComponentKey),
+    /// Adds a new sink as an output, with an async transform applied to each array sent to it.
+    ///
+    /// The transform is awaited as part of the send to this output alone, so it never holds up the
+    /// sends to any other output. However, a send only completes once every output has accepted the
+    /// array, so the transform's latency is still added to each send as a whole. In turn, arrays
+    /// are transformed one at a time, and reach the sink in the order they were sent.
+    AddAsyncTransformed(ComponentKey, BufferSender<EventArray>, AsyncTransform),
+    /// Adds a new sink as an output, with a callback invoked each time it accepts an array.
+    ///
+    /// The callback is given the output's key and the number of events it accepted. It isn't
+    /// invoked for arrays the output never received, such as those routed elsewhere or sent while
+    /// it was paused, nor for arrays it dropped or failed to accept.
+    AddWithAcceptCallback(ComponentKey, BufferSender<EventArray>, AcceptCallback),
+    /// Sets the given sink as the overflow output, replacing any existing one.
+    ///
+    /// The overflow receives a copy of every array that any other output failed to receive, whether
+    /// because the output was paused, its buffer was full, or it returned an error. A paused output
+    /// only fails to receive arrays the routing strategy would have sent it, so under strategies
+    /// that pick a single output for each array, such as `RoundRobin`, pausing an output alone
+    /// never sends arrays to the overflow. Each such array is sent to the overflow once, however
+    /// many outputs failed to receive it. Like a shadow, the overflow never blocks sends to the
+    /// rest of the fanout: if it is still busy with a previous array, the next array is dropped for
+    /// it instead, which is reflected in its own statistics.
+    SetOverflow(ComponentKey, BufferSender<EventArray>),
+    /// Replaces the routing strategy, along with the weight and priority of every output, at once.
+    ///
+    /// Outputs not listed in the configuration are given the default weight and priority, while
+    /// listed keys that don't match an output are ignored. Since the whole configuration is applied
+    /// as a single control message, no array is ever routed under a partially applied one.
+    ReconfigureRouting(RoutingConfig),
+    /// Adds several new sinks as outputs at once.
+    ///
+    /// Since they're all added by a single control message, the fanout never routes an array with
+    /// only some of them added. If any key is already an output, or is given more than once, none of
+    /// the sinks are added.
+    AddMany(Vec<(ComponentKey, BufferSender<EventArray>)>),
+    /// Adds a new sink as an output, which is delivered each array encoded by the given encoder.
+    ///
+    /// Encoded outputs receive every array sent, regardless of the routing strategy. Each array is
+    /// encoded once for each distinct encoder, before it's sent to any output, and the encoded bytes
+    /// are then shared by all outputs using that encoder.
+    AddEncoded(ComponentKey, Box<dyn BytesSink>, Encoder),
+    /// Adds a new sink as an output, which is delivered each array shared with every other such
+    /// output, rather than its own copy of it.
+    ///
+    /// Like encoded outputs, shared outputs receive every array sent, regardless of the routing
+    /// strategy. Each array is copied once for all shared outputs, which then each get a reference
+    /// to the same copy, unless it's smaller than the fanout's copy-on-write threshold. See
+    /// `Fanout::with_cow_threshold`.
+    AddShared(ComponentKey, Box<dyn SharedSink>),
+    /// Limits the rate of arrays sent to the given output, in arrays per second.
+    ///
+    /// Only the given output is limited, with up to a second's worth of arrays allowed in a burst.
+    /// Arrays over the limit are dropped for that output, and sent to the overflow output if there
+    /// is one, unless `WhenFull::Block` is given, in which case the send to that output is delayed
+    /// until the limit allows it. A rate of zero removes the limit.
+    SetSinkRateLimit(ComponentKey, u32, WhenFull),
+    /// Retires the given output, which is sent no more arrays but is left in place until its buffer
+    /// has drained.
+    ///
+    /// Whether the buffer has drained is checked before each send, with the output being removed
+    /// once its buffer is empty. Outputs whose buffer can't report its occupancy, or which are
+    /// awaiting replacement, are removed the first time it's checked.
+    Retire(ComponentKey),
+    /// Adds a new sink as an output, which is sent the given snapshot array ahead of any other.
+    ///
+    /// The snapshot is sent as part of the first send the output takes part in, immediately before
+    /// the array being sent, so a late-joining output gets a baseline of the current state before
+    /// it receives anything else.
+    AddWithSnapshot(ComponentKey, BufferSender<EventArray>, EventArray),
+    /// Removes every output at once, including shadows, the overflow output and encoded outputs,
+    /// and resets the routing strategy.
+    ///
+    /// Since they're all removed by a single control message, no output can be added between the
+    /// removals. Any sends in-flight to the removed outputs complete in the background, as with
+    /// `Remove`.
+    Clear,
+    /// Applies the given control messages in order, all at once.
+    ///
+    /// Every message in the batch takes effect before the next array is routed, whether or not a
+    /// send is in-flight, so no array is ever sent with only some of them applied.
+    Batch(Vec<ControlMessage>),
+    /// Applies the given control message, then acknowledges that it was applied.
+    ///
+    /// The acknowledgement is sent once the message has been handled, whatever its outcome, such as
+    /// when a duplicate output is ignored by a panic-free fanout. See `ControlChannel::send_acked`.
+    Acked(Box<ControlMessage>, oneshot::Sender<()>),
+    /// Pauses the given output, holding on to its sink until it's resumed with `Resume`.
+    ///
+    /// Like an output awaiting replacement, and unlike an output in a paused group, a paused output
+    /// holds up all sends until it's resumed. Since the sink itself is kept, anything already in its
+    /// buffer is kept too. If a send to the output is in-flight, it's paused once the send completes.
+    Pause(ComponentKey),
+    /// Resumes the given output, paused by `Pause`, with the sink it was holding on to.
+    Resume(ComponentKey),
+    /// Flushes the buffer of the given output every given interval, on a schedule of its own rather
+    /// than alongside every other output. A zero interval removes the schedule.
+    ///
+    /// Scheduled flushes are driven by `Fanout::send_stream` while it waits for the next array, so
+    /// they're only made while a stream is being sent.
+    SetFlushInterval(ComponentKey, Duration),
+    /// Adds a new sink as an output in the given reliability tier.
+    ///
+    /// How arrays the output fails to accept are handled depends on the policy set for its tier
+    /// with `Fanout::with_tier_policies`, with outputs in a tier without one being best-effort.
+    AddWithTier(ComponentKey, BufferSender<EventArray>, u8),
+    /// Adds a new sink as an output, which is only sent the arrays accepted by the given filter.
+    ///
+    /// The filter is applied to each array as a whole, after it has been routed: an array the
+    /// filter rejects is skipped for this output, and recorded as dropped for it, even if some of
+    /// its events would have matched. Arrays are never split between outputs.
+    AddFiltered(ComponentKey, BufferSender<EventArray>, Filter),
+    /// Adds a new sink as an output, which is only sent arrays of the given types.
+    ///
+    /// Since every array holds events of a single type, each array is either sent to the output
+    /// whole or not at all, and the output never has to handle types it can't. Empty arrays aren't
+    /// sent to the output either, whatever their type. Like a filtered output, the arrays skipped
+    /// are recorded as dropped for it.
+    AddForTypes(ComponentKey, BufferSender<EventArray>, DataType),
+    /// Adds a new sink as an output, created by the given factory.
+    ///
+    /// The factory is kept for as long as the output is, and called again to recreate the sink
+    /// whenever the output is reinstated after being quarantined. See
+    /// `FanoutErrorPolicy::Quarantine`.
+    AddWithFactory(ComponentKey, SinkFactory),
+    /// Resumes sending once the fanout has halted because an output failed under
+    /// `FanoutErrorPolicy::FailFast`, starting with any send waiting on it. Does nothing if the
+    /// fanout isn't halted.
+    Unhalt,
+}
+
+/// An async transform applied to each array before it is sent to a particular output.
+pub type AsyncTransform = Box<dyn Fn(EventArray) -> BoxFuture<'static, EventArray> + Send>;
+
+/// Decides whether a particular output is sent an array, by returning `true` for arrays it accepts.
+pub type Filter = Arc<dyn Fn(&EventArray) -> bool + Send + Sync>;
+
+/// Encodes an array into bytes, for delivery to a `BytesSink`.
+///
+/// Outputs added with clones of the same encoder share the encoded bytes of each array.
+pub type Encoder = Arc<dyn Fn(&EventArray) -> Bytes + Send + Sync>;
+
+/// A sink that's delivered each array encoded as bytes, rather than the array itself.
+pub trait BytesSink: Send {
+    /// Delivers an encoded array to the sink, resolving to an error if it failed to accept it.
+    ///
+    /// The error is logged along with the failure, so it should describe why the sink failed.
+    fn send(&mut self, bytes: Bytes) -> BoxFuture<'_, crate::Result<()>>;
+}
+
+/// A sink that's delivered each array shared with other sinks, rather than its own copy of it.
+///
+/// A sink only needing to read the array never copies it. One that needs to modify it can do so
+/// with `Arc::make_mut`, which only copies the array if it's still shared.
+pub trait SharedSink: Send {
+    /// Delivers a shared array to the sink, resolving to an error if it failed to accept it.
+    ///
+    /// The error is logged along with the failure, so it should describe why the sink failed.
+    fn send(&mut self, events: Arc<EventArray>) -> BoxFuture<'_, crate::Result<()>>;
 }
 
+/// Creates the sink of a particular output, as when it's first added and each time it's reinstated
+/// after being quarantined.
+pub type SinkFactory = Box<dyn FnMut() -> BufferSender<EventArray> + Send>;
+
+/// A callback invoked each time a particular output accepts an array, with the output's key and the
+/// number of events accepted.
+pub type AcceptCallback = Box<dyn Fn(&ComponentKey, usize) + Send>;
+
 impl fmt::Debug for ControlMessage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ControlMessage::")?;
@@ -30,760 +259,9962 @@ impl fmt::Debug for ControlMessage {
                 };
                 write!(f, "Replace({:?}, {})", id, status)
             }
+            Self::CancelReplace(id) => write!(f, "CancelReplace({:?})", id),
+            Self::AddToGroup(id, _, group) => write!(f, "AddToGroup({:?}, {:?})", id, group),
+            Self::PauseGroup(group) => write!(f, "PauseGroup({:?})", group),
+            Self::ResumeGroup(group) => write!(f, "ResumeGroup({:?})", group),
+            Self::SetPriority(id, priority) => write!(f, "SetPriority({:?}, {})", id, priority),
+            Self::AdjustWeight(id, delta) => write!(f, "AdjustWeight({:?}, {})", id, delta),
+            Self::Reorder(ids) => write!(f, "Reorder({:?})", ids),
+            Self::AddShadow(id, _, target) => write!(f, "AddShadow({:?}, {:?})", id, target),
+            Self::AddAsyncTransformed(id, _, _) => write!(f, "AddAsyncTransformed({:?})", id),
+            Self::AddWithAcceptCallback(id, _, _) => {
+                write!(f, "AddWithAcceptCallback({:?})", id)
+            }
+            Self::SetOverflow(id, _) => write!(f, "SetOverflow({:?})", id),
+            Self::ReconfigureRouting(config) => write!(
+                f,
+                "ReconfigureRouting({:?}, {:?})",
+                config.weights, config.priorities
+            ),
+            Self::AddMany(outputs) => write!(
+                f,
+                "AddMany({:?})",
+                outputs.iter().map(|(id, _)| id).collect::<Vec<_>>()
+            ),
+            Self::AddEncoded(id, _, _) => write!(f, "AddEncoded({:?})", id),
+            Self::AddShared(id, _) => write!(f, "AddShared({:?})", id),
+            Self::SetSinkRateLimit(id, per_sec, on_exceed) => write!(
+                f,
+                "SetSinkRateLimit({:?}, {}, {:?})",
+                id, per_sec, on_exceed
+            ),
+            Self::Retire(id) => write!(f, "Retire({:?})", id),
+            Self::AddWithSnapshot(id, _, _) => write!(f, "AddWithSnapshot({:?})", id),
+            Self::Clear => write!(f, "Clear"),
+            Self::Batch(messages) => write!(f, "Batch({:?})", messages),
+            Self::Acked(message, _) => write!(f, "Acked({:?})", message),
+            Self::Pause(id) => write!(f, "Pause({:?})", id),
+            Self::Resume(id) => write!(f, "Resume({:?})", id),
+            Self::SetFlushInterval(id, interval) => {
+                write!(f, "SetFlushInterval({:?}, {:?})", id, interval)
+            }
+            Self::AddWithTier(id, _, tier) => write!(f, "AddWithTier({:?}, {})", id, tier),
+            Self::AddFiltered(id, _, _) => write!(f, "AddFiltered({:?})", id),
+            Self::AddForTypes(id, _, types) => write!(f, "AddForTypes({:?}, {})", id, types),
+            Self::AddWithFactory(id, _) => write!(f, "AddWithFactory({:?})", id),
+            Self::Unhalt => write!(f, "Unhalt"),
         }
     }
 }
 
-// TODO: We should really wrap this in a custom type that has dedicated methods for each operation
-// so that high-lever components don't need to do the raw channel sends, etc.
-pub type ControlChannel = mpsc::UnboundedSender<ControlMessage>;
+impl ControlMessage {
+    /// Gets the keys of the outputs added by this control message, if it adds any.
+    fn added_keys_mut(&mut self) -> Vec<&mut ComponentKey> {
+        match self {
+            Self::Add(id, _)
+            | Self::AddToGroup(id, _, _)
+            | Self::AddShadow(id, _, _)
+            | Self::AddAsyncTransformed(id, _, _)
+            | Self::AddWithAcceptCallback(id, _, _)
+            | Self::AddEncoded(id, _, _)
+            | Self::AddShared(id, _)
+            | Self::AddWithSnapshot(id, _, _)
+            | Self::AddWithTier(id, _, _)
+            | Self::AddFiltered(id, _, _)
+            | Self::AddForTypes(id, _, _)
+            | Self::AddWithFactory(id, _)
+            | Self::SetOverflow(id, _) => vec![id],
+            Self::AddMany(outputs) => outputs.iter_mut().map(|(id, _)| id).collect(),
+            Self::Batch(messages) => messages.iter_mut().flat_map(Self::added_keys_mut).collect(),
+            Self::Acked(message, _) => message.added_keys_mut(),
+            _ => Vec::new(),
+        }
+    }
 
-pub struct Fanout {
-    senders: IndexMap<ComponentKey, Option<Sender>>,
-    control_channel: mpsc::UnboundedReceiver<ControlMessage>,
+    /// Gets a description of this control message, without any of the sinks it carries.
+    pub fn descriptor(&self) -> ControlMessageDescriptor {
+        match self {
+            Self::Add(id, _) => ControlMessageDescriptor::Add(id.clone()),
+            Self::Remove(id) => ControlMessageDescriptor::Remove(id.clone()),
+            Self::Replace(id, sink) => {
+                ControlMessageDescriptor::Replace(id.clone(), sink.is_some())
+            }
+            Self::CancelReplace(id) => ControlMessageDescriptor::CancelReplace(id.clone()),
+            Self::AddToGroup(id, _, group) => {
+                ControlMessageDescriptor::AddToGroup(id.clone(), group.clone())
+            }
+            Self::PauseGroup(group) => ControlMessageDescriptor::PauseGroup(group.clone()),
+            Self::ResumeGroup(group) => ControlMessageDescriptor::ResumeGroup(group.clone()),
+            Self::SetPriority(id, priority) => {
+                ControlMessageDescriptor::SetPriority(id.clone(), *priority)
+            }
+            Self::AdjustWeight(id, delta) => {
+                ControlMessageDescriptor::AdjustWeight(id.clone(), *delta)
+            }
+            Self::Reorder(ids) => ControlMessageDescriptor::Reorder(ids.clone()),
+            Self::AddShadow(id, _, target) => {
+                ControlMessageDescriptor::AddShadow(id.clone(), target.clone())
+            }
+            Self::AddAsyncTransformed(id, _, _) => {
+                ControlMessageDescriptor::AddAsyncTransformed(id.clone())
+            }
+            Self::AddWithAcceptCallback(id, _, _) => {
+                ControlMessageDescriptor::AddWithAcceptCallback(id.clone())
+            }
+            Self::SetOverflow(id, _) => ControlMessageDescriptor::SetOverflow(id.clone()),
+            Self::ReconfigureRouting(_) => ControlMessageDescriptor::ReconfigureRouting,
+            Self::AddMany(outputs) => ControlMessageDescriptor::AddMany(
+                outputs.iter().map(|(id, _)| id.clone()).collect(),
+            ),
+            Self::AddEncoded(id, _, _) => ControlMessageDescriptor::AddEncoded(id.clone()),
+            Self::AddShared(id, _) => ControlMessageDescriptor::AddShared(id.clone()),
+            Self::SetSinkRateLimit(id, per_sec, _) => {
+                ControlMessageDescriptor::SetSinkRateLimit(id.clone(), *per_sec)
+            }
+            Self::Retire(id) => ControlMessageDescriptor::Retire(id.clone()),
+            Self::AddWithSnapshot(id, _, _) => {
+                ControlMessageDescriptor::AddWithSnapshot(id.clone())
+            }
+            Self::Clear => ControlMessageDescriptor::Clear,
+            Self::Batch(messages) => {
+                ControlMessageDescriptor::Batch(messages.iter().map(Self::descriptor).collect())
+            }
+            // Acknowledging a message makes no difference to how it's applied.
+            Self::Acked(message, _) => message.descriptor(),
+            Self::Pause(id) => ControlMessageDescriptor::Pause(id.clone()),
+            Self::Resume(id) => ControlMessageDescriptor::Resume(id.clone()),
+            Self::SetFlushInterval(id, interval) => {
+                ControlMessageDescriptor::SetFlushInterval(id.clone(), *interval)
+            }
+            Self::AddWithTier(id, _, tier) => {
+                ControlMessageDescriptor::AddWithTier(id.clone(), *tier)
+            }
+            Self::AddFiltered(id, _, _) => ControlMessageDescriptor::AddFiltered(id.clone()),
+            Self::AddForTypes(id, _, types) => {
+                ControlMessageDescriptor::AddForTypes(id.clone(), *types)
+            }
+            Self::AddWithFactory(id, _) => ControlMessageDescriptor::AddWithFactory(id.clone()),
+            Self::Unhalt => ControlMessageDescriptor::Unhalt,
+        }
+    }
 }
 
-impl Fanout {
-    pub fn new() -> (Self, ControlChannel) {
-        let (control_tx, control_rx) = mpsc::unbounded_channel();
+/// A description of a control message.
+///
+/// This mirrors `ControlMessage`, but without the sinks themselves, so that a set of control
+/// operations can be described, and validated, before actually being applied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ControlMessageDescriptor {
+    Add(ComponentKey),
+    Remove(ComponentKey),
+    /// Whether or not a replacement sink is given, where `false` pauses the output.
+    Replace(ComponentKey, bool),
+    CancelReplace(ComponentKey),
+    AddToGroup(ComponentKey, String),
+    PauseGroup(String),
+    ResumeGroup(String),
+    SetPriority(ComponentKey, u8),
+    AdjustWeight(ComponentKey, i32),
+    Reorder(Vec<ComponentKey>),
+    AddShadow(ComponentKey, ComponentKey),
+    AddAsyncTransformed(ComponentKey),
+    AddWithAcceptCallback(ComponentKey),
+    SetOverflow(ComponentKey),
+    ReconfigureRouting,
+    AddMany(Vec<ComponentKey>),
+    AddEncoded(ComponentKey),
+    AddShared(ComponentKey),
+    SetSinkRateLimit(ComponentKey, u32),
+    Retire(ComponentKey),
+    AddWithSnapshot(ComponentKey),
+    Clear,
+    Batch(Vec<ControlMessageDescriptor>),
+    Pause(ComponentKey),
+    Resume(ComponentKey),
+    SetFlushInterval(ComponentKey, Duration),
+    AddWithTier(ComponentKey, u8),
+    AddFiltered(ComponentKey),
+    AddForTypes(ComponentKey, DataType),
+    AddWithFactory(ComponentKey),
+    Unhalt,
+}
 
-        let fanout = Self {
-            senders: Default::default(),
-            control_channel: control_rx,
-        };
+impl ControlMessageDescriptor {
+    /// Gets the keys of the outputs added by the described control message, if it adds any.
+    fn added_keys_mut(&mut self) -> Vec<&mut ComponentKey> {
+        match self {
+            Self::Add(id)
+            | Self::AddToGroup(id, _)
+            | Self::AddShadow(id, _)
+            | Self::AddAsyncTransformed(id)
+            | Self::AddWithAcceptCallback(id)
+            | Self::AddEncoded(id)
+            | Self::AddShared(id)
+            | Self::AddWithSnapshot(id)
+            | Self::AddWithTier(id, _)
+            | Self::AddFiltered(id)
+            | Self::AddForTypes(id, _)
+            | Self::AddWithFactory(id)
+            | Self::SetOverflow(id) => vec![id],
+            Self::AddMany(ids) => ids.iter_mut().collect(),
+            Self::Batch(descriptors) => descriptors
+                .iter_mut()
+                .flat_map(Self::added_keys_mut)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
 
-        (fanout, control_tx)
+/// Observes a particular output of a fanout, by its key.
+pub type SinkObserver = Box<dyn Fn(&ComponentKey) + Send + Sync>;
+
+/// Observes how long a send to a particular output of a fanout took, by the output's key.
+pub type LatencyObserver = Box<dyn Fn(&ComponentKey, Duration) + Send + Sync>;
+
+/// Rewrites the key of each output added to a fanout.
+pub type KeyMapper = Box<dyn Fn(ComponentKey) -> ComponentKey + Send>;
+
+/// Applies the given key mapper, if any, to the given key of an output being added.
+fn map_added_key(key_mapper: Option<&KeyMapper>, id: &mut ComponentKey) {
+    if let Some(key_mapper) = key_mapper {
+        *id = key_mapper(id.clone());
     }
+}
 
-    /// Add a new sink as an output.
+/// The sending half of a fanout's control channel, for sending it raw control messages.
+///
+/// For dedicated methods for the most common operations, wrap it in a `FanoutControl`.
+///
+/// Every control message is stamped with the time it was sent, so that the fanout can tell how long
+/// control messages have been waiting to be applied.
+#[derive(Clone, Debug)]
+pub struct ControlChannel {
+    sender: RawControlSender,
+    enqueued: Arc<std::sync::Mutex<VecDeque<Instant>>>,
+}
+
+/// The underlying sender of a control channel, which is unbounded unless the fanout was created
+/// with `Fanout::with_bounded_control`.
+#[derive(Clone, Debug)]
+enum RawControlSender {
+    Unbounded(mpsc::UnboundedSender<ControlMessage>),
+    Bounded(mpsc::Sender<ControlMessage>),
+}
+
+impl ControlChannel {
+    /// Sends a control message to the fanout.
+    ///
+    /// # Errors
+    ///
+    /// If the fanout has been dropped, the control message is returned. It's also returned if the
+    /// channel is bounded and already full, in which case `send_waiting` can be used to wait for
+    /// room instead.
     ///
     /// # Panics
     ///
-    /// Function will panic if a sink with the same ID is already present.
-    pub fn add(&mut self, id: ComponentKey, sink: BufferSender<EventArray>) {
-        assert!(
-            !self.senders.contains_key(&id),
-            "Adding duplicate output id to fanout: {id}"
-        );
-        self.senders.insert(id, Some(Sender::new(sink)));
-    }
-
-    fn remove(&mut self, id: &ComponentKey) {
-        assert!(
-            self.senders.remove(id).is_some(),
-            "Removing non-existent sink from fanout: {id}"
-        );
-    }
-
-    fn replace(&mut self, id: &ComponentKey, sink: BufferSender<EventArray>) {
-        match self.senders.get_mut(id) {
-            Some(sender) => {
-                // While a sink must be _known_ to be replaced, it must also be empty (previously
-                // paused or consumed when the `SendGroup` was created), otherwise an invalid
-                // sequence of control operations has been applied.
-                assert!(
-                    sender.replace(Sender::new(sink)).is_none(),
-                    "Replacing existing sink is not valid: {id}"
-                );
+    /// This method will panic if the lock on the send times has been poisoned.
+    pub fn send(&self, message: ControlMessage) -> Result<(), SendError<ControlMessage>> {
+        let mut enqueued = self.enqueued.lock().expect("control channel lock poisoned");
+        match &self.sender {
+            RawControlSender::Unbounded(sender) => sender.send(message)?,
+            RawControlSender::Bounded(sender) => {
+                sender.try_send(message).map_err(|error| match error {
+                    TrySendError::Full(message) | TrySendError::Closed(message) => {
+                        SendError(message)
+                    }
+                })?;
             }
-            None => panic!("Replacing unknown sink from fanout: {id}"),
         }
+        enqueued.push_back(Instant::now());
+        Ok(())
     }
 
-    fn pause(&mut self, id: &ComponentKey) {
-        match self.senders.get_mut(id) {
-            Some(sender) => {
-                // A sink must be known and present to be replaced, otherwise an invalid sequence of
-                // control operations has been applied.
-                assert!(
-                    sender.take().is_some(),
-                    "Pausing non-existent sink is not valid: {id}"
-                );
+    /// Sends a control message to the fanout, waiting for room in the channel if it's bounded and
+    /// full.
+    ///
+    /// Room is made as the fanout applies the control messages already waiting, which it does
+    /// before each send, and while a send waits on its outputs. For an unbounded channel, this is
+    /// the same as `send`.
+    ///
+    /// # Errors
+    ///
+    /// If the fanout has been dropped, the control message is returned.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the lock on the send times has been poisoned.
+    pub async fn send_waiting(
+        &self,
+        message: ControlMessage,
+    ) -> Result<(), SendError<ControlMessage>> {
+        match &self.sender {
+            RawControlSender::Unbounded(_) => self.send(message),
+            RawControlSender::Bounded(sender) => {
+                let permit = match sender.reserve().await {
+                    Ok(permit) => permit,
+                    Err(_) => return Err(SendError(message)),
+                };
+                let mut enqueued = self.enqueued.lock().expect("control channel lock poisoned");
+                permit.send(message);
+                enqueued.push_back(Instant::now());
+                Ok(())
             }
-            None => panic!("Pausing unknown sink from fanout: {id}"),
         }
     }
 
-    /// Apply a control message directly against this instance.
+    /// Sends a control message to the fanout, to be acknowledged once it's been applied.
     ///
-    /// This method should not be used if there is an active `SendGroup` being processed.
-    fn apply_control_message(&mut self, message: ControlMessage) {
-        trace!("Processing control message outside of send: {:?}", message);
-
-        match message {
-            ControlMessage::Add(id, sink) => self.add(id, sink),
-            ControlMessage::Remove(id) => self.remove(&id),
-            ControlMessage::Replace(id, None) => self.pause(&id),
-            ControlMessage::Replace(id, Some(sink)) => self.replace(&id, sink),
-        }
+    /// The returned receiver resolves once the fanout has handled the message, or with an error if
+    /// the fanout is dropped before it does.
+    ///
+    /// # Errors
+    ///
+    /// If the fanout has been dropped, the control message is returned.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the lock on the send times has been poisoned.
+    pub fn send_acked(
+        &self,
+        message: ControlMessage,
+    ) -> Result<oneshot::Receiver<()>, SendError<ControlMessage>> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        self.send(ControlMessage::Acked(Box::new(message), ack_tx))
+            .map_err(|SendError(message)| match message {
+                ControlMessage::Acked(message, _) => SendError(*message),
+                message => SendError(message),
+            })?;
+        Ok(ack_rx)
     }
+}
 
-    /// If any sink is awaiting replacement (i.e. it was temporarily replaced with `None`), read
-    /// and process messages from the control channel until that is no longer true.
-    async fn wait_for_replacements(&mut self) {
-        while self.senders.values().any(Option::is_none) {
-            if let Some(msg) = self.control_channel.recv().await {
-                self.apply_control_message(msg);
-            } else {
-                // If the control channel is closed, there's nothing else we can do.
-            }
-        }
+/// A handle for controlling a fanout, with dedicated methods for the most common operations, so
+/// that components don't need to build the raw control messages themselves.
+///
+/// It wraps the fanout's `ControlChannel`, and can be cloned so that several tasks can control the
+/// same fanout.
+#[derive(Clone, Debug)]
+pub struct FanoutControl(ControlChannel);
+
+impl FanoutControl {
+    /// Wraps the given control channel.
+    pub fn new(control: ControlChannel) -> Self {
+        Self(control)
     }
 
-    pub async fn send_stream(&mut self, events: impl Stream<Item = EventArray>) {
-        tokio::pin!(events);
-        while let Some(event_array) = events.next().await {
-            self.send(event_array).await;
-        }
+    /// Gets the underlying control channel, for sending any other control message.
+    pub fn control_channel(&self) -> &ControlChannel {
+        &self.0
     }
 
-    /// Send a batch of events to all connected sinks.
+    /// Adds a new sink as an output, as with `ControlMessage::Add`.
     ///
-    /// This will block on the resolution of any pending reload before proceeding with the send
-    /// operation.
+    /// # Errors
+    ///
+    /// If the fanout has been dropped, the control message is returned.
     ///
     /// # Panics
     ///
-    /// This method can panic if the fanout receives a control message that violates some invariant
-    /// about its current state (e.g. remove a non-existent sink, etc). This would imply a bug in
-    /// Vector's config reloading logic.
-    pub async fn send(&mut self, events: EventArray) {
-        // First, process any available control messages in a non-blocking fashion.  If any of our
-        // senders were replaced, we additionally wait until they're replaced.
-        while let Ok(message) = self.control_channel.try_recv() {
-            self.apply_control_message(message);
-        }
-
-        self.wait_for_replacements().await;
-
-        // Nothing to send if we have no sender.
-        if self.senders.is_empty() {
-            trace!("No senders present.");
-            return;
-        }
-
-        // Keep track of whether the control channel has returned `Ready(None)`, and stop polling
-        // it once it has. If we don't do this check, it will continue to return `Ready(None)` any
-        // time it is polled, which can lead to a busy loop below.
-        //
-        // In real life this is likely a non-issue, but it can lead to strange behavior in tests if
-        // left unhandled.
-        let mut control_channel_open = true;
-
-        // Create our send group which arms all senders to send the given events, and handles
-        // adding/removing/replacing senders while the send is in-flight.
-        let mut send_group = SendGroup::new(&mut self.senders, events);
-
-        loop {
-            tokio::select! {
-                // Semantically, it's not hugely important that this select is biased. It does,
-                // however, make testing simpler when you can count on control messages being
-                // processed first.
-                biased;
+    /// This method will panic if the lock on the send times has been poisoned.
+    pub fn add(
+        &self,
+        id: ComponentKey,
+        sink: BufferSender<EventArray>,
+    ) -> Result<(), SendError<ControlMessage>> {
+        self.0.send(ControlMessage::Add(id, sink))
+    }
 
-                maybe_msg = self.control_channel.recv(), if control_channel_open => {
-                    trace!("Processing control message inside of send: {:?}", maybe_msg);
+    /// Removes the given output, as with `ControlMessage::Remove`.
+    ///
+    /// # Errors
+    ///
+    /// If the fanout has been dropped, the control message is returned.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the lock on the send times has been poisoned.
+    pub fn remove(&self, id: ComponentKey) -> Result<(), SendError<ControlMessage>> {
+        self.0.send(ControlMessage::Remove(id))
+    }
 
-                    // During a send operation, control messages must be applied via the
-                    // `SendGroup`, since it has exclusive access to the senders.
-                    match maybe_msg {
-                        Some(ControlMessage::Add(id, sink)) => {
-                            send_group.add(id, sink);
-                        },
-                        Some(ControlMessage::Remove(id)) => {
-                            send_group.remove(&id);
-                        },
-                        Some(ControlMessage::Replace(id, Some(sink))) => {
-                            send_group.replace(&id, Sender::new(sink));
-                        },
-                        Some(ControlMessage::Replace(id, None)) => {
-                            send_group.pause(&id);
-                        },
-                        None => {
-                            // Control channel is closed, process must be shutting down
-                            control_channel_open = false;
-                        }
-                    }
-                }
+    /// Replaces the sink of the given output, as with `ControlMessage::Replace`.
+    ///
+    /// Giving no sink starts the replacement, holding up sends until the output is given its new
+    /// sink.
+    ///
+    /// # Errors
+    ///
+    /// If the fanout has been dropped, the control message is returned.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the lock on the send times has been poisoned.
+    pub fn replace(
+        &self,
+        id: ComponentKey,
+        sink: Option<BufferSender<EventArray>>,
+    ) -> Result<(), SendError<ControlMessage>> {
+        self.0.send(ControlMessage::Replace(id, sink))
+    }
+}
 
-                () = send_group.send() => {
-                    // All in-flight sends have completed, so return sinks to the base collection.
-                    // We extend instead of assign here because other sinks could have been added
-                    // while the send was in-flight.
-                    trace!("Sent item to fanout.");
-                    break;
-                }
-            }
-        }
+impl From<ControlChannel> for FanoutControl {
+    fn from(control: ControlChannel) -> Self {
+        Self::new(control)
     }
 }
 
-struct SendGroup<'a> {
-    senders: &'a mut IndexMap<ComponentKey, Option<Sender>>,
-    sends: HashMap<ComponentKey, ReusableBoxFuture<'static, Sender>>,
+/// The receiving half of a fanout's control channel, which keeps track of when each control
+/// message still waiting to be received was sent.
+struct ControlReceiver {
+    receiver: RawControlReceiver,
+    enqueued: Arc<std::sync::Mutex<VecDeque<Instant>>>,
 }
 
-impl<'a> SendGroup<'a> {
-    fn new(senders: &'a mut IndexMap<ComponentKey, Option<Sender>>, events: EventArray) -> Self {
-        // If we don't have a valid `Sender` for all sinks, then something went wrong in our logic
-        // to ensure we were starting with all valid/idle senders prior to initiating the send.
-        debug_assert!(senders.values().all(Option::is_some));
+/// The underlying receiver of a control channel, matching its `RawControlSender`.
+enum RawControlReceiver {
+    Unbounded(mpsc::UnboundedReceiver<ControlMessage>),
+    Bounded(mpsc::Receiver<ControlMessage>),
+}
 
-        let last_sender_idx = senders.len().saturating_sub(1);
-        let mut events = Some(events);
+impl ControlReceiver {
+    fn channel() -> (ControlChannel, Self) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self::from_raw(
+            RawControlSender::Unbounded(sender),
+            RawControlReceiver::Unbounded(receiver),
+        )
+    }
 
-        // We generate a send future for each sender we have, which arms them with the events to
-        // send but also takes ownership of the sender itself, which we give back when the sender completes.
-        let mut sends = HashMap::new();
-        for (i, (key, sender)) in senders.iter_mut().enumerate() {
-            let mut sender = sender
-                .take()
-                .expect("sender must be present to initialize SendGroup");
-
-            // First, arm each sender with the item to actually send.
-            if i == last_sender_idx {
-                sender.input = events.take();
-            } else {
-                sender.input = events.clone();
-            }
+    fn bounded_channel(capacity: usize) -> (ControlChannel, Self) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        Self::from_raw(
+            RawControlSender::Bounded(sender),
+            RawControlReceiver::Bounded(receiver),
+        )
+    }
 
-            // Now generate a send for that sender which we'll drive to completion.
-            let send = async move {
-                sender.flush().await;
-                sender
-            };
+    fn from_raw(sender: RawControlSender, receiver: RawControlReceiver) -> (ControlChannel, Self) {
+        let enqueued = Arc::default();
+        let control = ControlChannel {
+            sender,
+            enqueued: Arc::clone(&enqueued),
+        };
+        (control, Self { receiver, enqueued })
+    }
 
-            sends.insert(key.clone(), ReusableBoxFuture::new(send));
+    async fn recv(&mut self) -> Option<ControlMessage> {
+        let message = match &mut self.receiver {
+            RawControlReceiver::Unbounded(receiver) => receiver.recv().await,
+            RawControlReceiver::Bounded(receiver) => receiver.recv().await,
+        };
+        if message.is_some() {
+            self.received();
         }
-
-        Self { senders, sends }
+        message
     }
 
-    fn try_detach_send(&mut self, id: &ComponentKey) {
-        if let Some(send) = self.sends.remove(id) {
-            tokio::spawn(async move {
-                send.await;
-            });
-        }
+    fn try_recv(&mut self) -> Result<ControlMessage, TryRecvError> {
+        let message = match &mut self.receiver {
+            RawControlReceiver::Unbounded(receiver) => receiver.try_recv()?,
+            RawControlReceiver::Bounded(receiver) => receiver.try_recv()?,
+        };
+        self.received();
+        Ok(message)
     }
 
-    #[allow(clippy::needless_pass_by_value)]
-    fn add(&mut self, id: ComponentKey, sink: BufferSender<EventArray>) {
-        // When we're in the middle of a send, we can only keep track of the new sink, but can't
-        // actually send to it, as we don't have the item to send... so only add it to `senders`.
-        assert!(
-            self.senders
-                .insert(id.clone(), Some(Sender::new(sink)))
-                .is_none(),
-            "Adding duplicate output id to fanout: {id}"
-        );
+    fn received(&self) {
+        self.enqueued
+            .lock()
+            .expect("control channel lock poisoned")
+            .pop_front();
     }
 
-    fn remove(&mut self, id: &ComponentKey) {
-        // We may or may not be removing a sender that we're try to drive a send against, so we have
-        // to also detach the send future for the sender if it exists, otherwise we'd be hanging
-        // around still trying to send to it.
-        assert!(
-            self.senders.remove(id).is_some(),
-            "Removing non-existent sink from fanout: {id}"
-        );
+    /// Whether no control messages are waiting to be received.
+    fn is_empty(&self) -> bool {
+        self.enqueued
+            .lock()
+            .expect("control channel lock poisoned")
+            .is_empty()
+    }
 
-        // Now try and detach the in-flight send, if it exists.
-        self.try_detach_send(id);
+    /// Gets the number of control messages waiting to be received.
+    fn len(&self) -> usize {
+        self.enqueued
+            .lock()
+            .expect("control channel lock poisoned")
+            .len()
     }
 
-    fn replace(&mut self, id: &ComponentKey, sink: Sender) {
-        match self.senders.get_mut(id) {
-            Some(sender) => {
-                // While a sink must be _known_ to be replaced, it must also be empty (previously
-                // paused or consumed when the `SendGroup` was created), otherwise an invalid
-                // sequence of control operations has been applied.
-                assert!(
-                    sender.replace(sink).is_none(),
-                    "Replacing existing sink is not valid: {id}"
-                );
-            }
-            None => panic!("Replacing unknown sink from fanout: {id}"),
-        }
+    /// Gets how long ago the oldest control message still waiting to be received was sent.
+    fn oldest_age(&self) -> Option<Duration> {
+        self.enqueued
+            .lock()
+            .expect("control channel lock poisoned")
+            .front()
+            .map(Instant::elapsed)
     }
+}
 
-    fn pause(&mut self, id: &ComponentKey) {
+/// Statistics about the events sent through a fanout.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FanoutStats {
+    /// The number of event arrays sent, by type.
+    pub by_type: EventArrayCounts,
+    /// The number of outputs event arrays were routed to, summed over every array sent, by type.
+    pub routed_by_type: EventArrayCounts,
+    /// The number of event arrays each output has dropped, by the reason they were dropped.
+    pub drops_by_reason: HashMap<ComponentKey, HashMap<DropReason, u64>>,
+    /// The total time spent cloning event arrays for each output, when clone timing is enabled.
+    pub clone_time: Duration,
+    /// The number of clones timed in `clone_time`.
+    pub clone_count: u64,
+}
+
+impl FanoutStats {
+    /// Gets the average time taken to clone an event array for an output, or `None` if no clones
+    /// have been timed.
+    pub fn avg_clone_time(&self) -> Option<Duration> {
+        self.clone_time
+            .checked_div(u32::try_from(self.clone_count).unwrap_or(u32::MAX))
+    }
+
+    fn record_drop(&mut self, id: &ComponentKey, reason: DropReason) {
+        *self
+            .drops_by_reason
+            .entry(id.clone())
+            .or_default()
+            .entry(reason)
+            .or_default() += 1;
+    }
+}
+
+/// Why an output dropped an event array, rather than accepting it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DropReason {
+    /// The output's buffer was full, and configured to drop the newest arrays when full.
+    BufferFull,
+    /// The array was evicted from the output's buffer to make room for a newer array.
+    Evicted,
+    /// The array's deadline passed before it could be sent.
+    Expired,
+    /// The output was paused when an array that would otherwise have been sent to it was sent.
+    Paused,
+    /// The output's rate limit was exceeded, and configured to drop arrays over the limit.
+    RateLimited,
+    /// The array was filtered out before reaching the output.
+    Filtered,
+}
+
+/// A copy of all of a fanout's statistics, captured at a single point in time.
+///
+/// Unlike reading each statistic from the fanout in turn, every value here is from the same moment,
+/// so values can be combined or compared without sends in between skewing them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatsSnapshot {
+    /// The number of event arrays sent, by type.
+    pub by_type: EventArrayCounts,
+    /// The number of outputs event arrays were routed to, summed over every array sent, by type.
+    pub routed_by_type: EventArrayCounts,
+    /// The number of event arrays each output has dropped, by the reason they were dropped.
+    pub drops_by_reason: HashMap<ComponentKey, HashMap<DropReason, u64>>,
+    /// The number of sends each output has failed to accept.
+    pub errors: HashMap<ComponentKey, u64>,
+    /// The number of arrays each output has accepted.
+    pub deliveries: HashMap<ComponentKey, u64>,
+    /// The total time each output has held up sends.
+    pub backpressure: HashMap<ComponentKey, Duration>,
+    /// The estimated number of events sent through the fanout per second.
+    pub throughput_eps: f64,
+    /// The statistics of each shadow output, as well as the overflow output, if any.
+    pub shadows: HashMap<ComponentKey, ShadowStats>,
+    /// The total time spent cloning event arrays for each output, when clone timing is enabled.
+    pub clone_time: Duration,
+    /// The number of clones timed in `clone_time`.
+    pub clone_count: u64,
+}
+
+/// Statistics about the arrays sent to a shadow output.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ShadowStats {
+    /// The number of arrays the shadow accepted.
+    pub sent: u64,
+    /// The number of arrays the shadow failed to accept.
+    pub errors: u64,
+    /// The number of arrays dropped because the shadow was still busy with a previous array.
+    pub dropped: u64,
+}
+
+/// A description of a fanout's delivery graph, for visualization: the fanout, with an edge to each
+/// of its outputs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FanoutGraph {
+    /// The name of the fanout, if it has one.
+    pub name: Option<String>,
+    /// The edges to each output, with regular outputs first, in order, followed by shadows, the
+    /// overflow output and encoded outputs.
+    pub edges: Vec<FanoutEdge>,
+}
+
+/// An edge in a fanout's delivery graph, to one of its outputs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FanoutEdge {
+    /// The key of the output.
+    pub output: ComponentKey,
+    /// How the output is sent arrays: `routed`, `shadow`, `overflow`, `encoded` or `shared`.
+    pub mode: &'static str,
+    /// The output a shadow receives copies of the arrays sent to, if it's a shadow.
+    pub target: Option<ComponentKey>,
+    /// The weight of the output, if it's routed.
+    pub weight: Option<u32>,
+    /// The state of the output, if it's routed: `ready`, `paused`, `held`, `quarantined` or
+    /// `awaiting_replacement`.
+    pub state: Option<&'static str>,
+}
+
+/// A consistent snapshot of a fanout's routable state, taken by `Fanout::freeze_and_snapshot` as
+/// all delivery is paused.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrozenSnapshot {
+    /// The name of the fanout, if it has one.
+    pub name: Option<String>,
+    /// Each regular output, in order.
+    pub outputs: Vec<FrozenOutput>,
+    /// Each shadow output, along with the output it shadows, or `None` for the overflow output.
+    pub shadows: Vec<(ComponentKey, Option<ComponentKey>)>,
+    /// The key of each encoded output.
+    pub encoded: Vec<ComponentKey>,
+}
+
+/// The configuration and buffered arrays of a single output, in a `FrozenSnapshot`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrozenOutput {
+    /// The key of the output.
+    pub key: ComponentKey,
+    /// The state of the output before it was frozen: `ready`, `paused`, `held`, `quarantined` or
+    /// `awaiting_replacement`.
+    pub state: &'static str,
+    /// The group the output is in, if any.
+    pub group: Option<String>,
+    /// Whether or not the output is retiring.
+    pub retiring: bool,
+    /// The priority of the output.
+    pub priority: u8,
+    /// The weight of the output.
+    pub weight: u32,
+    /// The reliability tier of the output, if it was added in one.
+    pub tier: Option<u8>,
+    /// The rate limit of the output, in arrays per second, and what happens to arrays over it.
+    pub rate_limit: Option<(u32, WhenFull)>,
+    /// The interval the output is flushed at, if it's flushed on a schedule of its own.
+    pub flush_interval: Option<Duration>,
+    /// The arrays the fanout is holding on to for the output, which haven't been sent to it yet:
+    /// its snapshot, followed by any arrays queued for retrying.
+    pub buffered: Vec<EventArray>,
+}
+
+/// A point-in-time view of a fanout's outputs, taken by `Fanout::snapshot`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FanoutSnapshot {
+    /// The key of each regular output, in order, along with whether it currently has a sink, as
+    /// opposed to being paused or awaiting replacement.
+    pub outputs: Vec<(ComponentKey, bool)>,
+}
+
+/// A count of event arrays, by type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EventArrayCounts {
+    pub logs: u64,
+    pub metrics: u64,
+    pub traces: u64,
+}
+
+impl EventArrayCounts {
+    fn increment(&mut self, events: &EventArray) {
+        self.add(events, 1);
+    }
+
+    fn add(&mut self, events: &EventArray, count: u64) {
+        match events {
+            EventArray::Logs(_) => self.logs += count,
+            EventArray::Metrics(_) => self.metrics += count,
+            EventArray::Traces(_) => self.traces += count,
+        }
+    }
+}
+
+const DEFAULT_LOG_SUPPRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+const DEFAULT_PRIORITY: u8 = 0;
+const DEFAULT_WEIGHT: u32 = 1;
+
+/// What a fanout does once its control channel has been closed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ControlClosedBehavior {
+    /// Keep sending to the current outputs indefinitely, lifting any halt under
+    /// `FanoutErrorPolicy::FailFast`, since nothing could unhalt the fanout anymore.
+    FailOpen,
+    /// Close all outputs, dropping any events sent from then on.
+    FailClosed,
+}
+
+/// An error from an operation on a fanout.
+#[derive(Clone, Debug, Eq, PartialEq, Snafu)]
+pub enum FanoutError {
+    #[snafu(display("Adding duplicate output id to fanout: {}", id))]
+    DuplicateOutput { id: ComponentKey },
+    #[snafu(display("Replacing unknown sink from fanout: {}", id))]
+    UnknownOutput { id: ComponentKey },
+    #[snafu(display("Events sent through fanout were not delivered: {:?}", status))]
+    NotDelivered { status: BatchStatus },
+    #[snafu(display("Fanout outputs failed to accept events: {:?}", ids))]
+    SendFailed { ids: Vec<ComponentKey> },
+    #[snafu(display("Fanout halted by an earlier failure"))]
+    Halted,
+}
+
+/// What a fanout does when one of its outputs fails to accept the events sent to it.
+///
+/// Whatever the policy, the events the output failed to accept are dropped for it, and `try_send`
+/// reports the failure.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FanoutErrorPolicy {
+    /// Stop sending altogether, until the fanout is unhalted with `ControlMessage::Unhalt`. Until
+    /// then, every later send waits, holding on to its array, and `send_stream` takes no more
+    /// arrays from its stream, such that no more events are dropped, while `try_send` fails with
+    /// `FanoutError::Halted` without sending its array. The outputs are kept. See
+    /// `Fanout::is_halted`.
+    ///
+    /// If the control channel is closed while halted, the fanout can never be unhalted, so the
+    /// fanout's `ControlClosedBehavior` decides instead: failing closed drops every output, along
+    /// with any events held or sent from then on, while failing open lifts the halt.
+    FailFast,
+    /// Remove the output, and keep sending to the others.
+    DropErroredSink,
+    /// Keep the output, and keep sending to it. A sole output may still be removed, as per the
+    /// fanout's `SingleSinkErrorPolicy`.
+    DropEventContinue,
+    /// Quarantine the output, such that it's sent no arrays until it's reinstated once a backoff
+    /// has elapsed, and keep sending to the others.
+    ///
+    /// The backoff starts at `initial_backoff`, and doubles each time the output fails again
+    /// without accepting an array in between, up to `max_backoff`. Quarantined outputs whose
+    /// backoff has elapsed are reinstated ahead of the next send. Since a sink that fails is
+    /// closed, an output added with `ControlMessage::AddWithFactory` is reinstated with a new sink
+    /// from its factory, while any other output keeps its original sink, and so is only reinstated
+    /// in earnest once its sink is replaced.
+    Quarantine {
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    },
+}
+
+/// How the outputs in a reliability tier are delivered arrays. See `ControlMessage::AddWithTier`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TierPolicy {
+    /// Arrays an output fails to accept are queued, and retried ahead of the next array sent to it,
+    /// until it accepts them.
+    ///
+    /// Since a sink that fails is closed, queued arrays are only delivered once the output's sink
+    /// is replaced. At most `max_queued` arrays are queued for each output, beyond which the oldest
+    /// are dropped.
+    AtLeastOnce { max_queued: usize },
+    /// Arrays an output fails to accept are dropped for it.
+    BestEffort,
+}
+
+impl TierPolicy {
+    /// Gets the most arrays to queue for retrying, if outputs in the tier retry at all.
+    fn max_queued(&self) -> Option<usize> {
+        match self {
+            Self::AtLeastOnce { max_queued } => Some(*max_queued),
+            Self::BestEffort => None,
+        }
+    }
+}
+
+/// What a fanout does when its only output fails to accept the events sent to it.
+///
+/// `send` has no way to return an error, so unless the policy is `PropagateIfLast`, the source
+/// sending through the fanout keeps running, and only `try_send` and the like report the failure.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SingleSinkErrorPolicy {
+    /// Keep the output, and keep sending to it.
+    Retain,
+    /// Keep the output, but halt the fanout, as under `FanoutErrorPolicy::FailFast`, such that the
+    /// source stops sending until the fanout is unhalted with `ControlMessage::Unhalt`. The send
+    /// that failed returns `FanoutError::SendFailed` from `try_send`, and any later one
+    /// `FanoutError::Halted`, while `send` waits and `send_stream` takes no more arrays.
+    PropagateIfLast,
+    /// Remove the output, leaving the fanout empty, such that sends do nothing until another output
+    /// is added.
+    DropToEmpty,
+}
+
+/// The outputs chosen by a `RoutingStrategy` to receive an event array, as indexes into the
+/// outputs it was given.
+pub type RoutingSelection = SmallVec<[usize; 8]>;
+
+/// Decides which of a fanout's outputs each event array is sent to.
+pub trait RoutingStrategy {
+    /// Select the outputs that should receive the given array.
+    ///
+    /// `sinks` holds the outputs that are able to receive the array, in the order they were added,
+    /// and the returned selection indexes into it. Any index that is out of range, or that is
+    /// selected more than once, is ignored.
+    fn select(&mut self, sinks: &[ComponentKey], array: &EventArray) -> RoutingSelection;
+
+    /// Select the outputs that should receive the given array, knowing which of them are ready.
+    ///
+    /// `ready` holds, for each of `sinks`, whether or not its buffer has room for the array right
+    /// now, such that sending to it wouldn't have to wait. By default, readiness is ignored and
+    /// this simply defers to `select`.
+    fn select_ready(
+        &mut self,
+        sinks: &[ComponentKey],
+        _ready: &[bool],
+        array: &EventArray,
+    ) -> RoutingSelection {
+        self.select(sinks, array)
+    }
+
+    /// Select the outputs that should receive the given array, knowing which of them are ready
+    /// and the weight of each.
+    ///
+    /// `weights` holds the weight of each of `sinks`. By default, weights are ignored and this
+    /// simply defers to `select_ready`.
+    fn select_weighted(
+        &mut self,
+        sinks: &[ComponentKey],
+        ready: &[bool],
+        _weights: &[u32],
+        array: &EventArray,
+    ) -> RoutingSelection {
+        self.select_ready(sinks, ready, array)
+    }
+
+    /// The output the next array would be sent to, without selecting it.
+    ///
+    /// `sinks`, `ready` and `weights` are as for `select_weighted`. This is only meaningful for
+    /// strategies that send each array to a single output, so by default there's no next target.
+    fn next_target(
+        &self,
+        _sinks: &[ComponentKey],
+        _ready: &[bool],
+        _weights: &[u32],
+    ) -> Option<usize> {
+        None
+    }
+
+    /// Forget any state kept between selections, such as whose turn it is, as when every output has
+    /// been removed. By default, there's no such state.
+    fn reset(&mut self) {}
+}
+
+/// Sends every array to all outputs. This is the default strategy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Broadcast;
+
+impl RoutingStrategy for Broadcast {
+    fn select(&mut self, sinks: &[ComponentKey], _array: &EventArray) -> RoutingSelection {
+        (0..sinks.len()).collect()
+    }
+}
+
+/// Sends each array to a single output, taking turns between outputs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl RoutingStrategy for RoundRobin {
+    fn select(&mut self, sinks: &[ComponentKey], _array: &EventArray) -> RoutingSelection {
+        if sinks.is_empty() {
+            return RoutingSelection::new();
+        }
+
+        let selected = self.next % sinks.len();
+        self.next = selected + 1;
+        smallvec![selected]
+    }
+
+    fn next_target(
+        &self,
+        sinks: &[ComponentKey],
+        _ready: &[bool],
+        _weights: &[u32],
+    ) -> Option<usize> {
+        if sinks.is_empty() {
+            None
+        } else {
+            Some(self.next % sinks.len())
+        }
+    }
+
+    fn reset(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// Sends each array to a single output, preferring outputs that are ready for it.
+///
+/// Starting from a cursor that takes turns between outputs, like `RoundRobin`, the array is sent to
+/// the first output whose buffer has room for it. Only if none of them do is it sent to the output
+/// at the cursor, waiting on it like any other send.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WorkStealing {
+    next: usize,
+}
+
+impl WorkStealing {
+    fn target(self, count: usize, is_ready: impl Fn(usize) -> bool) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+
+        let start = self.next % count;
+        let target = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|i| is_ready(*i))
+            .unwrap_or(start);
+        Some(target)
+    }
+
+    fn select_from(&mut self, count: usize, is_ready: impl Fn(usize) -> bool) -> RoutingSelection {
+        if let Some(selected) = self.target(count, is_ready) {
+            self.next = self.next % count + 1;
+            smallvec![selected]
+        } else {
+            RoutingSelection::new()
+        }
+    }
+}
+
+impl RoutingStrategy for WorkStealing {
+    fn select(&mut self, sinks: &[ComponentKey], _array: &EventArray) -> RoutingSelection {
+        self.select_from(sinks.len(), |_| true)
+    }
+
+    fn select_ready(
+        &mut self,
+        sinks: &[ComponentKey],
+        ready: &[bool],
+        _array: &EventArray,
+    ) -> RoutingSelection {
+        self.select_from(sinks.len(), |i| ready.get(i).copied().unwrap_or(true))
+    }
+
+    fn next_target(
+        &self,
+        sinks: &[ComponentKey],
+        ready: &[bool],
+        _weights: &[u32],
+    ) -> Option<usize> {
+        self.target(sinks.len(), |i| ready.get(i).copied().unwrap_or(true))
+    }
+
+    fn reset(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// Sends each array to a single output, taking turns between outputs in proportion to their
+/// weights.
+///
+/// Over every run of arrays as long as the total weight of all outputs, each output is sent as many
+/// arrays in a row as its weight, in order. Outputs with a weight of zero are never sent any arrays,
+/// so if every output has a weight of zero, arrays aren't sent anywhere.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Weighted {
+    next: u64,
+}
+
+impl Weighted {
+    fn target(self, weights: &[u32]) -> Option<usize> {
+        let total = weights.iter().copied().map(u64::from).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+
+        let mut position = self.next % total;
+        weights.iter().copied().map(u64::from).position(|weight| {
+            if position < weight {
+                true
+            } else {
+                position -= weight;
+                false
+            }
+        })
+    }
+}
+
+impl RoutingStrategy for Weighted {
+    fn select(&mut self, sinks: &[ComponentKey], array: &EventArray) -> RoutingSelection {
+        // Without any weights to go by, every output is given the same weight.
+        self.select_weighted(sinks, &[], &vec![DEFAULT_WEIGHT; sinks.len()], array)
+    }
+
+    fn select_weighted(
+        &mut self,
+        _sinks: &[ComponentKey],
+        _ready: &[bool],
+        weights: &[u32],
+        _array: &EventArray,
+    ) -> RoutingSelection {
+        if let Some(selected) = self.target(weights) {
+            self.next += 1;
+            smallvec![selected]
+        } else {
+            RoutingSelection::new()
+        }
+    }
+
+    fn next_target(
+        &self,
+        _sinks: &[ComponentKey],
+        _ready: &[bool],
+        weights: &[u32],
+    ) -> Option<usize> {
+        self.target(weights)
+    }
+
+    fn reset(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// A complete routing configuration, applied to a fanout all at once by
+/// `ControlMessage::ReconfigureRouting`.
+pub struct RoutingConfig {
+    /// The strategy used to decide which outputs each event array is sent to.
+    pub strategy: Box<dyn RoutingStrategy + Send>,
+    /// The weight of each output. Outputs not listed get the default weight of one.
+    pub weights: HashMap<ComponentKey, u32>,
+    /// The priority of each output. Outputs not listed get the default priority of zero.
+    pub priorities: HashMap<ComponentKey, u8>,
+}
+
+/// How a fanout distributes event arrays across its outputs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FanoutMode {
+    /// Send every array to all outputs, as with the `Broadcast` strategy.
+    Broadcast,
+    /// Send each array to a single output, in proportion to the given weights, as with the
+    /// `Weighted` strategy.
+    ///
+    /// Weights are given to outputs in the order they were added. Outputs beyond the end of the
+    /// list keep their current weight.
+    LoadBalance(Vec<u32>),
+    /// Send each array to a single output, taking turns between outputs in the order they were
+    /// added, as with the `WorkStealing` strategy.
+    ///
+    /// While every output is ready, each is sent exactly one array in turn, so over any run of
+    /// arrays as long as the number of outputs, every output is sent one. If the output whose turn
+    /// it is isn't ready, the array goes to the next ready output after it instead, and the turn
+    /// still only advances by one, so a slow output is skipped rather than falling behind for good.
+    /// Only if no output is ready does the send wait, on the output whose turn it is.
+    RoundRobin,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct Fanout {
+    senders: IndexMap<ComponentKey, Output>,
+    shadows: IndexMap<ComponentKey, Shadow>,
+    encoded: IndexMap<ComponentKey, EncodedOutput>,
+    control_channel: ControlReceiver,
+    control_closed_behavior: ControlClosedBehavior,
+    single_sink_error_policy: SingleSinkErrorPolicy,
+    error_policy: FanoutErrorPolicy,
+    /// Whether an output failed under `FanoutErrorPolicy::FailFast`, such that nothing more is sent.
+    halted: bool,
+    /// Limits the number of arrays in-flight at once, if set. See `with_max_inflight`.
+    inflight: Option<Arc<Semaphore>>,
+    send_timeout: Option<Duration>,
+    close_timeout: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    min_ready_sinks: usize,
+    clone_timing: bool,
+    cow_threshold: usize,
+    sink_ready_observer: Option<SinkObserver>,
+    latency_alert: Option<(Duration, LatencyObserver)>,
+    tier_policies: HashMap<u8, TierPolicy>,
+    key_mapper: Option<KeyMapper>,
+    strategy: Box<dyn RoutingStrategy + Send>,
+    panic_free: bool,
+    global_sequence: bool,
+    next_sequence: u64,
+    /// The name of the fanout, which is shared by the path of every event it stamps.
+    name: Option<Arc<str>>,
+    path_stamping: bool,
+    exactly_once_window: usize,
+    control_on_flush: bool,
+    cooperative_chunk: Option<usize>,
+    /// The number of sends started, which the output polled first in cooperative mode rotates
+    /// with.
+    poll_start: usize,
+    /// The dead-letter sink, which is locked for as long as a send to it is in-flight.
+    dead_letter: Option<Arc<Mutex<BufferSender<EventArray>>>>,
+    stats: FanoutStats,
+    throughput: ThroughputTracker,
+    error_log_limiter: ErrorLogLimiter,
+}
+
+impl Fanout {
+    pub fn new() -> (Self, ControlChannel) {
+        Self::with_control(ControlReceiver::channel())
+    }
+
+    /// Creates a fanout whose control channel holds at most `capacity` control messages waiting to
+    /// be applied, rather than any number of them.
+    ///
+    /// This bounds the memory a misbehaving control plane can take up. The fanout applies waiting
+    /// control messages before each send, and while a send waits on its outputs, so the channel
+    /// only stays full while the fanout isn't sending. Events are never held up by a full channel,
+    /// since the fanout only ever receives from it. On the sending side, `ControlChannel::send`
+    /// hands back a control message that doesn't fit, while `ControlChannel::send_waiting` waits
+    /// for room.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the capacity is zero.
+    pub fn with_bounded_control(capacity: usize) -> (Self, ControlChannel) {
+        Self::with_control(ControlReceiver::bounded_channel(capacity))
+    }
+
+    fn with_control(
+        (control_tx, control_rx): (ControlChannel, ControlReceiver),
+    ) -> (Self, ControlChannel) {
+        let fanout = Self {
+            senders: Default::default(),
+            shadows: Default::default(),
+            encoded: Default::default(),
+            control_channel: control_rx,
+            control_closed_behavior: ControlClosedBehavior::FailOpen,
+            single_sink_error_policy: SingleSinkErrorPolicy::Retain,
+            error_policy: FanoutErrorPolicy::DropEventContinue,
+            halted: false,
+            inflight: None,
+            send_timeout: None,
+            close_timeout: None,
+            keepalive_interval: None,
+            min_ready_sinks: 0,
+            clone_timing: false,
+            cow_threshold: 0,
+            sink_ready_observer: None,
+            latency_alert: None,
+            tier_policies: HashMap::new(),
+            key_mapper: None,
+            strategy: Box::new(Broadcast),
+            panic_free: false,
+            global_sequence: false,
+            next_sequence: 0,
+            name: None,
+            path_stamping: false,
+            exactly_once_window: 0,
+            control_on_flush: false,
+            cooperative_chunk: None,
+            poll_start: 0,
+            dead_letter: None,
+            stats: FanoutStats::default(),
+            throughput: ThroughputTracker::default(),
+            error_log_limiter: ErrorLogLimiter::new(DEFAULT_LOG_SUPPRESS_INTERVAL),
+        };
+
+        (fanout, control_tx)
+    }
+
+    /// Creates a fanout with the given sinks as its initial outputs, in order.
+    ///
+    /// # Errors
+    ///
+    /// If the same ID is given for more than one sink, no fanout is created, and
+    /// `FanoutError::DuplicateOutput` is returned with the first ID given again.
+    pub fn with_sinks(
+        sinks: impl IntoIterator<Item = (ComponentKey, BufferSender<EventArray>)>,
+    ) -> Result<(Self, ControlChannel), FanoutError> {
+        let (mut fanout, control) = Self::new();
+        fanout.add_all(sinks)?;
+        Ok((fanout, control))
+    }
+
+    /// Creates a new fanout with the given sinks as its outputs, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the same key is given more than once. See `with_sinks` for a fallible version.
+    pub fn from_sinks(
+        sinks: impl IntoIterator<Item = (ComponentKey, BufferSender<EventArray>)>,
+    ) -> (Self, ControlChannel) {
+        Self::with_sinks(sinks).expect("sink keys must be unique")
+    }
+
+    /// Set what the fanout does once its control channel has been closed.
+    ///
+    /// Defaults to `ControlClosedBehavior::FailOpen`. When set to `ControlClosedBehavior::FailClosed`,
+    /// all outputs are closed as soon as the fanout notices the control channel is closed, or after
+    /// the current send completes if the channel is closed while a send is in-flight.
+    #[must_use]
+    pub fn with_control_closed_behavior(mut self, behavior: ControlClosedBehavior) -> Self {
+        self.control_closed_behavior = behavior;
+        self
+    }
+
+    /// Set what the fanout does when its only output fails to accept the events sent to it.
+    ///
+    /// Defaults to `SingleSinkErrorPolicy::Retain`. Errors from an output are always logged, and
+    /// unless the error policy says otherwise, outputs are never removed for failing while there
+    /// are others, nor is the fanout halted. This only applies under
+    /// `FanoutErrorPolicy::DropEventContinue`.
+    #[must_use]
+    pub fn with_single_sink_error_policy(mut self, policy: SingleSinkErrorPolicy) -> Self {
+        self.single_sink_error_policy = policy;
+        self
+    }
+
+    /// Set what the fanout does when any of its outputs fails to accept the events sent to it.
+    ///
+    /// Defaults to `FanoutErrorPolicy::DropEventContinue`.
+    #[must_use]
+    pub fn with_error_policy(mut self, policy: FanoutErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Set how often errors are logged for any one output.
+    ///
+    /// Only the first error from a given output within each interval is logged, along with a count
+    /// of the errors suppressed since the last one logged. Defaults to 10 seconds.
+    #[must_use]
+    pub fn with_log_suppress_interval(mut self, interval: Duration) -> Self {
+        self.error_log_limiter = ErrorLogLimiter::new(interval);
+        self
+    }
+
+    /// Set the maximum amount of time a single send may take.
+    ///
+    /// If sending to all outputs takes longer than the given timeout, any outputs which have not yet
+    /// accepted the events are considered too slow and are dropped from the fanout, and the send
+    /// completes with the remaining outputs.
+    #[must_use]
+    pub fn with_send_timeout(mut self, timeout: Duration) -> Self {
+        self.send_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of arrays that may be in-flight at once.
+    ///
+    /// An array is in-flight from when its send starts until every output it was sent to has
+    /// accepted it. While a send only completes once that's the case, sends to outputs which are
+    /// removed, or paused, while waiting on them are left to complete in the background, so a
+    /// fanout can otherwise have any number of arrays in-flight. Once the limit is reached, a send
+    /// waits until an earlier array is no longer in-flight. Sends to shadows and to the overflow
+    /// output aren't counted, since they never hold up a send.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the limit is zero.
+    #[must_use]
+    pub fn with_max_inflight(mut self, max_inflight: usize) -> Self {
+        assert!(max_inflight > 0, "max in-flight arrays must be nonzero");
+        self.inflight = Some(Arc::new(Semaphore::new(max_inflight)));
+        self
+    }
+
+    /// Set the maximum amount of time `drain` may take, when it isn't given a timeout of its own.
+    ///
+    /// Outputs which have not been drained once the timeout elapses are dropped without waiting on
+    /// them any further, and each is logged, so that a sink which never finishes can't hold up
+    /// shutdown indefinitely.
+    #[must_use]
+    pub fn with_close_timeout(mut self, timeout: Duration) -> Self {
+        self.close_timeout = Some(timeout);
+        self
+    }
+
+    /// Set how long `send_stream` may go without sending anything before it sends a keepalive.
+    ///
+    /// A keepalive is an empty array of log events, sent to all outputs in order to keep any
+    /// downstream connections from timing out while idle, and so sinks can safely ignore it. It
+    /// is sent each time the interval elapses without any other array being sent, and is not
+    /// counted in the fanout's statistics.
+    #[must_use]
+    pub fn with_keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Set the minimum number of outputs that must be ready before any array is sent.
+    ///
+    /// Until at least this many outputs are present, not paused or quarantined, not awaiting
+    /// replacement, and have room in their buffer as per `ready_status`, sends wait for control
+    /// messages to add or restore enough of them, for quarantined outputs to be reinstated, or for
+    /// full buffers to be read from, rather than delivering the array to fewer outputs. Defaults to
+    /// zero.
+    #[must_use]
+    pub fn with_min_ready_sinks(mut self, min_ready_sinks: usize) -> Self {
+        self.min_ready_sinks = min_ready_sinks;
+        self
+    }
+
+    /// Set whether or not to time how long it takes to clone each event array sent to an output.
+    ///
+    /// Timings are recorded in the fanout's statistics, where `FanoutStats::avg_clone_time` gives
+    /// their average. Disabled by default, as it adds some overhead to every send.
+    #[must_use]
+    pub fn with_clone_timing(mut self, clone_timing: bool) -> Self {
+        self.clone_timing = clone_timing;
+        self
+    }
+
+    /// Set the number of events below which an array is copied for each shared output, rather than
+    /// shared between them.
+    ///
+    /// Sharing an array saves copying it for each shared output, but any output that modifies it
+    /// then copies it anyway, and shared arrays are kept alive until every output is done with
+    /// them. Small arrays are cheap enough to copy that each shared output is better off with its
+    /// own. Defaults to zero, such that every array is shared. Outputs other than shared outputs
+    /// always get their own copy.
+    #[must_use]
+    pub fn with_cow_threshold(mut self, events: usize) -> Self {
+        self.cow_threshold = events;
+        self
+    }
+
+    /// Set a function to call whenever an output that had to be waited on becomes ready.
+    ///
+    /// The observer is called with the output's key as soon as a send to it that couldn't complete
+    /// right away, such as because its buffer was full, does complete. Outputs that accept a send
+    /// straight away never had to be waited on, and so aren't observed.
+    #[must_use]
+    pub fn with_sink_ready_observer(
+        mut self,
+        observer: impl Fn(&ComponentKey) + Send + Sync + 'static,
+    ) -> Self {
+        self.sink_ready_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Set a function to call whenever a send to an output takes longer than the given threshold.
+    ///
+    /// Latency is measured for each output separately, from when the send starts to when that
+    /// output accepts the array, and the observer is called with the output's key and its latency
+    /// as soon as it does. Sends which never complete, such as those abandoned after the send
+    /// timeout, aren't observed.
+    #[must_use]
+    pub fn with_latency_alert(
+        mut self,
+        threshold: Duration,
+        observer: impl Fn(&ComponentKey, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.latency_alert = Some((threshold, Box::new(observer)));
+        self
+    }
+
+    /// Set a function used to rewrite the key of every output added to the fanout.
+    ///
+    /// The mapper is applied whenever an output is added, whether directly or by way of a control
+    /// message, before the output is actually inserted. This allows namespacing keys to avoid
+    /// collisions, but only the key given when adding an output is rewritten: every other operation
+    /// must refer to the output by its rewritten key.
+    #[must_use]
+    pub fn with_key_mapper(
+        mut self,
+        key_mapper: impl Fn(ComponentKey) -> ComponentKey + Send + 'static,
+    ) -> Self {
+        self.key_mapper = Some(Box::new(key_mapper));
+        self
+    }
+
+    /// Set the strategy used to decide which outputs each event array is sent to.
+    ///
+    /// Defaults to `Broadcast`, which sends every array to all outputs. Paused outputs are never
+    /// offered to the strategy.
+    #[must_use]
+    pub fn with_strategy(mut self, strategy: Box<dyn RoutingStrategy + Send>) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set how arrays are distributed across outputs.
+    ///
+    /// This is shorthand for setting the `Broadcast`, `Weighted` or `WorkStealing` strategy, along
+    /// with the weight of each existing output when load balancing. When load balancing, only the output
+    /// chosen for an array takes part in sending it, so a send waits on that output alone.
+    #[must_use]
+    pub fn with_mode(mut self, mode: FanoutMode) -> Self {
+        self.strategy = match mode {
+            FanoutMode::Broadcast => Box::new(Broadcast),
+            FanoutMode::LoadBalance(weights) => {
+                for (output, weight) in self.senders.values_mut().zip(weights) {
+                    output.weight = weight;
+                }
+                Box::new(Weighted::default())
+            }
+            FanoutMode::RoundRobin => Box::new(WorkStealing::default()),
+        };
+        self
+    }
+
+    /// Set whether invalid operations are logged and ignored, rather than panicking.
+    ///
+    /// Adding a duplicate output, replacing or cancelling the replacement of an output that isn't
+    /// awaiting replacement, pausing an output that's already paused, resuming one that isn't
+    /// paused, and any other operation on an unknown output all panic by default, as they can only
+    /// happen if an invalid sequence of control operations has been applied. When panic-free, each
+    /// is instead logged as an error and the fanout is left as it was, except that replacing an
+    /// output that isn't awaiting replacement swaps in the new sink.
+    ///
+    /// Removing or replacing an unknown output never panics, since the output may have been removed
+    /// concurrently with a topology reload, and is only logged as a warning.
+    #[must_use]
+    pub fn with_panic_free(mut self, panic_free: bool) -> Self {
+        self.panic_free = panic_free;
+        self
+    }
+
+    /// Set whether every array sent is stamped with a global sequence number.
+    ///
+    /// The sequence number is set in the metadata of each event before the array is cloned for each
+    /// output, so every output sees the same number for the same array, and numbers increase with
+    /// every array sent. This allows a downstream merge of several outputs to restore the order in
+    /// which arrays were sent.
+    #[must_use]
+    pub fn with_global_sequence(mut self, global_sequence: bool) -> Self {
+        self.global_sequence = global_sequence;
+        self
+    }
+
+    /// Set the name of the fanout, which identifies it when stamping the path of each array.
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(Arc::from(name.into()));
+        self
+    }
+
+    /// Set whether every array sent is stamped with the name of the fanout.
+    ///
+    /// The name is appended to the path in the metadata of each event before it's delivered, so
+    /// that an array sent through several nested fanouts carries the name of each, in order. Has no
+    /// effect unless the fanout is named with `with_name`.
+    #[must_use]
+    pub fn with_path_stamping(mut self, path_stamping: bool) -> Self {
+        self.path_stamping = path_stamping;
+        self
+    }
+
+    /// Enable exactly-once delivery of arrays sent with `send_with_token`, remembering the dedup
+    /// tokens of the given number of arrays most recently accepted by each output.
+    ///
+    /// Only a bounded window of tokens is remembered per output, so a retry is only suppressed for
+    /// an output if it accepted no more than `window` other arrays since the original attempt.
+    /// Tokens are also forgotten when an output is removed. Defaults to zero, which disables it.
+    #[must_use]
+    pub fn with_exactly_once(mut self, window: usize) -> Self {
+        self.exactly_once_window = window;
+        self
+    }
+
+    /// Set whether control messages are only applied when the fanout is flushed.
+    ///
+    /// By default, control messages are applied as soon as the fanout notices them, including while
+    /// a send is in-flight. When enabled, they're instead left waiting on the control channel until
+    /// `flush` is called, so the outputs only change between batches. The exception is when a send
+    /// can't proceed without them, i.e. while an output is awaiting replacement or too few outputs
+    /// are ready, in which case they're applied as usual.
+    #[must_use]
+    pub fn with_control_on_flush(mut self, control_on_flush: bool) -> Self {
+        self.control_on_flush = control_on_flush;
+        self
+    }
+
+    /// Set the maximum number of outputs whose sends are driven each time a send is polled.
+    ///
+    /// By default, every in-flight send is polled each time the send itself is, which with hundreds
+    /// of outputs can keep the task from yielding to others for a long time. In cooperative mode,
+    /// at most `chunk` of them are polled before the task yields, in output order, with the rest
+    /// left for subsequent polls. A chunk of zero disables cooperative mode.
+    ///
+    /// Each send starts polling one output further along than the send before it, wrapping around
+    /// to the first output, so that no output is always polled first. Under backpressure, an output
+    /// early in the order that's chronically slow then can't keep the outputs after it from being
+    /// polled promptly on every send.
+    ///
+    /// Every output is still armed with the array when the send starts, and the next send still
+    /// only starts once they've all accepted it, so each output gets the same arrays in the same
+    /// order as before. However, outputs later in the order can be handed an array several polls
+    /// after outputs earlier in the order, by which time their consumers may already have
+    /// processed it.
+    #[must_use]
+    pub fn with_cooperative_send(mut self, chunk: usize) -> Self {
+        self.cooperative_chunk = if chunk == 0 { None } else { Some(chunk) };
+        self
+    }
+
+    /// Set the delivery policy of each reliability tier, replacing any set before.
+    ///
+    /// Outputs in a tier without a policy are best-effort, like outputs added without a tier.
+    #[must_use]
+    pub fn with_tier_policies(mut self, policies: HashMap<u8, TierPolicy>) -> Self {
+        self.tier_policies = policies;
+        self
+    }
+
+    /// Set the dead-letter sink, replacing any existing one.
+    ///
+    /// The dead-letter sink is sent each array that any output failed to accept, once per array
+    /// however many outputs failed, so that it can be captured and reprocessed. Unlike the overflow
+    /// output, it isn't sent arrays that outputs dropped for being full or rate limited, or missed
+    /// while paused.
+    ///
+    /// Sends to it happen in the background, in order, so that a slow dead-letter sink never holds
+    /// up the fanout. If the dead-letter sink fails as well, or doesn't accept the array before the
+    /// send timeout elapses, the error is logged and the array is dropped.
+    pub fn set_dead_letter(&mut self, sink: BufferSender<EventArray>) {
+        self.dead_letter = Some(Arc::new(Mutex::new(sink)));
+    }
+
+    /// Add a new sink as an output.
+    ///
+    /// # Panics
+    ///
+    /// Function will panic if a sink with the same ID is already present, unless the fanout is
+    /// panic-free.
+    pub fn add(&mut self, id: ComponentKey, sink: BufferSender<EventArray>) {
+        if let Err(error) = self.try_add(id, sink) {
+            invalid_operation(self.panic_free, format_args!("{}", error));
+        }
+    }
+
+    /// Add a new sink as an output, unless an output with the same ID is already present.
+    ///
+    /// # Errors
+    ///
+    /// If an output with the same ID is already present, as determined by `contains`, the sink is
+    /// not added and `FanoutError::DuplicateOutput` is returned.
+    pub fn try_add(
+        &mut self,
+        mut id: ComponentKey,
+        sink: BufferSender<EventArray>,
+    ) -> Result<(), FanoutError> {
+        map_added_key(self.key_mapper.as_ref(), &mut id);
+        if self.contains(&id) {
+            return Err(FanoutError::DuplicateOutput { id });
+        }
+        self.senders.insert(id, Output::new(sink, None));
+        Ok(())
+    }
+
+    /// Add several new sinks as outputs, in order, unless any of their IDs is already taken.
+    ///
+    /// # Errors
+    ///
+    /// If any ID is already an output, as determined by `contains`, or is given for an earlier sink
+    /// in the same batch, none of the sinks are added, and `FanoutError::DuplicateOutput` is
+    /// returned with the first such ID.
+    pub fn add_all(
+        &mut self,
+        sinks: impl IntoIterator<Item = (ComponentKey, BufferSender<EventArray>)>,
+    ) -> Result<(), FanoutError> {
+        let mut sinks = sinks.into_iter().collect::<Vec<_>>();
+        for (id, _) in &mut sinks {
+            map_added_key(self.key_mapper.as_ref(), id);
+        }
+
+        let mut ids = HashSet::new();
+        if let Some((id, _)) = sinks
+            .iter()
+            .find(|(id, _)| self.contains(id) || !ids.insert(id))
+        {
+            return Err(FanoutError::DuplicateOutput { id: id.clone() });
+        }
+
+        for (id, sink) in sinks {
+            self.senders.insert(id, Output::new(sink, None));
+        }
+        Ok(())
+    }
+
+    fn add_to_group(
+        &mut self,
+        id: ComponentKey,
+        sink: BufferSender<EventArray>,
+        group: Option<String>,
+    ) {
+        self.add_output(id, Output::new(sink, group));
+    }
+
+    fn add_output(&mut self, id: ComponentKey, output: Output) {
+        if key_in_use(&self.senders, &self.shadows, &self.encoded, &id) {
+            invalid_operation(
+                self.panic_free,
+                format_args!("Adding duplicate output id to fanout: {id}"),
+            );
+            return;
+        }
+        self.senders.insert(id, output);
+    }
+
+    /// Removes the output with the given ID, returning whether or not it was present.
+    ///
+    /// Shadows, the overflow output and encoded outputs are removed the same way as any other.
+    pub fn try_remove(&mut self, id: &ComponentKey) -> bool {
+        self.shadows.shift_remove(id).is_some()
+            || self.encoded.shift_remove(id).is_some()
+            || self.senders.shift_remove(id).is_some()
+    }
+
+    fn remove(&mut self, id: &ComponentKey) {
+        if !self.try_remove(id) {
+            warn!(message = "Removing non-existent sink from fanout.", output = %id);
+        }
+    }
+
+    /// Replaces the sink of the output with the given ID.
+    ///
+    /// # Errors
+    ///
+    /// If no output with the given ID is present, such as when it was removed concurrently with a
+    /// topology reload, the sink is dropped and `FanoutError::UnknownOutput` is returned.
+    pub fn try_replace(
+        &mut self,
+        id: &ComponentKey,
+        sink: BufferSender<EventArray>,
+    ) -> Result<(), FanoutError> {
+        let output = self
+            .senders
+            .get_mut(id)
+            .ok_or_else(|| FanoutError::UnknownOutput { id: id.clone() })?;
+
+        // While a sink must be _known_ to be replaced, it must also be empty (previously paused or
+        // consumed when the `SendGroup` was created), otherwise an invalid sequence of control
+        // operations has been applied.
+        if output.sender.replace(Sender::new(sink)).is_some() {
+            invalid_operation(
+                self.panic_free,
+                format_args!("Replacing existing sink is not valid: {id}"),
+            );
+        }
+        Ok(())
+    }
+
+    fn replace(&mut self, id: &ComponentKey, sink: BufferSender<EventArray>) {
+        reset_backpressure(&mut self.senders, id);
+        if let Err(error) = self.try_replace(id, sink) {
+            warn!(message = "Failed to replace sink.", %error);
+        }
+    }
+
+    fn pause(&mut self, id: &ComponentKey) {
+        reset_backpressure(&mut self.senders, id);
+        match self.senders.get_mut(id) {
+            Some(output) => {
+                // A sink must be known and present to be replaced, otherwise an invalid sequence of
+                // control operations has been applied.
+                if output.sender.take().is_none() {
+                    invalid_operation(
+                        self.panic_free,
+                        format_args!("Pausing non-existent sink is not valid: {id}"),
+                    );
+                }
+            }
+            None => invalid_operation(
+                self.panic_free,
+                format_args!("Pausing unknown sink from fanout: {id}"),
+            ),
+        }
+    }
+
+    fn cancel_replace(&mut self, id: &ComponentKey) {
+        cancel_replace(&mut self.senders, id, self.panic_free);
+    }
+
+    fn add_shadow(
+        &mut self,
+        id: ComponentKey,
+        sink: BufferSender<EventArray>,
+        target: ComponentKey,
+    ) {
+        add_shadow(
+            &self.senders,
+            &mut self.shadows,
+            &self.encoded,
+            id,
+            sink,
+            target,
+            self.panic_free,
+        );
+    }
+
+    fn set_priority(&mut self, id: &ComponentKey, priority: u8) {
+        set_priority(&mut self.senders, id, priority, self.panic_free);
+    }
+
+    fn adjust_weight(&mut self, id: &ComponentKey, delta: i32) {
+        adjust_weight(&mut self.senders, id, delta, self.panic_free);
+    }
+
+    /// Emits the current state of the fanout as a single structured `info` event.
+    ///
+    /// This is meant for diagnosing a running fanout on demand, such as in response to a signal.
+    /// Each output is listed with its state, followed by the number of arrays it has accepted,
+    /// failed to accept, and dropped.
+    pub fn trace_state(&self) {
+        let outputs = self
+            .senders
+            .iter()
+            .map(|(key, output)| {
+                let drops = self
+                    .stats
+                    .drops_by_reason
+                    .get(key)
+                    .map_or(0, |reasons| reasons.values().sum::<u64>());
+                (
+                    key.id(),
+                    output.state(),
+                    output.deliveries,
+                    output.errors,
+                    drops,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        info!(
+            message = "Fanout state.",
+            name = self.name.as_deref(),
+            output_count = self.senders.len(),
+            outputs = ?outputs,
+            logs_sent = self.stats.by_type.logs,
+            metrics_sent = self.stats.by_type.metrics,
+            traces_sent = self.stats.by_type.traces,
+        );
+    }
+
+    /// Renders the current metrics of the fanout in the `OpenMetrics` text exposition format.
+    ///
+    /// Per-output metrics are labeled with the output's key. Buffer occupancy is only included for
+    /// outputs that are able to report it.
+    pub fn to_openmetrics(&self) -> String {
+        let mut lines = vec![
+            "# TYPE fanout_outputs gauge".to_string(),
+            format!("fanout_outputs {}", self.senders.len()),
+            "# TYPE fanout_event_arrays_sent counter".to_string(),
+        ];
+        let by_type = &self.stats.by_type;
+        for (event_type, count) in [
+            ("logs", by_type.logs),
+            ("metrics", by_type.metrics),
+            ("traces", by_type.traces),
+        ] {
+            lines.push(format!(
+                "fanout_event_arrays_sent_total{{type=\"{}\"}} {}",
+                event_type, count
+            ));
+        }
+
+        lines.push("# TYPE fanout_output_errors counter".to_string());
+        for (key, output) in &self.senders {
+            lines.push(format!(
+                "fanout_output_errors_total{{output=\"{}\"}} {}",
+                escape_label_value(key.id()),
+                output.errors
+            ));
+        }
+
+        let occupancy = self.occupancy();
+        lines.push("# TYPE fanout_output_buffer_events gauge".to_string());
+        for (key, (events, _)) in occupancy_of(&occupancy) {
+            lines.push(format!(
+                "fanout_output_buffer_events{{output=\"{}\"}} {}",
+                escape_label_value(key.id()),
+                events
+            ));
+        }
+        lines.push("# TYPE fanout_output_buffer_max_events gauge".to_string());
+        for (key, (_, max_events)) in occupancy_of(&occupancy) {
+            lines.push(format!(
+                "fanout_output_buffer_max_events{{output=\"{}\"}} {}",
+                escape_label_value(key.id()),
+                max_events
+            ));
+        }
+
+        lines.push("# EOF".to_string());
+        let mut exposition = lines.join("\n");
+        exposition.push('\n');
+        exposition
+    }
+
+    /// Describes the fanout's delivery graph, with an edge to each of its outputs.
+    pub fn graph(&self) -> FanoutGraph {
+        let routed = self.senders.iter().map(|(key, output)| FanoutEdge {
+            output: key.clone(),
+            mode: "routed",
+            target: None,
+            weight: Some(output.weight),
+            state: Some(output.state()),
+        });
+        let shadows = self.shadows.iter().map(|(key, shadow)| FanoutEdge {
+            output: key.clone(),
+            mode: if shadow.target.is_some() {
+                "shadow"
+            } else {
+                "overflow"
+            },
+            target: shadow.target.clone(),
+            weight: None,
+            state: None,
+        });
+        let encoded = self.encoded.iter().map(|(key, output)| FanoutEdge {
+            output: key.clone(),
+            mode: match output {
+                EncodedOutput::Bytes { .. } => "encoded",
+                EncodedOutput::Shared(_) => "shared",
+            },
+            target: None,
+            weight: None,
+            state: None,
+        });
+
+        FanoutGraph {
+            name: self.name.as_deref().map(String::from),
+            edges: routed.chain(shadows).chain(encoded).collect(),
+        }
+    }
+
+    /// Renders the fanout's delivery graph in the Graphviz DOT language.
+    ///
+    /// The fanout is a node named after it, or `fanout` if it has no name, with a node for each
+    /// output labeled with its key, weight and state. Shadows are drawn as dashed edges from the
+    /// output they shadow.
+    pub fn to_dot(&self) -> String {
+        let graph = self.graph();
+        let name = escape_label_value(graph.name.as_deref().unwrap_or("fanout"));
+        let mut lines = vec![
+            format!("digraph \"{}\" {{", name),
+            format!("    \"{}\" [shape=box];", name),
+        ];
+        for edge in &graph.edges {
+            let key = escape_label_value(edge.output.id());
+            let mut label = vec![key.clone()];
+            if let Some(weight) = edge.weight {
+                label.push(format!("weight: {}", weight));
+            }
+            if let Some(state) = edge.state {
+                label.push(format!("state: {}", state));
+            }
+            lines.push(format!(
+                "    \"{}\" [label=\"{}\"];",
+                key,
+                label.join("\\n")
+            ));
+
+            let (from, style) = match &edge.target {
+                Some(target) => (escape_label_value(target.id()), ", style=dashed"),
+                None => (name.clone(), ""),
+            };
+            lines.push(format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                from, key, edge.mode, style
+            ));
+        }
+        lines.push("}".to_string());
+
+        let mut dot = lines.join("\n");
+        dot.push('\n');
+        dot
+    }
+
+    /// Gets the statistics about the arrays sent to the given shadow or overflow output so far, or
+    /// `None` if no such output exists.
+    pub fn shadow_stats(&self, id: &ComponentKey) -> Option<ShadowStats> {
+        self.shadows.get(id).map(Shadow::stats)
+    }
+
+    /// Gets the statistics about the events sent through this fanout so far.
+    pub fn stats(&self) -> &FanoutStats {
+        &self.stats
+    }
+
+    /// Captures a snapshot of all of the fanout's statistics at once.
+    ///
+    /// The snapshot is unaffected by any sends made after it is taken.
+    pub fn stats_guard(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            by_type: self.stats.by_type,
+            routed_by_type: self.stats.routed_by_type,
+            drops_by_reason: self.stats.drops_by_reason.clone(),
+            errors: self
+                .senders
+                .iter()
+                .map(|(key, output)| (key.clone(), output.errors))
+                .collect(),
+            deliveries: self
+                .senders
+                .iter()
+                .map(|(key, output)| (key.clone(), output.deliveries))
+                .collect(),
+            backpressure: self
+                .senders
+                .iter()
+                .map(|(key, output)| (key.clone(), output.backpressure))
+                .collect(),
+            throughput_eps: self.throughput_eps(),
+            shadows: self
+                .shadows
+                .iter()
+                .map(|(key, shadow)| (key.clone(), shadow.stats()))
+                .collect(),
+            clone_time: self.stats.clone_time,
+            clone_count: self.stats.clone_count,
+        }
+    }
+
+    /// Gets the average number of outputs each event array was routed to, by type.
+    ///
+    /// Only types of which at least one array has been sent are included. Outputs an array was
+    /// routed to are counted whether or not they went on to accept it.
+    #[allow(clippy::cast_precision_loss)] // averages needn't be exact
+    pub fn avg_fanout_by_type(&self) -> HashMap<DataType, f64> {
+        let (sent, routed) = (&self.stats.by_type, &self.stats.routed_by_type);
+        [
+            (DataType::Log, sent.logs, routed.logs),
+            (DataType::Metric, sent.metrics, routed.metrics),
+            (DataType::Trace, sent.traces, routed.traces),
+        ]
+        .into_iter()
+        .filter(|(_, sent, _)| *sent > 0)
+        .map(|(data_type, sent, routed)| (data_type, routed as f64 / sent as f64))
+        .collect()
+    }
+
+    /// Gets a smoothed estimate of the number of events sent through this fanout per second.
+    ///
+    /// The estimate decays exponentially, so that it mostly reflects the last few seconds.
+    pub fn throughput_eps(&self) -> f64 {
+        self.throughput.rate_at(Instant::now())
+    }
+
+    /// Resets all of the fanout's statistics, including those of each output and shadow, as if
+    /// nothing had been sent through it yet.
+    ///
+    /// Outputs, routing state, and configuration are all left as they are.
+    pub fn reset_stats(&mut self) {
+        self.stats = FanoutStats::default();
+        self.throughput = ThroughputTracker::default();
+        for output in self.senders.values_mut() {
+            output.errors = 0;
+            output.deliveries = 0;
+            output.backpressure = Duration::ZERO;
+        }
+        for shadow in self.shadows.values() {
+            shadow.reset_stats();
+        }
+    }
+
+    /// Gets how long ago the oldest control message that has yet to be applied was sent, or `None`
+    /// if there are no such control messages.
+    ///
+    /// Control messages are only applied when sending, so a fanout that is stuck waiting, or simply
+    /// has nothing to send, will see this grow for as long as control messages keep waiting.
+    pub fn oldest_pending_control_age(&self) -> Option<Duration> {
+        self.control_channel.oldest_age()
+    }
+
+    /// Gets the number of control messages that have been sent but have yet to be applied.
+    ///
+    /// Like `oldest_pending_control_age`, this grows while the fanout isn't sending, so a fanout
+    /// that keeps falling behind on topology changes shows up as a backlog that doesn't drain.
+    pub fn pending_control_messages(&self) -> usize {
+        self.control_channel.len()
+    }
+
+    /// Gets the number of outputs.
+    ///
+    /// Every registered output is counted, including those that are paused or awaiting replacement,
+    /// but not shadows, the overflow output, or encoded outputs.
+    pub fn len(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// Whether or not the fanout has no outputs, as counted by `len`.
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    /// Whether or not the fanout has stopped sending, since an output failed under
+    /// `FanoutErrorPolicy::FailFast`, and hasn't been unhalted since.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether or not an output with the given key is present, such that adding another output with
+    /// the same key would be rejected as a duplicate.
+    ///
+    /// Unlike `len` and `output_ids`, this includes shadows, the overflow output and encoded outputs,
+    /// since their keys are shared with every other output. Outputs that are paused or awaiting
+    /// replacement are present all the same.
+    pub fn contains(&self, id: &ComponentKey) -> bool {
+        key_in_use(&self.senders, &self.shadows, &self.encoded, id)
+    }
+
+    /// Gets the keys of the outputs counted by `len`, in the order the outputs were added.
+    pub fn output_ids(&self) -> impl Iterator<Item = &ComponentKey> {
+        self.senders.keys()
+    }
+
+    /// Gets the number of arrays each output has accepted, in the order the outputs were added.
+    ///
+    /// Counts carry over when an output's sink is replaced, but start over from zero when an output
+    /// is removed and added again.
+    pub fn delivery_counts(&self) -> Vec<(ComponentKey, u64)> {
+        self.senders
+            .iter()
+            .map(|(key, output)| (key.clone(), output.deliveries))
+            .collect()
+    }
+
+    /// Gets the occupancy of each output's buffer, as the number of events it holds and the total
+    /// number of events it can hold.
+    ///
+    /// The occupancy is `None` for outputs whose buffer is unable to report it, as well as outputs
+    /// that are awaiting replacement.
+    pub fn occupancy(&self) -> Vec<(ComponentKey, Option<(usize, usize)>)> {
+        self.senders
+            .iter()
+            .map(|(key, output)| {
+                let occupancy = output
+                    .sender
+                    .as_ref()
+                    .and_then(|sender| sender.inner.occupancy());
+                (key.clone(), occupancy)
+            })
+            .collect()
+    }
+
+    /// Gets the total time each output has held up sends, in the order the outputs were added.
+    ///
+    /// A send to an output is held up from when it's first found waiting on the output's sink until
+    /// the sink accepts the array, so an output that keeps up never accrues any time, while the
+    /// bottleneck of a pipeline accrues the most. Sends still in-flight aren't counted until they
+    /// complete. The time is reset when the output's sink is replaced or the fanout's stats are
+    /// reset, and is gone along with the output once it's removed.
+    pub fn backpressure_durations(&self) -> Vec<(ComponentKey, Duration)> {
+        self.senders
+            .iter()
+            .map(|(key, output)| (key.clone(), output.backpressure))
+            .collect()
+    }
+
+    /// Gets whether each output is ready for another array, in the order the outputs were added.
+    ///
+    /// An output is ready if its buffer has room for an array of a single event right now, so that
+    /// sending to it wouldn't have to wait. Outputs whose buffer is unable to report its occupancy
+    /// are assumed to be ready, while outputs awaiting replacement or paused by
+    /// `ControlMessage::Pause` are never ready, since they hold up every send. Readiness is only
+    /// read from each buffer, so checking it never affects a send.
+    pub fn ready_status(&self) -> Vec<(ComponentKey, bool)> {
+        let probe = EventArray::Logs(Vec::new());
+        self.senders
+            .iter()
+            .map(|(key, output)| {
+                let ready = output.sender.is_some() && output.has_room_for(&probe);
+                (key.clone(), ready)
+            })
+            .collect()
+    }
+
+    /// Whether or not the fanout, as currently configured, can drop events.
+    ///
+    /// A fanout is lossy if any of its outputs are lossy. See `sink_is_lossy`.
+    pub fn is_lossy(&self) -> bool {
+        self.senders
+            .values()
+            .any(|output| self.output_is_lossy(output))
+    }
+
+    /// Whether or not the given output, as currently configured, can miss events sent to it, or
+    /// `None` if no such output exists.
+    ///
+    /// Every output is lossy if the fanout has a send timeout, since slow outputs are dropped along
+    /// with the events being sent to them, or if its error policy is
+    /// `FanoutErrorPolicy::DropErroredSink` or `FanoutErrorPolicy::Quarantine`, since an output
+    /// that fails then misses the arrays sent after it's removed or while it's quarantined. Other
+    /// error policies keep sending to an output that fails, or hold every send until the fanout is
+    /// unhalted. Otherwise, an output is lossy if it's paused or quarantined, since it misses any
+    /// events sent while it's paused or quarantined, if it's rate limited without blocking, or if
+    /// its buffer drops the newest arrays when full, since those arrays are dropped, or if it's in a
+    /// `TierPolicy::BestEffort` tier.
+    pub fn sink_is_lossy(&self, id: &ComponentKey) -> Option<bool> {
+        self.senders
+            .get(id)
+            .map(|output| self.output_is_lossy(output))
+    }
+
+    /// Whether or not the given output, as currently configured, can miss events sent to it.
+    fn output_is_lossy(&self, output: &Output) -> bool {
+        let best_effort = output.tier.and_then(|tier| self.tier_policies.get(&tier))
+            == Some(&TierPolicy::BestEffort);
+        let drops_failed = matches!(
+            self.error_policy,
+            FanoutErrorPolicy::DropErroredSink | FanoutErrorPolicy::Quarantine { .. }
+        );
+        self.send_timeout.is_some() || drops_failed || best_effort || output.is_lossy()
+    }
+
+    /// Gets the current weight of the given output, or `None` if no such output exists.
+    pub fn weight(&self, id: &ComponentKey) -> Option<u32> {
+        self.senders.get(id).map(|output| output.weight)
+    }
+
+    /// Gets the number of arrays waiting to be sent to the given output's replacement, or zero if
+    /// it isn't awaiting replacement.
+    ///
+    /// Sends wait for a replacement rather than buffering arrays for it, so the only arrays waiting
+    /// are those the output failed to accept before it was replaced, and is holding on to for a
+    /// retry under `TierPolicy::AtLeastOnce`. They're sent to the replacement ahead of the next
+    /// array.
+    pub fn spill_depth(&self, id: &ComponentKey) -> usize {
+        self.senders
+            .get(id)
+            .filter(|output| output.sender.is_none() && output.held.is_none())
+            .map_or(0, |output| output.retries.len())
+    }
+
+    /// Gets the key and priority of every output, ordered from highest to lowest priority.
+    ///
+    /// Outputs with the same priority are ordered by when they were added.
+    pub fn sinks_by_priority(&self) -> Vec<(ComponentKey, u8)> {
+        let mut sinks = self
+            .senders
+            .iter()
+            .map(|(key, output)| (key.clone(), output.priority))
+            .collect::<Vec<_>>();
+        // Sorting is stable, so insertion order is preserved for outputs with the same priority.
+        sinks.sort_by_key(|(_, priority)| Reverse(*priority));
+        sinks
+    }
+
+    /// Captures which outputs the fanout has, and which of them currently have a sink.
+    ///
+    /// Unlike `freeze_and_snapshot`, this leaves delivery as it is. Since no send can be in-flight
+    /// while the fanout is borrowed, the snapshot is of a consistent point, though control messages
+    /// that haven't been applied yet aren't reflected in it.
+    pub fn snapshot(&self) -> FanoutSnapshot {
+        FanoutSnapshot {
+            outputs: self
+                .senders
+                .iter()
+                .map(|(key, output)| (key.clone(), output.sender.is_some()))
+                .collect(),
+        }
+    }
+
+    /// Pauses all delivery, and captures everything needed to reconstruct the fanout's routing
+    /// elsewhere.
+    ///
+    /// Every output's sink is held, as with `ControlMessage::Pause`, so sends from then on wait until
+    /// each output is resumed with `ControlMessage::Resume`. Since no send can be in-flight while
+    /// the fanout is borrowed, the snapshot is of a consistent point, with nothing part-delivered.
+    /// Control messages that haven't been applied yet aren't reflected in it.
+    pub fn freeze_and_snapshot(&mut self) -> FrozenSnapshot {
+        let outputs = self
+            .senders
+            .iter_mut()
+            .map(|(key, output)| {
+                let state = output.state();
+                if let Some(sender) = output.sender.take() {
+                    output.held = Some(sender);
+                }
+                FrozenOutput {
+                    key: key.clone(),
+                    state,
+                    group: output.group.clone(),
+                    retiring: output.retiring,
+                    priority: output.priority,
+                    weight: output.weight,
+                    tier: output.tier,
+                    rate_limit: output
+                        .rate_limit
+                        .as_ref()
+                        .map(|limit| (limit.per_sec, limit.on_exceed)),
+                    flush_interval: output.flush_schedule.map(|(interval, _)| interval),
+                    buffered: output
+                        .snapshot
+                        .iter()
+                        .chain(&output.retries)
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect();
+        debug!(message = "Froze fanout.", outputs = self.senders.len());
+
+        FrozenSnapshot {
+            name: self.name.as_deref().map(String::from),
+            outputs,
+            shadows: self
+                .shadows
+                .iter()
+                .map(|(key, shadow)| (key.clone(), shadow.target.clone()))
+                .collect(),
+            encoded: self.encoded.keys().cloned().collect(),
+        }
+    }
+
+    /// Checks whether the given control messages would apply cleanly, in order, against this
+    /// instance, without actually applying them.
+    ///
+    /// # Errors
+    ///
+    /// If any of the control messages would violate an invariant about the state of the fanout at
+    /// the point it would be applied (e.g. adding a duplicate output, removing a non-existent output,
+    /// etc), a description of every such problem is returned.
+    #[allow(clippy::too_many_lines)]
+    pub fn validate_batch(
+        &self,
+        descriptors: &[ControlMessageDescriptor],
+    ) -> Result<(), Vec<String>> {
+        // Track which outputs would exist at each step, and whether or not they'd have a sink.
+        let mut outputs = self
+            .senders
+            .iter()
+            .map(|(key, output)| (key.clone(), output.sender.is_some()))
+            .collect::<HashMap<_, _>>();
+        let mut held = self
+            .senders
+            .iter()
+            .filter(|(_, output)| output.held.is_some())
+            .map(|(key, _)| key.clone())
+            .collect::<HashSet<_>>();
+        let mut shadows = self.shadows.keys().cloned().collect::<HashSet<_>>();
+        let mut overflow = self.overflow().map(|(key, _)| key.clone());
+        // Encoded outputs, including shared outputs, share the same keys but take no other control
+        // messages than `Remove`.
+        let mut encoded = self.encoded.keys().cloned().collect::<HashSet<_>>();
+
+        let mut problems = Vec::new();
+        for mut descriptor in flatten_batches(descriptors) {
+            for id in descriptor.added_keys_mut() {
+                map_added_key(self.key_mapper.as_ref(), id);
+            }
+
+            match &descriptor {
+                ControlMessageDescriptor::Add(id)
+                | ControlMessageDescriptor::AddToGroup(id, _)
+                | ControlMessageDescriptor::AddAsyncTransformed(id)
+                | ControlMessageDescriptor::AddWithAcceptCallback(id)
+                | ControlMessageDescriptor::AddWithSnapshot(id)
+                | ControlMessageDescriptor::AddWithTier(id, _)
+                | ControlMessageDescriptor::AddFiltered(id)
+                | ControlMessageDescriptor::AddForTypes(id, _)
+                | ControlMessageDescriptor::AddWithFactory(id) => {
+                    if outputs.contains_key(id) || shadows.contains(id) || encoded.contains(id) {
+                        problems.push(format!("Adding duplicate output id to fanout: {id}"));
+                    } else {
+                        outputs.insert(id.clone(), true);
+                    }
+                }
+                ControlMessageDescriptor::AddEncoded(id)
+                | ControlMessageDescriptor::AddShared(id) => {
+                    if outputs.contains_key(id) || shadows.contains(id) || encoded.contains(id) {
+                        problems.push(format!("Adding duplicate output id to fanout: {id}"));
+                    } else {
+                        encoded.insert(id.clone());
+                    }
+                }
+                ControlMessageDescriptor::AddMany(ids) => {
+                    let mut added = HashSet::new();
+                    let duplicates = ids
+                        .iter()
+                        .filter(|&id| {
+                            outputs.contains_key(id)
+                                || shadows.contains(id)
+                                || encoded.contains(id)
+                                || !added.insert(id)
+                        })
+                        .map(|id| format!("Adding duplicate output id to fanout: {id}"))
+                        .collect::<Vec<_>>();
+                    if duplicates.is_empty() {
+                        outputs.extend(ids.iter().map(|id| (id.clone(), true)));
+                    } else {
+                        problems.extend(duplicates);
+                    }
+                }
+                ControlMessageDescriptor::AddShadow(id, target) => {
+                    if outputs.contains_key(id) || shadows.contains(id) || encoded.contains(id) {
+                        problems.push(format!("Adding duplicate output id to fanout: {id}"));
+                    } else if !outputs.contains_key(target) {
+                        problems.push(format!("Shadowing unknown sink from fanout: {target}"));
+                    } else {
+                        shadows.insert(id.clone());
+                    }
+                }
+                ControlMessageDescriptor::SetOverflow(id) => {
+                    if let Some(overflow) = overflow.take() {
+                        shadows.remove(&overflow);
+                    }
+                    if outputs.contains_key(id) || shadows.contains(id) || encoded.contains(id) {
+                        problems.push(format!("Adding duplicate output id to fanout: {id}"));
+                    } else {
+                        shadows.insert(id.clone());
+                        overflow = Some(id.clone());
+                    }
+                }
+                ControlMessageDescriptor::Remove(id) => {
+                    held.remove(id);
+                    if !shadows.remove(id) && !encoded.remove(id) && outputs.remove(id).is_none() {
+                        problems.push(format!("Removing non-existent sink from fanout: {id}"));
+                    }
+                    if overflow.as_ref() == Some(id) {
+                        overflow = None;
+                    }
+                }
+                ControlMessageDescriptor::Replace(id, with_sink) => match outputs.get_mut(id) {
+                    Some(has_sink) if *has_sink == *with_sink => {
+                        problems.push(if *with_sink {
+                            format!("Replacing existing sink is not valid: {id}")
+                        } else {
+                            format!("Pausing non-existent sink is not valid: {id}")
+                        });
+                    }
+                    Some(has_sink) => *has_sink = *with_sink,
+                    None if *with_sink => {
+                        problems.push(format!("Replacing unknown sink from fanout: {id}"));
+                    }
+                    None => problems.push(format!("Pausing unknown sink from fanout: {id}")),
+                },
+                ControlMessageDescriptor::CancelReplace(id) => match outputs.get(id) {
+                    Some(false) => {
+                        outputs.remove(id);
+                        held.remove(id);
+                    }
+                    Some(true) => problems.push(format!(
+                        "Cancelling replace of sink not awaiting replacement: {id}"
+                    )),
+                    None => problems.push(format!(
+                        "Cancelling replace of unknown sink from fanout: {id}"
+                    )),
+                },
+                ControlMessageDescriptor::SetPriority(id, _) => {
+                    if !outputs.contains_key(id) {
+                        problems.push(format!(
+                            "Setting priority of unknown sink from fanout: {id}"
+                        ));
+                    }
+                }
+                ControlMessageDescriptor::SetSinkRateLimit(id, _) => {
+                    if !outputs.contains_key(id) {
+                        problems.push(format!("Rate limiting unknown sink from fanout: {id}"));
+                    }
+                }
+                ControlMessageDescriptor::Retire(id) => {
+                    if !outputs.contains_key(id) {
+                        problems.push(format!("Retiring unknown sink from fanout: {id}"));
+                    }
+                }
+                ControlMessageDescriptor::AdjustWeight(id, _) => {
+                    if !outputs.contains_key(id) {
+                        problems.push(format!(
+                            "Adjusting weight of unknown sink from fanout: {id}"
+                        ));
+                    }
+                }
+                ControlMessageDescriptor::Clear => {
+                    outputs.clear();
+                    held.clear();
+                    shadows.clear();
+                    encoded.clear();
+                    overflow = None;
+                }
+                ControlMessageDescriptor::Pause(id) => match outputs.get_mut(id) {
+                    Some(has_sink) if *has_sink => {
+                        *has_sink = false;
+                        held.insert(id.clone());
+                    }
+                    Some(_) => {
+                        problems.push(format!("Pausing non-existent sink is not valid: {id}"));
+                    }
+                    None => problems.push(format!("Pausing unknown sink from fanout: {id}")),
+                },
+                ControlMessageDescriptor::Resume(id) => {
+                    if held.remove(id) {
+                        outputs.insert(id.clone(), true);
+                    } else if outputs.contains_key(id) {
+                        problems.push(format!(
+                            "Resuming sink that isn't paused is not valid: {id}"
+                        ));
+                    } else {
+                        problems.push(format!("Resuming unknown sink from fanout: {id}"));
+                    }
+                }
+                ControlMessageDescriptor::SetFlushInterval(id, _) => {
+                    if !outputs.contains_key(id) {
+                        problems.push(format!(
+                            "Scheduling flushes of unknown sink from fanout: {id}"
+                        ));
+                    }
+                }
+                ControlMessageDescriptor::PauseGroup(_)
+                | ControlMessageDescriptor::ResumeGroup(_)
+                | ControlMessageDescriptor::Reorder(_)
+                | ControlMessageDescriptor::ReconfigureRouting
+                | ControlMessageDescriptor::Batch(_)
+                | ControlMessageDescriptor::Unhalt => {}
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Record the array being sent as dropped by each output for which `reason` gives a reason.
+    fn record_drops(&mut self, reason: impl Fn(&Output) -> Option<DropReason>) {
+        for (id, output) in &self.senders {
+            if let Some(reason) = reason(output) {
+                self.stats.record_drop(id, reason);
+            }
+        }
+    }
+
+    /// Gets the outputs the routing strategy may pick from for the given array, along with whether
+    /// each of them has room for it, and the weight of each.
+    fn routable_outputs(&self, events: &EventArray) -> (Vec<ComponentKey>, Vec<bool>, Vec<u32>) {
+        let outputs = self.senders.iter().filter(|(_, output)| {
+            !output.paused && !output.retiring && output.quarantined_until.is_none()
+        });
+        let sinks = outputs.clone().map(|(key, _)| key.clone()).collect();
+        let ready = outputs
+            .clone()
+            .map(|(_, output)| output.has_room_for(events))
+            .collect();
+        let weights = outputs.map(|(_, output)| output.weight).collect();
+        (sinks, ready, weights)
+    }
+
+    /// Stamps every event in the array with the next global sequence number, if enabled.
+    ///
+    /// Empty arrays, such as keepalives, have nothing to stamp and so don't use up a number.
+    fn stamp_sequence(&mut self, events: &mut EventArray) {
+        if self.global_sequence && !events.is_empty() {
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            events.for_each_event(|mut event| event.metadata_mut().set_sequence(Some(sequence)));
+        }
+    }
+
+    /// Appends the name of the fanout to the path of every event in the array, if enabled.
+    fn stamp_path(&self, events: &mut EventArray) {
+        if let (true, Some(name)) = (self.path_stamping, &self.name) {
+            events.for_each_event(|mut event| event.metadata_mut().push_path(Arc::clone(name)));
+        }
+    }
+
+    /// Gets the overflow output, if there is one.
+    fn overflow(&self) -> Option<(&ComponentKey, &Shadow)> {
+        self.shadows
+            .iter()
+            .find(|(_, shadow)| shadow.target.is_none())
+    }
+
+    /// Selects the outputs the given array is sent to, skipping any that already accepted the
+    /// array with the given dedup token, along with whether paused outputs miss the array.
+    ///
+    /// Paused outputs are never offered to the strategy, so they're judged to miss the array if the
+    /// strategy selected every output it was offered, as `Broadcast` does, unless the strategy only
+    /// ever picks a single output, such as `RoundRobin`, and had more than none to pick from.
+    fn select_outputs(
+        &mut self,
+        events: &EventArray,
+        token: Option<u64>,
+    ) -> (HashSet<ComponentKey>, bool) {
+        let (sinks, ready, weights) = self.routable_outputs(events);
+        let single_target = self
+            .strategy
+            .next_target(&sinks, &ready, &weights)
+            .is_some();
+
+        let selected = self
+            .strategy
+            .select_weighted(&sinks, &ready, &weights, events)
+            .into_iter()
+            .filter_map(|i| sinks.get(i).cloned())
+            .collect::<HashSet<_>>();
+        let paused_missed = !single_target && selected.len() == sinks.len();
+
+        let senders = &self.senders;
+        let selected = selected
+            .into_iter()
+            .filter(|key| {
+                token.map_or(true, |token| {
+                    senders
+                        .get(key)
+                        .map_or(true, |output| !output.delivered.contains(&token))
+                })
+            })
+            .collect();
+        (selected, paused_missed)
+    }
+
+    /// Removes the selected outputs whose filter rejects the given array, recording the array as
+    /// dropped for each of them.
+    fn skip_filtered(
+        &mut self,
+        selected: &mut HashSet<ComponentKey>,
+        events: &EventArray,
+        record_stats: bool,
+    ) {
+        let senders = &self.senders;
+        let stats = &mut self.stats;
+        selected.retain(|id| {
+            let accepted = senders
+                .get(id)
+                .map_or(true, |output| output.accepts(events));
+            if !accepted && record_stats {
+                stats.record_drop(id, DropReason::Filtered);
+            }
+            accepted
+        });
+    }
+
+    /// Remembers that the given outputs accepted the array with the given dedup token, forgetting
+    /// the oldest token of each output beyond the exactly-once window.
+    fn record_delivered(&mut self, token: u64, delivered: Vec<ComponentKey>) {
+        if self.exactly_once_window == 0 {
+            return;
+        }
+
+        for id in delivered {
+            if let Some(output) = self.senders.get_mut(&id) {
+                output.delivered.push_back(token);
+                if output.delivered.len() > self.exactly_once_window {
+                    output.delivered.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Gets the output the next array would be sent to, when the routing strategy sends each array
+    /// to a single output, such as `RoundRobin` or `Weighted`.
+    ///
+    /// Paused outputs are skipped, as they are when sending, and outputs are judged ready as if the
+    /// next array held a single event. Returns `None` if the strategy doesn't pick a single output,
+    /// such as `Broadcast`, or if there are no outputs to pick from.
+    pub fn current_rr_target(&self) -> Option<ComponentKey> {
+        let (sinks, ready, weights) = self.routable_outputs(&EventArray::Logs(Vec::new()));
+        self.strategy
+            .next_target(&sinks, &ready, &weights)
+            .and_then(|i| sinks.get(i).cloned())
+    }
+
+    /// Apply a control message directly against this instance.
+    ///
+    /// This method should not be used if there is an active `SendGroup` being processed.
+    fn apply_control_message(&mut self, mut message: ControlMessage) {
+        trace!("Processing control message outside of send: {:?}", message);
+
+        for id in message.added_keys_mut() {
+            map_added_key(self.key_mapper.as_ref(), id);
+        }
+        self.apply_mapped_control_message(message);
+    }
+
+    /// Apply a control message, whose added keys have already been mapped, directly against this
+    /// instance.
+    fn apply_mapped_control_message(&mut self, message: ControlMessage) {
+        match message {
+            ControlMessage::Add(id, sink) => self.add_to_group(id, sink, None),
+            ControlMessage::Remove(id) => self.remove(&id),
+            ControlMessage::Replace(id, None) => self.pause(&id),
+            ControlMessage::Replace(id, Some(sink)) => self.replace(&id, sink),
+            ControlMessage::CancelReplace(id) => self.cancel_replace(&id),
+            ControlMessage::AddToGroup(id, sink, group) => self.add_to_group(id, sink, Some(group)),
+            ControlMessage::PauseGroup(group) => {
+                set_group_paused(&mut self.senders, &group, true);
+            }
+            ControlMessage::ResumeGroup(group) => {
+                set_group_paused(&mut self.senders, &group, false);
+            }
+            ControlMessage::SetPriority(id, priority) => self.set_priority(&id, priority),
+            ControlMessage::AdjustWeight(id, delta) => self.adjust_weight(&id, delta),
+            ControlMessage::Reorder(ids) => reorder(&mut self.senders, &ids),
+            ControlMessage::AddShadow(id, sink, target) => self.add_shadow(id, sink, target),
+            ControlMessage::AddAsyncTransformed(id, sink, transform) => {
+                self.add_output(id, Output::with_transform(sink, transform));
+            }
+            ControlMessage::AddWithAcceptCallback(id, sink, on_accept) => {
+                self.add_output(id, Output::with_accept_callback(sink, on_accept));
+            }
+            ControlMessage::AddWithSnapshot(id, sink, snapshot) => {
+                self.add_output(id, Output::with_snapshot(sink, snapshot));
+            }
+            ControlMessage::AddWithTier(id, sink, tier) => {
+                self.add_output(id, Output::with_tier(sink, tier));
+            }
+            ControlMessage::AddFiltered(id, sink, filter) => {
+                self.add_output(id, Output::with_filter(sink, filter));
+            }
+            ControlMessage::AddForTypes(id, sink, types) => {
+                self.add_output(id, Output::with_filter(sink, type_filter(types)));
+            }
+            ControlMessage::AddWithFactory(id, factory) => {
+                self.add_output(id, Output::with_factory(factory));
+            }
+            ControlMessage::SetOverflow(id, sink) => {
+                set_overflow(
+                    &self.senders,
+                    &mut self.shadows,
+                    &self.encoded,
+                    id,
+                    sink,
+                    self.panic_free,
+                );
+            }
+            ControlMessage::ReconfigureRouting(config) => {
+                self.strategy = reconfigure_routing(&mut self.senders, config);
+            }
+            ControlMessage::AddMany(outputs) => {
+                add_many(
+                    &mut self.senders,
+                    &self.shadows,
+                    &self.encoded,
+                    outputs,
+                    self.panic_free,
+                );
+            }
+            ControlMessage::SetSinkRateLimit(id, per_sec, on_exceed) => {
+                set_rate_limit(&mut self.senders, &id, per_sec, on_exceed, self.panic_free);
+            }
+            ControlMessage::Retire(id) => retire(&mut self.senders, &id, self.panic_free),
+            ControlMessage::AddEncoded(id, sink, encoder) => {
+                add_encoded(
+                    &self.senders,
+                    &self.shadows,
+                    &mut self.encoded,
+                    id,
+                    EncodedOutput::bytes(sink, encoder),
+                    self.panic_free,
+                );
+            }
+            ControlMessage::AddShared(id, sink) => {
+                add_encoded(
+                    &self.senders,
+                    &self.shadows,
+                    &mut self.encoded,
+                    id,
+                    EncodedOutput::shared(sink),
+                    self.panic_free,
+                );
+            }
+            ControlMessage::Clear => {
+                self.senders.clear();
+                self.shadows.clear();
+                self.encoded.clear();
+                self.strategy.reset();
+            }
+            ControlMessage::Batch(messages) => {
+                for message in messages {
+                    self.apply_mapped_control_message(message);
+                }
+            }
+            ControlMessage::Acked(message, ack) => {
+                self.apply_mapped_control_message(*message);
+                let _ = ack.send(());
+            }
+            ControlMessage::Pause(id) => hold(&mut self.senders, &id, self.panic_free),
+            ControlMessage::Resume(id) => resume(&mut self.senders, &id, self.panic_free),
+            ControlMessage::SetFlushInterval(id, interval) => {
+                set_flush_interval(&mut self.senders, &id, interval, self.panic_free);
+            }
+            ControlMessage::Unhalt => self.halted = false,
+        }
+    }
+
+    /// Handles an output failing to accept the events sent to it.
+    ///
+    /// The events it failed to accept are dropped, and the output is kept or removed as per the
+    /// error policy. Since an output that fails once will often keep failing, errors are logged at
+    /// most once per the configured suppression interval for any given output.
+    ///
+    /// An output that gets removed because of the error is always logged, and counted by the
+    /// `fanout_outputs_removed_total` metric.
+    ///
+    /// The cause of the failure is logged too, when the output reports one.
+    fn handle_sink_error(
+        &mut self,
+        id: &ComponentKey,
+        phase: &'static str,
+        cause: Option<&crate::Error>,
+    ) {
+        if let Some(output) = self.senders.get_mut(id) {
+            output.errors += 1;
+        }
+
+        if self.error_policy == FanoutErrorPolicy::DropEventContinue
+            && self.single_sink_error_policy == SingleSinkErrorPolicy::DropToEmpty
+            && self.senders.len() == 1
+            && self.senders.contains_key(id)
+        {
+            self.senders.clear();
+            warn!(
+                message = "Sole fanout output failed to accept events, removing it.",
+                output = %id,
+                phase,
+                index = 0,
+                remaining = self.senders.len(),
+            );
+            counter!(
+                "fanout_outputs_removed_total", 1,
+                "output" => id.to_string(),
+                "phase" => phase,
+            );
+        }
+
+        if self.error_policy == FanoutErrorPolicy::DropEventContinue
+            && self.single_sink_error_policy == SingleSinkErrorPolicy::PropagateIfLast
+            && self.senders.len() == 1
+            && self.senders.contains_key(id)
+            && !self.halted
+        {
+            error!(
+                message = "Sole fanout output failed to accept events, stopping.",
+                output = %id,
+                phase,
+            );
+            self.halted = true;
+        }
+
+        if self.error_policy == FanoutErrorPolicy::DropErroredSink {
+            let removed = match self.senders.get_index_of(id) {
+                Some(index) => self.senders.shift_remove_index(index).map(|_| index),
+                None => self
+                    .encoded
+                    .shift_remove_full(id)
+                    .map(|(index, _, _)| index),
+            };
+            if let Some(index) = removed {
+                warn!(
+                    message = "Fanout output failed to accept events, removing it.",
+                    output = %id,
+                    phase,
+                    index,
+                    remaining = self.senders.len() + self.encoded.len(),
+                );
+                counter!(
+                    "fanout_outputs_removed_total", 1,
+                    "output" => id.to_string(),
+                    "phase" => phase,
+                );
+            }
+        }
+
+        if let FanoutErrorPolicy::Quarantine {
+            initial_backoff,
+            max_backoff,
+        } = self.error_policy
+        {
+            if let Some(output) = self.senders.get_mut(id) {
+                output.failures += 1;
+                let backoff = 2_u32
+                    .checked_pow(output.failures - 1)
+                    .and_then(|factor| initial_backoff.checked_mul(factor))
+                    .map_or(max_backoff, |backoff| cmp::min(backoff, max_backoff));
+                output.quarantined_until = Some(Instant::now() + backoff);
+                warn!(
+                    message = "Fanout output failed to accept events, quarantining it.",
+                    output = %id,
+                    ?backoff,
+                );
+            }
+        }
+
+        if let Some(suppressed) = self.error_log_limiter.record(id) {
+            if let Some(cause) = cause {
+                error!(
+                    message = "Fanout output failed to accept events. Events dropped.",
+                    output = %id,
+                    error = %cause,
+                    suppressed,
+                );
+            } else {
+                error!(
+                    message = "Fanout output failed to accept events. Events dropped.",
+                    output = %id,
+                    suppressed,
+                );
+            }
+        }
+    }
+
+    /// Handles the control channel having been closed, based on the configured behavior.
+    fn handle_control_closed(&mut self) {
+        if self.control_closed_behavior == ControlClosedBehavior::FailClosed
+            && !self.senders.is_empty()
+        {
+            debug!("Control channel closed, closing all outputs of fanout.");
+
+            // Dropping the senders closes them.
+            self.senders.clear();
+            self.encoded.clear();
+        }
+    }
+
+    /// If any sink is awaiting replacement (i.e. it was temporarily replaced with `None`), read
+    /// and process messages from the control channel until that is no longer true.
+    ///
+    /// Waiting on the control channel registers our waker with it, so the task is woken as soon as
+    /// the next control message arrives, regardless of how many messages it takes until the last
+    /// replacement is made.
+    async fn wait_for_replacements(&mut self) {
+        // Besides any replacements, we also wait for enough outputs to be ready, which can only
+        // change by way of control messages, by quarantined outputs being reinstated, or by full
+        // buffers being read from.
+        //
+        // Like in `send_array_reporting`, we stop polling the control channel once it's closed, as
+        // it would otherwise keep returning straight away, starving the other branches.
+        let mut control_channel_open = true;
+        while self.senders.values().any(|output| output.sender.is_none())
+            || self.ready_sink_count() < self.min_ready_sinks
+        {
+            let reinstatement = self.next_reinstatement();
+            let waiting_for_room = self.ready_sink_count() < self.min_ready_sinks;
+            let maybe_msg = tokio::select! {
+                biased;
+
+                maybe_msg = self.control_channel.recv(), if control_channel_open => maybe_msg,
+                () = sleep_until_due(reinstatement) => {
+                    self.reinstate_quarantined();
+                    continue;
+                }
+                () = wait_for_room(&self.senders), if waiting_for_room => continue,
+            };
+            if let Some(msg) = maybe_msg {
+                self.apply_control_message(msg);
+            } else {
+                // If we're failing closed, the outputs are all gone, and there's nothing left to
+                // wait for.
+                control_channel_open = false;
+                self.handle_control_closed();
+                if self.senders.is_empty() {
+                    return;
+                }
+
+                // If the control channel is closed, a replacement can never arrive, so there's
+                // nothing else we can do. Outputs can still become ready without control messages,
+                // though, by being reinstated or having their buffer read from, so we keep waiting
+                // on those.
+                if self.senders.values().any(|output| output.sender.is_none()) {
+                    future::pending::<()>().await;
+                }
+            }
+        }
+    }
+
+    /// Applies control messages as they arrive until the fanout is unhalted.
+    ///
+    /// If the control channel is closed, the fanout can never be unhalted, so rather than holding
+    /// events forever, which would keep the task sending them from ever finishing, this returns
+    /// straight away. Failing closed leaves no outputs to send to, while failing open lifts the halt
+    /// and keeps sending to the current outputs.
+    async fn wait_until_unhalted(&mut self) {
+        while self.halted {
+            if let Some(msg) = self.control_channel.recv().await {
+                self.apply_control_message(msg);
+            } else {
+                // If we're failing closed, the outputs are all gone, and there's nothing left to
+                // wait for.
+                self.handle_control_closed();
+                if !self.senders.is_empty() {
+                    warn!("Control channel closed while fanout halted, resuming sending.");
+                    self.halted = false;
+                }
+                return;
+            }
+        }
+    }
+
+    /// The number of outputs that are present, not paused, retiring or quarantined, not awaiting
+    /// replacement, and ready as per `ready_status`.
+    fn ready_sink_count(&self) -> usize {
+        let probe = EventArray::Logs(Vec::new());
+        self.senders
+            .values()
+            .filter(|output| output.is_available() && output.has_room_for(&probe))
+            .count()
+    }
+
+    /// Reinstates every quarantined output whose backoff has elapsed, recreating its sink if it
+    /// has a factory.
+    fn reinstate_quarantined(&mut self) {
+        let now = Instant::now();
+        for (id, output) in &mut self.senders {
+            if output.quarantined_until.map_or(false, |until| until <= now) {
+                output.quarantined_until = None;
+                if let (Some(factory), Some(sender)) =
+                    (output.factory.as_mut(), output.sender.as_mut())
+                {
+                    *sender = Sender::new(factory());
+                }
+                debug!(message = "Reinstating quarantined fanout output.", output = %id);
+            }
+        }
+    }
+
+    /// Gets when the next quarantined output is due to be reinstated, if any output is quarantined.
+    fn next_reinstatement(&self) -> Option<Instant> {
+        self.senders
+            .values()
+            .filter_map(|output| output.quarantined_until)
+            .min()
+    }
+
+    /// Removes every retiring output whose buffer has drained.
+    fn remove_retired(&mut self) {
+        self.senders.retain(|id, output| {
+            let retired = output.is_retired();
+            if retired {
+                debug!(message = "Removing retired output from fanout.", output = %id);
+            }
+            !retired
+        });
+    }
+
+    /// Gets when the next scheduled flush of an output is due, if any output with a sink has one.
+    fn next_flush_due(&self) -> Option<Instant> {
+        self.senders
+            .values()
+            .filter(|output| output.sender.is_some())
+            .filter_map(|output| output.flush_schedule.map(|(_, due)| due))
+            .min()
+    }
+
+    /// Flushes the buffer of every output whose scheduled flush is due, and schedules its next one.
+    async fn flush_scheduled(&mut self) {
+        let now = Instant::now();
+        let mut errored = Vec::new();
+        for (id, output) in &mut self.senders {
+            if let (Some(sender), Some((interval, due))) =
+                (output.sender.as_mut(), output.flush_schedule.as_mut())
+            {
+                if *due <= now {
+                    *due = now + *interval;
+                    trace!(message = "Flushing fanout output.", output = %id);
+                    if sender.inner.flush().await.is_err() {
+                        errored.push(id.clone());
+                    }
+                }
+            }
+        }
+        for id in &errored {
+            self.handle_sink_error(id, "flush", None);
+        }
+    }
+
+    pub async fn send_stream(&mut self, events: impl Stream<Item = EventArray>) {
+        tokio::pin!(events);
+        let mut keepalive = self
+            .keepalive_interval
+            .map(|interval| Instant::now() + interval);
+        loop {
+            // Nothing more is taken from the stream while halted, so no array is taken only to be
+            // held up.
+            if self.halted {
+                error!("Fanout halted by an earlier failure, waiting until it's unhalted.");
+                self.wait_until_unhalted().await;
+            }
+            let flush_due = self.next_flush_due();
+            tokio::select! {
+                maybe_event_array = events.next() => match maybe_event_array {
+                    Some(event_array) => {
+                        self.send_array(event_array, true, None, None).await;
+                    }
+                    None => break,
+                },
+                () = sleep_until_due(keepalive) => {
+                    trace!("Sending keepalive to fanout.");
+                    self.send_array(EventArray::Logs(Vec::new()), false, None, None).await;
+                }
+                () = sleep_until_due(flush_due) => {
+                    // Scheduled flushes don't send anything, so they leave the keepalive as it was.
+                    self.flush_scheduled().await;
+                    continue;
+                }
+            }
+            keepalive = self
+                .keepalive_interval
+                .map(|interval| Instant::now() + interval);
+        }
+    }
+
+    /// Send a batch of events to all connected sinks.
+    ///
+    /// This will block on the resolution of any pending reload before proceeding with the send
+    /// operation.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic if the fanout receives a control message that violates some invariant
+    /// about its current state (e.g. add a duplicate sink, etc), unless the fanout is panic-free.
+    /// This would imply a bug in Vector's config reloading logic. See `with_panic_free`.
+    pub async fn send(&mut self, events: EventArray) {
+        self.send_array(events, true, None, None).await;
+    }
+
+    /// Send a batch of events to all connected sinks, failing if any of them fails to accept it.
+    ///
+    /// Where `send` only logs a failed output, dropping the events for it, this reports the failure
+    /// to the caller however many outputs there are, so that a pipeline which can't tolerate
+    /// partial delivery can stop. The events are still sent to every other output, and failed
+    /// outputs are otherwise handled the same as by `send`.
+    ///
+    /// # Errors
+    ///
+    /// If any output fails to accept the events, whether the events themselves, their encoded form,
+    /// or the snapshot sent ahead of them, `FanoutError::SendFailed` is returned with every output
+    /// that failed. If the fanout is halted, `FanoutError::Halted` is returned straight away,
+    /// without sending the events to any output. See `FanoutErrorPolicy::FailFast`.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic if the fanout receives a control message that violates some invariant
+    /// about its current state (e.g. add a duplicate sink, etc), unless the fanout is panic-free.
+    /// This would imply a bug in Vector's config reloading logic. See `with_panic_free`.
+    pub async fn try_send(&mut self, events: EventArray) -> Result<(), FanoutError> {
+        if !self.control_on_flush {
+            self.apply_pending_control_messages();
+        }
+        if self.halted {
+            return Err(FanoutError::Halted);
+        }
+        let errored = self.send_array(events, true, None, None).await;
+        if errored.is_empty() {
+            Ok(())
+        } else {
+            Err(FanoutError::SendFailed { ids: errored })
+        }
+    }
+
+    /// Send a batch of events to all connected sinks, waiting until every sink is done with them.
+    ///
+    /// The events are attached to a new batch, which is only finalized once every copy of them
+    /// sent to an output has been dropped, such as by the sink reading from that output once it
+    /// has processed them. An output that never reads the events holds this up indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// If any output fails to accept the events, the batch is marked as errored, and
+    /// `FanoutError::SendFailed` is returned with every output that failed, without waiting on the
+    /// rest. If no output accepts the events at all, such as when there are no outputs, the batch
+    /// is likewise marked as errored. Otherwise, if any sink reports that it failed to deliver the
+    /// events, `FanoutError::NotDelivered` is returned with the status of the batch.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic if the fanout receives a control message that violates some invariant
+    /// about its current state (e.g. add a duplicate sink, etc), unless the fanout is panic-free.
+    /// This would imply a bug in Vector's config reloading logic. See `with_panic_free`.
+    pub async fn send_confirmed(&mut self, mut events: EventArray) -> Result<(), FanoutError> {
+        let (batch, receiver) = BatchNotifier::new_with_receiver();
+        events.for_each_event(|mut event| {
+            event
+                .metadata_mut()
+                .add_finalizer(EventFinalizer::new(Arc::clone(&batch)));
+        });
+        // Only the events themselves, and a finalizer of our own through which the outcome of the
+        // send is recorded, may hold on to the batch, or it would never be finalized.
+        let outcome = EventFinalizer::new(Arc::clone(&batch));
+        drop(batch);
+
+        let report = self.send_array_reporting(events, true, None, None).await;
+        if !report.errored.is_empty() {
+            outcome.update_status(EventStatus::Errored);
+            return Err(FanoutError::SendFailed {
+                ids: report.errored,
+            });
+        }
+        if report.accepted == 0 {
+            outcome.update_status(EventStatus::Errored);
+        }
+        drop(outcome);
+
+        match receiver.await {
+            BatchStatus::Delivered => Ok(()),
+            status => Err(FanoutError::NotDelivered { status }),
+        }
+    }
+
+    /// Send a batch of events to all connected sinks, to be delivered by the given deadline.
+    ///
+    /// Every event is stamped with the deadline, so that sinks can act on events that are close to,
+    /// or past, their deadline. If the deadline has already passed by the time the events would be
+    /// sent, including after waiting on any pending reload, they are dropped instead.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic under the same conditions as `send`.
+    pub async fn send_with_deadline(&mut self, mut events: EventArray, deadline: Instant) {
+        events.for_each_event(|mut event| event.metadata_mut().set_deadline(Some(deadline)));
+        self.send_array(events, true, Some(deadline), None).await;
+    }
+
+    /// Send a batch of events to all connected sinks, identified by the given dedup token.
+    ///
+    /// When exactly-once delivery is enabled, outputs that have already accepted an array with the
+    /// same token are skipped, so that retrying a send only delivers the array to the outputs that
+    /// didn't accept it the first time around. Tokens must be unique to each array, but the same
+    /// token must be given when retrying the send of an array.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic under the same conditions as `send`.
+    pub async fn send_with_token(&mut self, events: EventArray, token: u64) {
+        self.send_array(events, true, None, Some(token)).await;
+    }
+
+    /// Send several batches of events to all connected sinks, in order.
+    ///
+    /// Each array is sent in turn, exactly as if by `send`, so every output receives them in the
+    /// order given.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic under the same conditions as `send`.
+    pub async fn send_all_arrays(&mut self, arrays: Vec<EventArray>) {
+        for events in arrays {
+            self.send_array(events, true, None, None).await;
+        }
+    }
+
+    /// Waits until every control message sent so far has been applied.
+    ///
+    /// Unlike a send, this never waits on outputs or replacements. Once every waiting control
+    /// message has been applied, it yields to the runtime, so that any other tasks still sending
+    /// control messages get to run, and only returns once no more have arrived in the meantime.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic under the same conditions as `send`.
+    pub async fn control_quiescent(&mut self) {
+        loop {
+            self.apply_pending_control_messages();
+            tokio::task::yield_now().await;
+            if self.control_channel.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Marks a flush boundary, applying any control messages that are waiting.
+    ///
+    /// This is only needed when control messages are deferred until a flush, since otherwise they're
+    /// applied as soon as possible anyway.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic under the same conditions as `send`.
+    pub fn flush(&mut self) {
+        self.apply_pending_control_messages();
+    }
+
+    /// Flushes the buffer of every output, such as at a checkpoint boundary.
+    ///
+    /// Unlike `flush`, this hands off whatever each output's buffer is holding on to, without
+    /// applying control messages or sending anything new. Outputs paused by `ControlMessage::Pause`
+    /// are flushed too, while outputs awaiting replacement have no sink to flush. Arrays held for a
+    /// retry aren't in any buffer yet, so they're left for the next send. An output that fails is
+    /// handled as for a failed send, and the rest are flushed regardless.
+    ///
+    /// # Errors
+    ///
+    /// If any output failed to flush, an error is returned.
+    pub async fn flush_all(&mut self) -> Result<(), ()> {
+        let mut errored = Vec::new();
+        for (id, output) in &mut self.senders {
+            if let Some(sender) = output.sender.as_mut().or(output.held.as_mut()) {
+                trace!(message = "Flushing fanout output.", output = %id);
+                if sender.inner.flush().await.is_err() {
+                    errored.push(id.clone());
+                }
+            }
+        }
+        for id in &errored {
+            self.handle_sink_error(id, "flush", None);
+        }
+        if errored.is_empty() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Drains the fanout for shutdown, flushing every output before closing them all.
+    ///
+    /// Any control messages already waiting are applied first. Then, one output at a time, any
+    /// arrays the output is holding on to for a retry are sent to it, and its buffer is flushed.
+    /// Outputs paused by `ControlMessage::Pause` are drained too, while outputs awaiting
+    /// replacement have no sink to drain. An output that fails is handled as for a failed send, and
+    /// the rest are drained regardless. Finally, the fanout is dropped, which closes every sink.
+    ///
+    /// If a timeout is given, or otherwise set with `with_close_timeout`, and elapses before every
+    /// output has been drained, the remaining outputs are dropped without waiting on them any
+    /// further, with each of them logged so that it's known the drain wasn't fully graceful. The
+    /// timeout elapsing isn't an error in itself.
+    ///
+    /// # Errors
+    ///
+    /// If any output failed to drain, an error is returned, since not every array can be assumed to
+    /// have been handed off.
+    ///
+    /// # Panics
+    ///
+    /// This method can panic under the same conditions as `send`.
+    pub async fn drain(mut self, timeout: Option<Duration>) -> Result<(), ()> {
+        self.apply_pending_control_messages();
+
+        let mut drained = 0;
+        let mut errored = Vec::new();
+        let drain = drain_outputs(&mut self.senders, &mut drained, &mut errored);
+        let timed_out = if let Some(timeout) = timeout.or(self.close_timeout) {
+            time::timeout(timeout, drain).await.is_err()
+        } else {
+            drain.await;
+            false
+        };
+
+        // The outputs still to be drained are found before handling errors, since that can remove
+        // the outputs already drained.
+        if timed_out {
+            for id in self.senders.keys().skip(drained) {
+                warn!(
+                    message = "Timed out draining fanout output, dropping it.",
+                    output = %id,
+                );
+            }
+        }
+        for id in &errored {
+            self.handle_sink_error(id, "drain", None);
+        }
+        if errored.is_empty() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Applies every control message that's already waiting, without waiting for any more.
+    fn apply_pending_control_messages(&mut self) {
+        loop {
+            match self.control_channel.try_recv() {
+                Ok(message) => self.apply_control_message(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.handle_control_closed();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Checks whether the given deadline, if any, has passed, recording the array as dropped for
+    /// every output if so.
+    fn deadline_passed(&mut self, deadline: Option<Instant>, record_stats: bool) -> bool {
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                debug!("Deadline passed before events could be sent, dropping them.");
+                if record_stats {
+                    self.record_drops(|output| {
+                        Some(if output.paused {
+                            DropReason::Paused
+                        } else {
+                            DropReason::Expired
+                        })
+                    });
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Sends the array held back from a send to the overflow output, if any other output missed
+    /// it, and to the dead-letter sink, if any other output failed to accept it.
+    ///
+    /// Neither send is awaited, though the array stays in-flight until the send to the dead-letter
+    /// sink completes.
+    fn send_fallback(
+        &mut self,
+        events: EventArray,
+        overflow_needed: bool,
+        errored: &[ComponentKey],
+        inflight: Option<Arc<OwnedSemaphorePermit>>,
+    ) {
+        let overflow = self
+            .shadows
+            .values()
+            .find(|shadow| shadow.target.is_none())
+            .filter(|_| overflow_needed);
+        let dead_letter = self.dead_letter.as_ref().filter(|_| !errored.is_empty());
+        let (overflow_events, dead_letter_events) = match (&overflow, &dead_letter) {
+            (Some(_), Some(_)) => (Some(events.clone()), Some(events)),
+            (Some(_), None) => (Some(events), None),
+            (None, Some(_)) => (None, Some(events)),
+            (None, None) => (None, None),
+        };
+        if let (Some(overflow), Some(events)) = (overflow, overflow_events) {
+            overflow.send(events);
+        }
+        if let (Some(dead_letter), Some(events)) = (dead_letter, dead_letter_events) {
+            let dead_letter = Arc::clone(dead_letter);
+            let errored = errored.to_vec();
+            let send_timeout = self.send_timeout;
+            tokio::spawn(async move {
+                // Locking the sink for the whole send keeps arrays in the order they were sent in.
+                let send = async { dead_letter.lock().await.send(events).await };
+                let result = match send_timeout {
+                    Some(send_timeout) => {
+                        time::timeout(send_timeout, send).await.unwrap_or(Err(()))
+                    }
+                    None => send.await,
+                };
+                if result.is_err() {
+                    error!(
+                        message = "Dead-letter sink failed to accept events. Events dropped.",
+                        outputs = ?errored,
+                    );
+                }
+                drop(inflight);
+            });
+        }
+    }
+
+    /// Sends the given array, returning the outputs which failed to accept it.
+    async fn send_array(
+        &mut self,
+        events: EventArray,
+        record_stats: bool,
+        deadline: Option<Instant>,
+        token: Option<u64>,
+    ) -> Vec<ComponentKey> {
+        self.send_array_reporting(events, record_stats, deadline, token)
+            .await
+            .errored
+    }
+
+    /// Sends the given array, reporting which outputs failed to accept it, and how many accepted
+    /// it.
+    #[allow(clippy::too_many_lines)]
+    async fn send_array_reporting(
+        &mut self,
+        mut events: EventArray,
+        record_stats: bool,
+        deadline: Option<Instant>,
+        token: Option<u64>,
+    ) -> SendReport {
+        // First, process any available control messages in a non-blocking fashion, unless they're
+        // deferred until the next flush.  If any of our senders were replaced, we additionally wait
+        // until they're replaced.
+        if !self.control_on_flush {
+            self.apply_pending_control_messages();
+        }
+        if self.halted {
+            error!("Fanout halted by an earlier failure, holding events until it's unhalted.");
+            self.wait_until_unhalted().await;
+        }
+        self.reinstate_quarantined();
+        self.remove_retired();
+        self.wait_for_replacements().await;
+
+        // Nothing to send if we have no sender.
+        if self.senders.is_empty() {
+            trace!("No senders present.");
+            return SendReport::default();
+        }
+
+        if self.deadline_passed(deadline, record_stats) {
+            return SendReport::default();
+        }
+        let inflight = acquire_inflight(self.inflight.clone()).await;
+
+        if record_stats {
+            self.stats.by_type.increment(&events);
+            self.throughput.record(events.len());
+        }
+
+        // Keep track of whether the control channel has returned `Ready(None)`, and stop polling
+        // it once it has. If we don't do this check, it will continue to return `Ready(None)` any
+        // time it is polled, which can lead to a busy loop below.
+        //
+        // In real life this is likely a non-issue, but it can lead to strange behavior in tests if
+        // left unhandled.
+        let mut control_channel_open = true;
+        let apply_control_messages = !self.control_on_flush;
+
+        // Create our send group which arms all senders to send the given events, and handles
+        // adding/removing/replacing senders while the send is in-flight.
+        self.stamp_sequence(&mut events);
+        self.stamp_path(&mut events);
+        let (mut selected, paused_missed) = self.select_outputs(&events, token);
+        // Paused outputs only miss the array if it would have been sent to them otherwise, since
+        // strategies that pick one output for each array simply pick from the rest.
+        let any_paused = paused_missed && self.senders.values().any(|output| output.paused);
+        if record_stats && any_paused {
+            self.record_drops(|output| output.paused.then(|| DropReason::Paused));
+        }
+        self.skip_filtered(&mut selected, &events, record_stats);
+        if record_stats {
+            self.stats
+                .routed_by_type
+                .add(&events, selected.len() as u64);
+        }
+        // The overflow and dead-letter outputs are only sent the array when other outputs miss it,
+        // so a single copy is held back for both of them, and is only copied again if both need it.
+        let fallback_events =
+            (self.overflow().is_some() || self.dead_letter.is_some()).then(|| events.clone());
+        let encoded_sends = encode_array(&self.encoded, &events, self.cow_threshold);
+        let mut send_group = SendGroup::new(
+            &mut self.senders,
+            &mut self.shadows,
+            &mut self.encoded,
+            &selected,
+            events,
+            encoded_sends,
+            self.clone_timing,
+            self.panic_free,
+            self.sink_ready_observer.as_ref(),
+            self.latency_alert.as_ref(),
+            self.cooperative_chunk,
+            self.poll_start,
+            &self.tier_policies,
+            inflight.clone(),
+        );
+        self.poll_start = self.poll_start.wrapping_add(1);
+
+        // If we have a send timeout, any sends still in-flight once it elapses are abandoned.
+        let deadline = self.send_timeout.map(|timeout| Instant::now() + timeout);
+        let timeout = sleep_until_due(deadline);
+        tokio::pin!(timeout);
+
+        loop {
+            tokio::select! {
+                // Semantically, it's not hugely important that this select is biased. It does,
+                // however, make testing simpler when you can count on control messages being
+                // processed first.
+                biased;
+
+                maybe_msg = self.control_channel.recv(), if control_channel_open && apply_control_messages => {
+                    trace!("Processing control message inside of send: {:?}", maybe_msg);
+
+                    // During a send operation, control messages must be applied via the
+                    // `SendGroup`, since it has exclusive access to the senders.
+                    match maybe_msg {
+                        Some(mut message) => {
+                            for id in message.added_keys_mut() {
+                                map_added_key(self.key_mapper.as_ref(), id);
+                            }
+                            send_group.apply_control_message(message);
+                        }
+                        None => {
+                            // Control channel is closed, process must be shutting down
+                            control_channel_open = false;
+                        }
+                    }
+                }
+
+                () = send_group.send() => {
+                    // All in-flight sends have completed, so return sinks to the base collection.
+                    // We extend instead of assign here because other sinks could have been added
+                    // while the send was in-flight.
+                    trace!("Sent item to fanout.");
+                    break;
+                }
+
+                () = &mut timeout => {
+                    let dropped = send_group.drop_pending_sends();
+                    warn!(
+                        message = "Fanout send timed out, dropping slow outputs.",
+                        outputs = ?dropped,
+                    );
+                    break;
+                }
+            }
+        }
+
+        let errored = mem::take(&mut send_group.errored);
+        let buffer_full = mem::take(&mut send_group.buffer_full);
+        let delivered = mem::take(&mut send_group.delivered);
+        let rate_limited = mem::take(&mut send_group.rate_limited);
+        let encoded_errored = mem::take(&mut send_group.encoded_errored);
+        let accepted = delivered.len() + send_group.encoded_delivered;
+        if let Some(strategy) = send_group.strategy.take() {
+            self.strategy = strategy;
+        }
+        if send_group.cleared {
+            self.strategy.reset();
+        }
+        let (clone_time, clone_count) = (send_group.clone_time, send_group.clone_count);
+        let overflow_needed = any_paused
+            || !errored.is_empty()
+            || !buffer_full.is_empty()
+            || !rate_limited.is_empty();
+        for id in &errored {
+            self.handle_sink_error(id, "send", None);
+        }
+        for (id, error) in &encoded_errored {
+            self.handle_sink_error(id, "send", Some(error));
+        }
+        let errored = errored
+            .into_iter()
+            .chain(encoded_errored.into_iter().map(|(id, _)| id))
+            .collect::<Vec<_>>();
+        if self.error_policy == FanoutErrorPolicy::FailFast && !errored.is_empty() {
+            error!(
+                message = "Fanout outputs failed to accept events, stopping.",
+                outputs = ?errored,
+            );
+            self.halted = true;
+        }
+        if let Some(events) = fallback_events {
+            self.send_fallback(events, overflow_needed, &errored, inflight);
+        }
+        for id in &delivered {
+            if let Some(output) = self.senders.get_mut(id) {
+                output.failures = 0;
+                if record_stats {
+                    output.deliveries += 1;
+                }
+            }
+        }
+        if let Some(token) = token {
+            self.record_delivered(token, delivered);
+        }
+        if record_stats {
+            for id in buffer_full {
+                self.stats.record_drop(&id, DropReason::BufferFull);
+            }
+            for id in rate_limited {
+                self.stats.record_drop(&id, DropReason::RateLimited);
+            }
+            self.stats.clone_time += clone_time;
+            self.stats.clone_count += clone_count;
+        }
+
+        if !control_channel_open {
+            self.handle_control_closed();
+        }
+
+        SendReport { errored, accepted }
+    }
+}
+
+/// The outcome of sending an array to the fanout's outputs.
+#[derive(Debug, Default)]
+struct SendReport {
+    /// The outputs which failed to accept the array.
+    errored: Vec<ComponentKey>,
+    /// The number of outputs which accepted the array.
+    accepted: usize,
+}
+
+/// The outcome of a single output's send: its sender, the result of the send, the number of events
+/// sent, and any arrays to retry on its next send.
+type SendOutcome = (Sender, Result<bool, ()>, usize, VecDeque<EventArray>);
+
+/// Drives a single output's send, of its snapshot and any arrays to retry ahead of its armed input.
+///
+/// Outputs that retry, given the most arrays to queue for retrying, have their input queued if the
+/// send fails, along with any retries the sink failed to accept.
+async fn send_to_output(
+    mut sender: Sender,
+    delay: Duration,
+    snapshot: Option<EventArray>,
+    transformed: Option<BoxFuture<'static, EventArray>>,
+    mut retries: VecDeque<EventArray>,
+    max_queued: Option<usize>,
+) -> SendOutcome {
+    if !delay.is_zero() {
+        sleep(delay).await;
+    }
+    if let Some(snapshot) = snapshot {
+        if sender.inner.send(snapshot).await.is_err() {
+            sender.input = None;
+            return (sender, Err(()), 0, retries);
+        }
+    }
+    while let Some(retry) = retries.pop_front() {
+        if let Err(retry) = sender.inner.send_reporting_drop(retry).await {
+            retries.push_front(retry);
+            break;
+        }
+    }
+    if let Some(transformed) = transformed {
+        sender.input = Some(transformed.await);
+    }
+
+    let count = sender.input.as_ref().map_or(0, EventContainer::len);
+    let result = if retries.is_empty() {
+        sender.flush().await
+    } else {
+        // The sink failed on a retry, so the input is only queued behind it.
+        Err(sender.input.take())
+    };
+
+    // The input is only handed back if it wasn't sent, so it's never copied to be kept for a retry.
+    let (result, unsent) = match result {
+        Ok(dropped) => (Ok(dropped), None),
+        Err(unsent) => (Err(()), unsent),
+    };
+    match max_queued {
+        Some(max_queued) if result.is_err() => {
+            retries.extend(unsent);
+            let dropped = retries.len().saturating_sub(max_queued);
+            if dropped > 0 {
+                retries.drain(..dropped);
+                warn!(
+                    message = "Retry queue full, dropping oldest arrays.",
+                    dropped
+                );
+            }
+        }
+        None => retries.clear(),
+        Some(_) => {}
+    }
+    (sender, result, count, retries)
+}
+
+/// Drains each output in turn, as for `Fanout::drain`, counting the outputs drained so far and
+/// collecting those that failed.
+async fn drain_outputs(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    drained: &mut usize,
+    errored: &mut Vec<ComponentKey>,
+) {
+    for (id, output) in senders.iter_mut() {
+        if let Some(sender) = output.sender.as_mut().or(output.held.as_mut()) {
+            let mut result = Ok(());
+            while let Some(retry) = output.retries.pop_front() {
+                result = sender.inner.send(retry).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+            if result.is_ok() {
+                result = sender.inner.flush().await;
+            }
+            if result.is_err() {
+                errored.push(id.clone());
+            }
+        }
+        *drained += 1;
+    }
+}
+
+struct SendGroup<'a> {
+    senders: &'a mut IndexMap<ComponentKey, Output>,
+    shadows: &'a mut IndexMap<ComponentKey, Shadow>,
+    encoded: &'a mut IndexMap<ComponentKey, EncodedOutput>,
+    /// The in-flight sends, each resolving to its sender, the result of the send, and the number of
+    /// events sent.
+    sends: HashMap<ComponentKey, ReusableBoxFuture<'static, SendOutcome>>,
+    /// The in-flight sends to encoded outputs.
+    encoded_sends: HashMap<ComponentKey, EncodedSend>,
+    /// Outputs whose send completed with an error.
+    errored: Vec<ComponentKey>,
+    /// Encoded outputs whose send completed with an error, along with the error.
+    encoded_errored: Vec<(ComponentKey, crate::Error)>,
+    /// The number of encoded outputs which accepted the array.
+    encoded_delivered: usize,
+    /// Outputs which dropped the array because their buffer was full.
+    buffer_full: Vec<ComponentKey>,
+    /// Outputs which accepted the array.
+    delivered: Vec<ComponentKey>,
+    /// Outputs which dropped the array because it exceeded their rate limit.
+    rate_limited: Vec<ComponentKey>,
+    /// The routing strategy set while the send was in-flight, if any.
+    strategy: Option<Box<dyn RoutingStrategy + Send>>,
+    /// Whether every output was removed while the send was in-flight, such that the routing
+    /// strategy must be reset.
+    cleared: bool,
+    /// The total time spent cloning the array for each output, if clone timing is enabled.
+    clone_time: Duration,
+    clone_count: u64,
+    /// Outputs whose send has been polled without completing, and when it first was.
+    pending: HashMap<ComponentKey, Instant>,
+    /// Outputs paused while their send was in-flight, whose sender is held once it's returned.
+    holding: HashSet<ComponentKey>,
+    ready_observer: Option<&'a SinkObserver>,
+    latency_alert: Option<&'a (Duration, LatencyObserver)>,
+    /// When the send started, which the latency of each output's send is measured from.
+    started: Instant,
+    panic_free: bool,
+    /// The maximum number of sends polled at once, if cooperative sending is enabled.
+    cooperative_chunk: Option<usize>,
+    /// The outputs whose sends are still to be polled since every send was last polled, in output
+    /// order, when cooperative sending is enabled.
+    rotation: VecDeque<ComponentKey>,
+    /// How far along the output order polling starts, when cooperative sending is enabled.
+    poll_start: usize,
+    /// The waker each output's send is polled with when cooperative sending is enabled, which
+    /// records whether the send has been woken since it was last polled.
+    wakers: HashMap<ComponentKey, Arc<SendWaker>>,
+    /// The array's place under the fanout's in-flight limit, if any, which any detached send keeps
+    /// until it completes.
+    inflight: Option<Arc<OwnedSemaphorePermit>>,
+}
+
+impl<'a> SendGroup<'a> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        senders: &'a mut IndexMap<ComponentKey, Output>,
+        shadows: &'a mut IndexMap<ComponentKey, Shadow>,
+        encoded: &'a mut IndexMap<ComponentKey, EncodedOutput>,
+        selected: &HashSet<ComponentKey>,
+        events: EventArray,
+        encoded_sends: HashMap<ComponentKey, EncodedSend>,
+        clone_timing: bool,
+        panic_free: bool,
+        ready_observer: Option<&'a SinkObserver>,
+        latency_alert: Option<&'a (Duration, LatencyObserver)>,
+        cooperative_chunk: Option<usize>,
+        poll_start: usize,
+        tier_policies: &HashMap<u8, TierPolicy>,
+        inflight: Option<Arc<OwnedSemaphorePermit>>,
+    ) -> Self {
+        // If we don't have a valid `Sender` for all sinks, then something went wrong in our logic
+        // to ensure we were starting with all valid/idle senders prior to initiating the send.
+        debug_assert!(panic_free || senders.values().all(|output| output.sender.is_some()));
+
+        // Shadows are sent to independently of the send itself, since they must never hold it up.
+        for shadow in shadows.values() {
+            if shadow
+                .target
+                .as_ref()
+                .map_or(false, |target| selected.contains(target))
+            {
+                shadow.send(events.clone());
+            }
+        }
+
+        // Only the outputs selected by the routing strategy take part in the send, which never
+        // includes paused outputs, and only if they're within their rate limit and have a sender.
+        let mut armed = Vec::new();
+        let mut rate_limited = Vec::new();
+        for (i, (key, output)) in senders.iter_mut().enumerate() {
+            if !selected.contains(key) {
+                continue;
+            }
+
+            // An output over its rate limit either misses the array, or waits before sending it.
+            let delay = match output.rate_limit.as_mut().map(RateLimit::acquire) {
+                Some(None) => {
+                    rate_limited.push(key.clone());
+                    continue;
+                }
+                Some(Some(delay)) => delay,
+                None => Duration::ZERO,
+            };
+
+            // An output without a sender is left out of the send, and keeps waiting on its
+            // replacement.
+            if let Some(sender) = output.sender.take() {
+                armed.push((i, sender, delay));
+            } else {
+                invalid_operation(
+                    panic_free,
+                    format_args!("Sender must be present to initialize SendGroup: {key}"),
+                );
+            }
+        }
+
+        // We generate a send future for each sender we have, which arms them with the events to
+        // send but also takes ownership of the sender itself, which we give back when the sender completes.
+        let armed_count = armed.len();
+        let mut events = Some(events);
+        let mut clone_time = Duration::ZERO;
+        let mut clone_count = 0;
+        let mut sends = HashMap::new();
+        for (n, (i, mut sender, delay)) in armed.into_iter().enumerate() {
+            let (key, output) = senders
+                .get_index_mut(i)
+                .expect("armed output must be present");
+
+            // First, arm each sender with the item to actually send. Each is cloned only as it's
+            // armed, with the last sender armed taking the original, so no clone goes unsent.
+            let input = if n + 1 == armed_count {
+                events.take()
+            } else if clone_timing {
+                // This is timed against the real clock, since the clone takes real time even when
+                // the runtime's clock is paused.
+                let start = std::time::Instant::now();
+                let input = events.clone();
+                clone_time += start.elapsed();
+                clone_count += 1;
+                input
+            } else {
+                events.clone()
+            };
+
+            // If the output has an async transform, the sender is instead armed with the
+            // transformed item as part of its send, so that no other output waits on the transform.
+            let transformed = if let Some(transform) = &output.transform {
+                input.map(transform)
+            } else {
+                sender.input = input;
+                None
+            };
+
+            // Now generate a send for that sender which we'll drive to completion.
+            let max_queued = output
+                .tier
+                .and_then(|tier| tier_policies.get(&tier))
+                .and_then(TierPolicy::max_queued);
+            let send = send_to_output(
+                sender,
+                delay,
+                output.snapshot.take(),
+                transformed,
+                mem::take(&mut output.retries),
+                max_queued,
+            );
+
+            sends.insert(key.clone(), ReusableBoxFuture::new(send));
+        }
+
+        Self {
+            senders,
+            shadows,
+            encoded,
+            sends,
+            encoded_sends,
+            errored: Vec::new(),
+            encoded_errored: Vec::new(),
+            encoded_delivered: 0,
+            buffer_full: Vec::new(),
+            delivered: Vec::new(),
+            rate_limited,
+            strategy: None,
+            cleared: false,
+            clone_time,
+            clone_count,
+            pending: HashMap::new(),
+            holding: HashSet::new(),
+            ready_observer,
+            latency_alert,
+            started: Instant::now(),
+            panic_free,
+            cooperative_chunk,
+            rotation: VecDeque::new(),
+            poll_start,
+            wakers: HashMap::new(),
+            inflight,
+        }
+    }
+
+    /// Apply a control message against the senders of this group, while its send is in-flight.
+    fn apply_control_message(&mut self, message: ControlMessage) {
+        match message {
+            ControlMessage::Add(id, sink) => self.add(id, Output::new(sink, None)),
+            ControlMessage::Remove(id) => self.remove(&id),
+            ControlMessage::Replace(id, Some(sink)) => self.replace_sink(&id, sink),
+            ControlMessage::Replace(id, None) => self.pause(&id),
+            ControlMessage::CancelReplace(id) => self.cancel_replace(&id),
+            ControlMessage::AddToGroup(id, sink, group) => {
+                self.add(id, Output::new(sink, Some(group)));
+            }
+            ControlMessage::PauseGroup(group) => self.set_group_paused(&group, true),
+            ControlMessage::ResumeGroup(group) => self.set_group_paused(&group, false),
+            ControlMessage::SetPriority(id, priority) => self.set_priority(&id, priority),
+            ControlMessage::AdjustWeight(id, delta) => self.adjust_weight(&id, delta),
+            ControlMessage::Reorder(ids) => self.reorder(&ids),
+            ControlMessage::AddShadow(id, sink, target) => self.add_shadow(id, sink, target),
+            ControlMessage::AddAsyncTransformed(id, sink, transform) => {
+                self.add(id, Output::with_transform(sink, transform));
+            }
+            ControlMessage::AddWithAcceptCallback(id, sink, on_accept) => {
+                self.add(id, Output::with_accept_callback(sink, on_accept));
+            }
+            ControlMessage::AddWithSnapshot(id, sink, snapshot) => {
+                self.add(id, Output::with_snapshot(sink, snapshot));
+            }
+            ControlMessage::AddWithTier(id, sink, tier) => {
+                self.add(id, Output::with_tier(sink, tier));
+            }
+            ControlMessage::AddFiltered(id, sink, filter) => {
+                self.add(id, Output::with_filter(sink, filter));
+            }
+            ControlMessage::AddForTypes(id, sink, types) => {
+                self.add(id, Output::with_filter(sink, type_filter(types)));
+            }
+            ControlMessage::AddWithFactory(id, factory) => {
+                self.add(id, Output::with_factory(factory));
+            }
+            ControlMessage::SetOverflow(id, sink) => {
+                // Like a shadow, the overflow only gets arrays from the next send on.
+                set_overflow(
+                    self.senders,
+                    self.shadows,
+                    self.encoded,
+                    id,
+                    sink,
+                    self.panic_free,
+                );
+            }
+            ControlMessage::ReconfigureRouting(config) => {
+                // The array being sent was already routed, so the new strategy is only handed over
+                // to the fanout once the send completes.
+                self.strategy = Some(reconfigure_routing(self.senders, config));
+            }
+            ControlMessage::AddMany(outputs) => {
+                add_many(
+                    self.senders,
+                    self.shadows,
+                    self.encoded,
+                    outputs,
+                    self.panic_free,
+                );
+            }
+            ControlMessage::SetSinkRateLimit(id, per_sec, on_exceed) => {
+                // The array being sent already took its tokens, so only the next send is affected.
+                set_rate_limit(self.senders, &id, per_sec, on_exceed, self.panic_free);
+            }
+            ControlMessage::Retire(id) => {
+                // Any send already in-flight to the output completes as usual.
+                retire(self.senders, &id, self.panic_free);
+            }
+            ControlMessage::AddEncoded(id, sink, encoder) => {
+                // Like any other output added mid-send, it only gets arrays from the next send on.
+                add_encoded(
+                    self.senders,
+                    self.shadows,
+                    self.encoded,
+                    id,
+                    EncodedOutput::bytes(sink, encoder),
+                    self.panic_free,
+                );
+            }
+            ControlMessage::AddShared(id, sink) => {
+                // Like encoded outputs, it only gets arrays from the next send on.
+                add_encoded(
+                    self.senders,
+                    self.shadows,
+                    self.encoded,
+                    id,
+                    EncodedOutput::shared(sink),
+                    self.panic_free,
+                );
+            }
+            ControlMessage::Clear => self.clear(),
+            ControlMessage::Batch(messages) => {
+                for message in messages {
+                    self.apply_control_message(message);
+                }
+            }
+            ControlMessage::Acked(message, ack) => {
+                self.apply_control_message(*message);
+                let _ = ack.send(());
+            }
+            ControlMessage::Pause(id) => {
+                // The sender is out for the in-flight send, so it's held once it's returned.
+                if self.sends.contains_key(&id) {
+                    self.holding.insert(id);
+                } else {
+                    hold(self.senders, &id, self.panic_free);
+                }
+            }
+            ControlMessage::Resume(id) => {
+                if !self.holding.remove(&id) {
+                    resume(self.senders, &id, self.panic_free);
+                }
+            }
+            ControlMessage::SetFlushInterval(id, interval) => {
+                set_flush_interval(self.senders, &id, interval, self.panic_free);
+            }
+            // A fanout only halts once a send completes, so it's never halted mid-send.
+            ControlMessage::Unhalt => {}
+        }
+    }
+
+    /// Removes every output, detaching any in-flight sends to them.
+    fn clear(&mut self) {
+        let ids = self
+            .sends
+            .keys()
+            .chain(self.encoded_sends.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in &ids {
+            self.try_detach_send(id);
+        }
+        self.senders.clear();
+        self.shadows.clear();
+        self.encoded.clear();
+        self.pending.clear();
+        self.holding.clear();
+        self.rotation.clear();
+        self.cleared = true;
+    }
+
+    /// Detaches the in-flight send for the given output, if any, leaving it to complete in the
+    /// background. Returns whether there was a send to detach.
+    fn try_detach_send(&mut self, id: &ComponentKey) -> bool {
+        if let Some(send) = self.sends.remove(id) {
+            let inflight = self.inflight.clone();
+            tokio::spawn(async move {
+                // The send's outcome doesn't matter, since the output is already gone, but the array
+                // stays in-flight until it completes.
+                let (_sender, _result, _count, _retries) = send.await;
+                drop(inflight);
+            });
+            true
+        } else if let Some(send) = self.encoded_sends.remove(id) {
+            let inflight = self.inflight.clone();
+            tokio::spawn(async move {
+                let _result = send.await;
+                drop(inflight);
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn add(&mut self, id: ComponentKey, output: Output) {
+        // When we're in the middle of a send, we can only keep track of the new sink, but can't
+        // actually send to it, as we don't have the item to send... so only add it to `senders`.
+        if key_in_use(self.senders, self.shadows, self.encoded, &id) {
+            invalid_operation(
+                self.panic_free,
+                format_args!("Adding duplicate output id to fanout: {id}"),
+            );
+            return;
+        }
+        self.senders.insert(id, output);
+    }
+
+    fn add_shadow(
+        &mut self,
+        id: ComponentKey,
+        sink: BufferSender<EventArray>,
+        target: ComponentKey,
+    ) {
+        // Like any other output added mid-send, the shadow only gets arrays from the next send on.
+        add_shadow(
+            self.senders,
+            self.shadows,
+            self.encoded,
+            id,
+            sink,
+            target,
+            self.panic_free,
+        );
+    }
+
+    fn remove(&mut self, id: &ComponentKey) {
+        // Any send to a shadow is detached already, so there's nothing else to do for it.
+        if self.shadows.shift_remove(id).is_some() {
+            return;
+        }
+        if self.encoded.shift_remove(id).is_some() {
+            self.try_detach_send(id);
+            return;
+        }
+
+        // We may or may not be removing a sender that we're try to drive a send against, so we have
+        // to also detach the send future for the sender if it exists, otherwise we'd be hanging
+        // around still trying to send to it.
+        if self.senders.shift_remove(id).is_none() {
+            warn!(message = "Removing non-existent sink from fanout.", output = %id);
+            return;
+        }
+
+        // Now try and detach the in-flight send, if it exists.
+        self.try_detach_send(id);
+    }
+
+    /// Replaces the sink of the given output, as directed by `ControlMessage::Replace`.
+    fn replace_sink(&mut self, id: &ComponentKey, sink: BufferSender<EventArray>) {
+        reset_backpressure(self.senders, id);
+        self.replace(id, Sender::new(sink));
+    }
+
+    fn replace(&mut self, id: &ComponentKey, sink: Sender) {
+        let output = if let Some(output) = self.senders.get_mut(id) {
+            output
+        } else {
+            let error = FanoutError::UnknownOutput { id: id.clone() };
+            warn!(message = "Failed to replace sink.", %error);
+            return;
+        };
+
+        // While a sink must be _known_ to be replaced, it must also be empty (previously paused or
+        // consumed when the `SendGroup` was created), otherwise an invalid sequence of control
+        // operations has been applied.
+        if output.sender.replace(sink).is_some() {
+            invalid_operation(
+                self.panic_free,
+                format_args!("Replacing existing sink is not valid: {id}"),
+            );
+        }
+    }
+
+    fn pause(&mut self, id: &ComponentKey) {
+        reset_backpressure(self.senders, id);
         match self.senders.get_mut(id) {
-            Some(sender) => {
-                // A sink must be known and present to be replaced, otherwise an invalid sequence of
-                // control operations has been applied.
-                assert!(
-                    sender.take().is_some(),
-                    "Pausing non-existent sink is not valid: {id}"
+            Some(output) => {
+                // If we don't have the sender, then there must be a send to it in-flight, since an
+                // output can't already be awaiting replacement while a send is in progress. That
+                // send is detached so the old sink still gets the events it was armed with, while
+                // the output itself waits on its replacement.
+                //
+                // Otherwise, an invalid sequence of control operations has been applied.
+                if output.sender.take().is_none() && !self.try_detach_send(id) {
+                    invalid_operation(
+                        self.panic_free,
+                        format_args!("Pausing non-existent sink is not valid: {id}"),
+                    );
+                }
+            }
+            None => invalid_operation(
+                self.panic_free,
+                format_args!("Pausing unknown sink from fanout: {id}"),
+            ),
+        }
+    }
+
+    fn cancel_replace(&mut self, id: &ComponentKey) {
+        // An output with a send in-flight has no sender either, but it isn't awaiting replacement.
+        if self.sends.contains_key(id) {
+            invalid_operation(
+                self.panic_free,
+                format_args!("Cancelling replace of sink not awaiting replacement: {id}"),
+            );
+            return;
+        }
+        cancel_replace(self.senders, id, self.panic_free);
+    }
+
+    fn set_group_paused(&mut self, group: &str, paused: bool) {
+        // Sends already in-flight for the group's outputs are left to complete, since they were
+        // armed before the group was paused.
+        set_group_paused(self.senders, group, paused);
+    }
+
+    fn set_priority(&mut self, id: &ComponentKey, priority: u8) {
+        set_priority(self.senders, id, priority, self.panic_free);
+    }
+
+    fn adjust_weight(&mut self, id: &ComponentKey, delta: i32) {
+        adjust_weight(self.senders, id, delta, self.panic_free);
+    }
+
+    fn reorder(&mut self, ids: &[ComponentKey]) {
+        // In-flight sends are tracked by key rather than position, so they're unaffected.
+        reorder(self.senders, ids);
+    }
+
+    /// Drops every output which still has a send in-flight, along with the send itself.
+    ///
+    /// Returns the keys of the dropped outputs.
+    fn drop_pending_sends(&mut self) -> Vec<ComponentKey> {
+        let mut dropped = self.sends.drain().map(|(key, _)| key).collect::<Vec<_>>();
+        for key in &dropped {
+            self.senders.shift_remove(key);
+        }
+        for (key, _) in self.encoded_sends.drain() {
+            self.encoded.shift_remove(&key);
+            dropped.push(key);
+        }
+        dropped
+    }
+
+    async fn send(&mut self) {
+        // Right now, we do a linear scan of all sends, polling each send once in order to avoid
+        // waiting forever, such that we can let our control messages get picked up while sends are
+        // waiting.
+        loop {
+            if self.sends.is_empty() && self.encoded_sends.is_empty() {
+                break;
+            }
+
+            let mut done = Vec::new();
+            if let Some(chunk) = self.cooperative_chunk {
+                for key in self.next_chunk(chunk) {
+                    if let Some(send) = self.sends.get_mut(&key) {
+                        let waker = Arc::clone(
+                            self.wakers
+                                .entry(key.clone())
+                                .or_insert_with(|| Arc::new(SendWaker::default())),
+                        );
+                        if let Poll::Ready((sender, result, count, retries)) =
+                            future::poll_fn(|cx| Poll::Ready(waker.poll(send, cx))).await
+                        {
+                            done.push((key, sender, result, count, retries));
+                        } else {
+                            self.pending.entry(key).or_insert_with(Instant::now);
+                        }
+                    }
+                }
+            } else {
+                for (key, send) in &mut self.sends {
+                    if let Poll::Ready((sender, result, count, retries)) = poll!(send.get_pin()) {
+                        // The send completed, so we restore the sender and mark ourselves so that
+                        // this future gets dropped.
+                        done.push((key.clone(), sender, result, count, retries));
+                    } else if !self.pending.contains_key(key) {
+                        self.pending.insert(key.clone(), Instant::now());
+                    }
+                }
+            }
+
+            for (key, sender, result, count, retries) in done {
+                self.sends.remove(&key);
+                self.wakers.remove(&key);
+                let waited = self.pending.remove(&key).map(|since| since.elapsed());
+                if waited.is_some() {
+                    if let Some(observer) = self.ready_observer {
+                        observer(&key);
+                    }
+                }
+                if let Some((threshold, observer)) = self.latency_alert {
+                    let latency = self.started.elapsed();
+                    if latency > *threshold {
+                        observer(&key, latency);
+                    }
+                }
+                self.replace(&key, sender);
+                if let Some(output) = self.senders.get_mut(&key) {
+                    output.retries = retries;
+                    output.backpressure += waited.unwrap_or_default();
+                }
+                if self.holding.remove(&key) {
+                    hold(self.senders, &key, self.panic_free);
+                }
+                match result {
+                    Ok(false) => {
+                        if let Some(on_accept) = self
+                            .senders
+                            .get(&key)
+                            .and_then(|output| output.on_accept.as_ref())
+                        {
+                            on_accept(&key, count);
+                        }
+                        self.delivered.push(key);
+                    }
+                    Ok(true) => self.buffer_full.push(key),
+                    Err(()) => self.errored.push(key),
+                }
+            }
+
+            // Encoded outputs have no sender to hand back, so their sends are simply polled along
+            // with every other batch of sends.
+            let mut encoded_done = Vec::new();
+            for (key, send) in &mut self.encoded_sends {
+                if let Poll::Ready(result) = poll!(send.as_mut()) {
+                    encoded_done.push((key.clone(), result));
+                }
+            }
+            for (key, result) in encoded_done {
+                self.encoded_sends.remove(&key);
+                match result {
+                    Ok(()) => self.encoded_delivered += 1,
+                    Err(error) => self.encoded_errored.push((key, error)),
+                }
+            }
+
+            if !self.sends.is_empty() || !self.encoded_sends.is_empty() {
+                if self.rotation.is_empty() && !self.any_woken() {
+                    // We manually yield ourselves because we've polled all of the sends at this
+                    // point, so if any are left, then we're scheduled for a wake-up... this is a
+                    // really poor approximation of what `FuturesUnordered` is doing. Wake-ups are
+                    // coalesced by the task itself, so however many sends become ready before
+                    // we're next polled, they're all handled by that one poll.
+                    pending!();
+                } else {
+                    // Some sends haven't been polled since we were last woken, or were woken while
+                    // we yielded, in which case that wake-up was absorbed by the yield. Nothing
+                    // else is guaranteed to wake us for them, so we schedule our own wake-up.
+                    tokio::task::yield_now().await;
+                }
+            }
+        }
+    }
+
+    /// Whether any send still in-flight has been woken since it was last polled.
+    ///
+    /// Only sends polled cooperatively are tracked, since otherwise every send is polled each time
+    /// the task is.
+    fn any_woken(&self) -> bool {
+        self.sends.keys().any(|key| {
+            self.wakers
+                .get(key)
+                .map_or(false, |waker| waker.woken.load(Ordering::Acquire))
+        })
+    }
+
+    /// Takes the next `chunk` outputs to poll the sends of, in output order starting `poll_start`
+    /// outputs along, starting over once every send has been polled.
+    fn next_chunk(&mut self, chunk: usize) -> Vec<ComponentKey> {
+        if self.rotation.is_empty() {
+            let senders = &self.senders;
+            let mut keys = self.sends.keys().cloned().collect::<Vec<_>>();
+            keys.sort_by_key(|key| senders.get_index_of(key).unwrap_or(usize::MAX));
+            if !keys.is_empty() {
+                let start = self.poll_start % keys.len();
+                keys.rotate_left(start);
+            }
+            self.rotation.extend(keys);
+        }
+
+        let mut keys = Vec::with_capacity(chunk);
+        while keys.len() < chunk {
+            match self.rotation.pop_front() {
+                // Outputs removed since the rotation started no longer have a send to poll.
+                Some(key) if self.sends.contains_key(&key) => keys.push(key),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        keys
+    }
+}
+
+/// Wakes the task polling an output's send, while recording that the send was woken.
+///
+/// When sending cooperatively, a send polled in an earlier chunk can be woken while the task is
+/// yielding between chunks, and that wake-up is merged into the yield's own. The record is what
+/// tells the task not to wait for a wake-up that has already happened.
+#[derive(Default)]
+struct SendWaker {
+    woken: AtomicBool,
+    task: AtomicWaker,
+}
+
+impl SendWaker {
+    /// Polls the given send on behalf of the task with the given context.
+    fn poll(
+        self: &Arc<Self>,
+        send: &mut ReusableBoxFuture<'static, SendOutcome>,
+        cx: &mut Context<'_>,
+    ) -> Poll<SendOutcome> {
+        self.woken.store(false, Ordering::Release);
+        self.task.register(cx.waker());
+        let waker = task::waker_ref(self);
+        send.poll(&mut Context::from_waker(&waker))
+    }
+}
+
+impl ArcWake for SendWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.woken.store(true, Ordering::Release);
+        arc_self.task.wake();
+    }
+}
+
+/// Replaces each batch among the given descriptors with the descriptors it holds, in order.
+fn flatten_batches(descriptors: &[ControlMessageDescriptor]) -> Vec<ControlMessageDescriptor> {
+    let mut flattened = Vec::with_capacity(descriptors.len());
+    for descriptor in descriptors {
+        match descriptor {
+            ControlMessageDescriptor::Batch(descriptors) => {
+                flattened.extend(flatten_batches(descriptors));
+            }
+            descriptor => flattened.push(descriptor.clone()),
+        }
+    }
+    flattened
+}
+
+/// Waits until any available output whose buffer is full has room for another array, or forever if
+/// there are no such outputs.
+async fn wait_for_room(senders: &IndexMap<ComponentKey, Output>) {
+    let probe = EventArray::Logs(Vec::new());
+    let waits = senders
+        .values()
+        .filter(|output| output.is_available() && !output.has_room_for(&probe))
+        .filter_map(|output| output.sender.as_ref())
+        .map(|sender| {
+            Box::pin(async move {
+                // An output whose buffer has been closed is never going to have room.
+                if sender.inner.wait_for_room(1).await.is_err() {
+                    future::pending::<()>().await;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    if waits.is_empty() {
+        future::pending::<()>().await;
+    } else {
+        future::select_all(waits).await;
+    }
+}
+
+/// Sleeps until the given deadline, or forever if there isn't one.
+async fn sleep_until_due(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+/// Waits for room under the given in-flight limit, if any, returning the place taken by the array
+/// about to be sent.
+async fn acquire_inflight(inflight: Option<Arc<Semaphore>>) -> Option<Arc<OwnedSemaphorePermit>> {
+    let permit = inflight?
+        .acquire_owned()
+        .await
+        .expect("in-flight semaphore is never closed");
+    Some(Arc::new(permit))
+}
+
+/// Reports an invalid operation against the fanout, panicking unless the fanout is panic-free.
+///
+/// Callers are responsible for leaving the fanout in a consistent state when this returns.
+fn invalid_operation(panic_free: bool, reason: fmt::Arguments<'_>) {
+    if panic_free {
+        error!(message = "Invalid fanout operation ignored.", %reason);
+    } else {
+        panic!("{}", reason);
+    }
+}
+
+fn cancel_replace(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    id: &ComponentKey,
+    panic_free: bool,
+) {
+    // Since the replacement will never arrive, the output is removed rather than left in place, as
+    // otherwise it would block all sends indefinitely.
+    match senders.get(id) {
+        Some(output) if output.sender.is_none() => {
+            senders.shift_remove(id);
+        }
+        Some(_) => invalid_operation(
+            panic_free,
+            format_args!("Cancelling replace of sink not awaiting replacement: {id}"),
+        ),
+        None => invalid_operation(
+            panic_free,
+            format_args!("Cancelling replace of unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+/// Whether any kind of output already uses the given key, since every kind shares the same keys.
+fn key_in_use(
+    senders: &IndexMap<ComponentKey, Output>,
+    shadows: &IndexMap<ComponentKey, Shadow>,
+    encoded: &IndexMap<ComponentKey, EncodedOutput>,
+    id: &ComponentKey,
+) -> bool {
+    senders.contains_key(id) || shadows.contains_key(id) || encoded.contains_key(id)
+}
+
+fn add_shadow(
+    senders: &IndexMap<ComponentKey, Output>,
+    shadows: &mut IndexMap<ComponentKey, Shadow>,
+    encoded: &IndexMap<ComponentKey, EncodedOutput>,
+    id: ComponentKey,
+    sink: BufferSender<EventArray>,
+    target: ComponentKey,
+    panic_free: bool,
+) {
+    if key_in_use(senders, shadows, encoded, &id) {
+        invalid_operation(
+            panic_free,
+            format_args!("Adding duplicate output id to fanout: {id}"),
+        );
+    } else if !senders.contains_key(&target) {
+        invalid_operation(
+            panic_free,
+            format_args!("Shadowing unknown sink from fanout: {target}"),
+        );
+    } else {
+        shadows.insert(id, Shadow::new(sink, Some(target)));
+    }
+}
+
+/// Adds all of the given sinks as outputs, or none of them if any key is a duplicate.
+fn add_many(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    shadows: &IndexMap<ComponentKey, Shadow>,
+    encoded: &IndexMap<ComponentKey, EncodedOutput>,
+    outputs: Vec<(ComponentKey, BufferSender<EventArray>)>,
+    panic_free: bool,
+) {
+    let mut ids = HashSet::new();
+    for (id, _) in &outputs {
+        if key_in_use(senders, shadows, encoded, id) || !ids.insert(id) {
+            invalid_operation(
+                panic_free,
+                format_args!("Adding duplicate output id to fanout: {id}"),
+            );
+            return;
+        }
+    }
+    for (id, sink) in outputs {
+        senders.insert(id, Output::new(sink, None));
+    }
+}
+
+fn add_encoded(
+    senders: &IndexMap<ComponentKey, Output>,
+    shadows: &IndexMap<ComponentKey, Shadow>,
+    encoded: &mut IndexMap<ComponentKey, EncodedOutput>,
+    id: ComponentKey,
+    output: EncodedOutput,
+    panic_free: bool,
+) {
+    if key_in_use(senders, shadows, encoded, &id) {
+        invalid_operation(
+            panic_free,
+            format_args!("Adding duplicate output id to fanout: {id}"),
+        );
+        return;
+    }
+    encoded.insert(id, output);
+}
+
+/// Encodes the given array for every encoded output, only once for each distinct encoder, and
+/// copies it only once for all shared outputs, unless it has fewer events than the copy-on-write
+/// threshold, in which case it's copied for each of them.
+///
+/// Returns the send of the array to each encoded output, for the send group to drive.
+fn encode_array(
+    encoded: &IndexMap<ComponentKey, EncodedOutput>,
+    events: &EventArray,
+    cow_threshold: usize,
+) -> HashMap<ComponentKey, EncodedSend> {
+    let mut by_encoder = HashMap::new();
+    let mut shared = None;
+    encoded
+        .iter()
+        .map(|(key, output)| {
+            let send: EncodedSend = match output {
+                EncodedOutput::Bytes { sink, encoder } => {
+                    let bytes = by_encoder
+                        .entry(Arc::as_ptr(encoder).cast::<()>())
+                        .or_insert_with(|| encoder(events))
+                        .clone();
+                    let sink = Arc::clone(sink);
+                    Box::pin(async move { sink.lock().await.send(bytes).await })
+                }
+                EncodedOutput::Shared(sink) => {
+                    let events = if events.len() < cow_threshold {
+                        Arc::new(events.clone())
+                    } else {
+                        Arc::clone(shared.get_or_insert_with(|| Arc::new(events.clone())))
+                    };
+                    let sink = Arc::clone(sink);
+                    Box::pin(async move { sink.lock().await.send(events).await })
+                }
+            };
+            (key.clone(), send)
+        })
+        .collect()
+}
+
+/// Builds a filter accepting the non-empty arrays of the given types.
+fn type_filter(types: DataType) -> Filter {
+    Arc::new(move |events: &EventArray| {
+        let data_type = match events {
+            EventArray::Logs(_) => DataType::Log,
+            EventArray::Metrics(_) => DataType::Metric,
+            EventArray::Traces(_) => DataType::Trace,
+        };
+        !events.is_empty() && types.contains(data_type)
+    })
+}
+
+/// Applies the weights and priorities of the given routing configuration to every output, returning
+/// its strategy.
+fn reconfigure_routing(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    config: RoutingConfig,
+) -> Box<dyn RoutingStrategy + Send> {
+    for (key, output) in senders.iter_mut() {
+        output.weight = config.weights.get(key).copied().unwrap_or(DEFAULT_WEIGHT);
+        output.priority = config
+            .priorities
+            .get(key)
+            .copied()
+            .unwrap_or(DEFAULT_PRIORITY);
+    }
+    config.strategy
+}
+
+fn set_overflow(
+    senders: &IndexMap<ComponentKey, Output>,
+    shadows: &mut IndexMap<ComponentKey, Shadow>,
+    encoded: &IndexMap<ComponentKey, EncodedOutput>,
+    id: ComponentKey,
+    sink: BufferSender<EventArray>,
+    panic_free: bool,
+) {
+    // There's only ever one overflow output, so any existing one is replaced, though only once the
+    // new one is known to be valid, so that a rejected overflow leaves the existing one in place.
+    let duplicate = senders.contains_key(&id)
+        || encoded.contains_key(&id)
+        || shadows
+            .get(&id)
+            .map_or(false, |shadow| shadow.target.is_some());
+    if duplicate {
+        invalid_operation(
+            panic_free,
+            format_args!("Adding duplicate output id to fanout: {id}"),
+        );
+        return;
+    }
+    shadows.retain(|_, shadow| shadow.target.is_some());
+    shadows.insert(id, Shadow::new(sink, None));
+}
+
+/// Gets the outputs which were able to report their occupancy.
+fn occupancy_of(
+    occupancy: &[(ComponentKey, Option<(usize, usize)>)],
+) -> impl Iterator<Item = (&ComponentKey, (usize, usize))> {
+    occupancy
+        .iter()
+        .filter_map(|(key, occupancy)| occupancy.map(|occupancy| (key, occupancy)))
+}
+
+/// Escapes a label value for the `OpenMetrics` text exposition format, which is also how a quoted
+/// ID is escaped in the DOT language.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn set_group_paused(senders: &mut IndexMap<ComponentKey, Output>, group: &str, paused: bool) {
+    for output in senders.values_mut() {
+        if output.group.as_deref() == Some(group) {
+            output.paused = paused;
+        }
+    }
+}
+
+fn retire(senders: &mut IndexMap<ComponentKey, Output>, id: &ComponentKey, panic_free: bool) {
+    match senders.get_mut(id) {
+        Some(output) => output.retiring = true,
+        None => invalid_operation(
+            panic_free,
+            format_args!("Retiring unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+fn hold(senders: &mut IndexMap<ComponentKey, Output>, id: &ComponentKey, panic_free: bool) {
+    match senders.get_mut(id) {
+        Some(output) => {
+            // Only a sink that's present can be held, otherwise an invalid sequence of control
+            // operations has been applied.
+            if let Some(sender) = output.sender.take() {
+                output.held = Some(sender);
+            } else {
+                invalid_operation(
+                    panic_free,
+                    format_args!("Pausing non-existent sink is not valid: {id}"),
+                );
+            }
+        }
+        None => invalid_operation(
+            panic_free,
+            format_args!("Pausing unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+fn resume(senders: &mut IndexMap<ComponentKey, Output>, id: &ComponentKey, panic_free: bool) {
+    match senders.get_mut(id) {
+        Some(output) => {
+            if let Some(sender) = output.held.take() {
+                output.sender = Some(sender);
+            } else {
+                invalid_operation(
+                    panic_free,
+                    format_args!("Resuming sink that isn't paused is not valid: {id}"),
+                );
+            }
+        }
+        None => invalid_operation(
+            panic_free,
+            format_args!("Resuming unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+fn set_priority(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    id: &ComponentKey,
+    priority: u8,
+    panic_free: bool,
+) {
+    match senders.get_mut(id) {
+        Some(output) => output.priority = priority,
+        None => invalid_operation(
+            panic_free,
+            format_args!("Setting priority of unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+/// Resets the time the given output has held up sends, since it's no longer attributable to the
+/// output's new sink.
+///
+/// Unknown outputs are left to the replacement itself to report.
+fn reset_backpressure(senders: &mut IndexMap<ComponentKey, Output>, id: &ComponentKey) {
+    if let Some(output) = senders.get_mut(id) {
+        output.backpressure = Duration::ZERO;
+    }
+}
+
+fn set_rate_limit(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    id: &ComponentKey,
+    per_sec: u32,
+    on_exceed: WhenFull,
+    panic_free: bool,
+) {
+    match senders.get_mut(id) {
+        Some(output) => {
+            output.rate_limit = (per_sec > 0).then(|| RateLimit::new(per_sec, on_exceed));
+        }
+        None => invalid_operation(
+            panic_free,
+            format_args!("Rate limiting unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+fn set_flush_interval(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    id: &ComponentKey,
+    interval: Duration,
+    panic_free: bool,
+) {
+    match senders.get_mut(id) {
+        Some(output) => {
+            output.flush_schedule = if interval.is_zero() {
+                None
+            } else {
+                Some((interval, Instant::now() + interval))
+            };
+        }
+        None => invalid_operation(
+            panic_free,
+            format_args!("Scheduling flushes of unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+fn adjust_weight(
+    senders: &mut IndexMap<ComponentKey, Output>,
+    id: &ComponentKey,
+    delta: i32,
+    panic_free: bool,
+) {
+    match senders.get_mut(id) {
+        Some(output) => {
+            output.weight = if delta < 0 {
+                output.weight.saturating_sub(delta.unsigned_abs())
+            } else {
+                output.weight.saturating_add(delta.unsigned_abs())
+            };
+        }
+        None => invalid_operation(
+            panic_free,
+            format_args!("Adjusting weight of unknown sink from fanout: {id}"),
+        ),
+    }
+}
+
+fn reorder(senders: &mut IndexMap<ComponentKey, Output>, ids: &[ComponentKey]) {
+    let mut reordered = IndexMap::with_capacity(senders.len());
+    for id in ids {
+        if let Some(output) = senders.shift_remove(id) {
+            reordered.insert(id.clone(), output);
+        }
+    }
+
+    // Whatever is left wasn't listed, and goes at the end in the same relative order as before.
+    reordered.extend(senders.drain(..));
+    *senders = reordered;
+}
+
+struct Output {
+    /// The sender for this output, or `None` if it is awaiting replacement, is paused, or is
+    /// currently armed as part of an in-flight send.
+    sender: Option<Sender>,
+    /// The sender for this output while it's paused by `ControlMessage::Pause`, held until the
+    /// output is resumed.
+    held: Option<Sender>,
+    group: Option<String>,
+    /// Whether or not sends skip this output entirely.
+    ///
+    /// Unlike an output awaiting replacement, which blocks all sends until it is replaced, a paused
+    /// output simply misses any events sent while it is paused.
+    paused: bool,
+    /// Whether or not this output is retiring, such that it's sent no more arrays, and is removed
+    /// once its buffer has drained.
+    retiring: bool,
+    priority: u8,
+    weight: u32,
+    /// The number of sends this output has failed to accept.
+    errors: u64,
+    /// The number of arrays this output has accepted.
+    deliveries: u64,
+    /// The async transform applied to each array before it is sent to this output, if any.
+    transform: Option<AsyncTransform>,
+    /// The dedup tokens of the arrays most recently accepted by this output, oldest first.
+    delivered: VecDeque<u64>,
+    /// The callback invoked each time this output accepts an array, if any.
+    on_accept: Option<AcceptCallback>,
+    /// The limit on the rate of arrays sent to this output, if any.
+    rate_limit: Option<RateLimit>,
+    /// The snapshot to send ahead of the next array sent to this output, if it hasn't been sent yet.
+    snapshot: Option<EventArray>,
+    /// How often this output's buffer is flushed, and when its next flush is due, if it's flushed on
+    /// a schedule of its own.
+    flush_schedule: Option<(Duration, Instant)>,
+    /// The reliability tier of this output, if it was added in one.
+    tier: Option<u8>,
+    /// The arrays this output failed to accept, to be retried ahead of the next array sent to it.
+    retries: VecDeque<EventArray>,
+    /// The filter deciding which arrays this output is sent, if any.
+    filter: Option<Filter>,
+    /// The factory that created this output's sink, if any, used to recreate it when reinstated.
+    factory: Option<SinkFactory>,
+    /// When this output is to be reinstated, if it's quarantined.
+    quarantined_until: Option<Instant>,
+    /// The number of times this output has failed since it last accepted an array.
+    failures: u32,
+    /// The total time this output's sends have spent waiting on its sink.
+    backpressure: Duration,
+}
+
+impl Output {
+    fn new(sink: BufferSender<EventArray>, group: Option<String>) -> Self {
+        Self {
+            sender: Some(Sender::new(sink)),
+            held: None,
+            group,
+            paused: false,
+            retiring: false,
+            priority: DEFAULT_PRIORITY,
+            weight: DEFAULT_WEIGHT,
+            errors: 0,
+            deliveries: 0,
+            transform: None,
+            delivered: VecDeque::new(),
+            on_accept: None,
+            rate_limit: None,
+            snapshot: None,
+            flush_schedule: None,
+            tier: None,
+            retries: VecDeque::new(),
+            filter: None,
+            factory: None,
+            quarantined_until: None,
+            failures: 0,
+            backpressure: Duration::ZERO,
+        }
+    }
+
+    fn with_transform(sink: BufferSender<EventArray>, transform: AsyncTransform) -> Self {
+        Self {
+            transform: Some(transform),
+            ..Self::new(sink, None)
+        }
+    }
+
+    fn with_accept_callback(sink: BufferSender<EventArray>, on_accept: AcceptCallback) -> Self {
+        Self {
+            on_accept: Some(on_accept),
+            ..Self::new(sink, None)
+        }
+    }
+
+    fn with_snapshot(sink: BufferSender<EventArray>, snapshot: EventArray) -> Self {
+        Self {
+            snapshot: Some(snapshot),
+            ..Self::new(sink, None)
+        }
+    }
+
+    fn with_tier(sink: BufferSender<EventArray>, tier: u8) -> Self {
+        Self {
+            tier: Some(tier),
+            ..Self::new(sink, None)
+        }
+    }
+
+    fn with_filter(sink: BufferSender<EventArray>, filter: Filter) -> Self {
+        Self {
+            filter: Some(filter),
+            ..Self::new(sink, None)
+        }
+    }
+
+    fn with_factory(mut factory: SinkFactory) -> Self {
+        let sink = factory();
+        Self {
+            factory: Some(factory),
+            ..Self::new(sink, None)
+        }
+    }
+
+    /// Whether or not this output is sent the given array, according to its filter.
+    fn accepts(&self, events: &EventArray) -> bool {
+        self.filter.as_ref().map_or(true, |filter| filter(events))
+    }
+
+    /// Whether or not this output has retired, i.e. it's retiring and its buffer has drained.
+    fn is_retired(&self) -> bool {
+        self.retiring
+            && self
+                .sender
+                .as_ref()
+                .and_then(|sender| sender.inner.occupancy())
+                .map_or(true, |(used, _)| used == 0)
+    }
+
+    /// Whether or not this output, in and of itself, can miss events sent to it.
+    fn is_lossy(&self) -> bool {
+        self.paused
+            || self.quarantined_until.is_some()
+            || self
+                .rate_limit
+                .as_ref()
+                .map_or(false, |limit| limit.on_exceed != WhenFull::Block)
+            || self
+                .sender
+                .as_ref()
+                .or(self.held.as_ref())
+                .map_or(false, |sender| sender.inner.drops_when_full())
+    }
+
+    /// Whether or not this output can be sent to at all: it's present, not paused, retiring or
+    /// quarantined, and not awaiting replacement.
+    fn is_available(&self) -> bool {
+        self.sender.is_some() && !self.paused && !self.retiring && self.quarantined_until.is_none()
+    }
+
+    /// Whether or not this output's buffer has room for the given array right now, such that sending
+    /// it wouldn't have to wait. Outputs that can't report their occupancy are assumed to have room.
+    fn has_room_for(&self, events: &EventArray) -> bool {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.inner.occupancy())
+            .map_or(true, |(used, max)| {
+                used + cmp::min(max, cmp::max(events.len(), 1)) <= max
+            })
+    }
+
+    /// Describes the state of this output, for diagnostics.
+    ///
+    /// This is only meaningful outside of a send, since outputs with a send in-flight have no
+    /// sender either.
+    fn state(&self) -> &'static str {
+        if self.held.is_some() {
+            "held"
+        } else if self.sender.is_none() {
+            "awaiting_replacement"
+        } else if self.paused {
+            "paused"
+        } else if self.quarantined_until.is_some() {
+            "quarantined"
+        } else {
+            "ready"
+        }
+    }
+}
+
+/// A token bucket limiting the rate of arrays sent to a single output.
+struct RateLimit {
+    per_sec: u32,
+    on_exceed: WhenFull,
+    /// The number of arrays that can be sent right away, which is negative while sends are being
+    /// delayed to respect the limit.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimit {
+    fn new(per_sec: u32, on_exceed: WhenFull) -> Self {
+        Self {
+            per_sec,
+            on_exceed,
+            tokens: f64::from(per_sec),
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token for the next array, returning how long its send must be delayed to respect the
+    /// limit, or `None` if it must be dropped instead.
+    fn acquire(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let rate = f64::from(self.per_sec);
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Some(Duration::ZERO)
+        } else if self.on_exceed == WhenFull::Block {
+            self.tokens -= 1.0;
+            Some(Duration::from_secs_f64(-self.tokens / rate))
+        } else {
+            None
+        }
+    }
+}
+
+/// An output which is delivered each array in a form other than its own copy of it.
+///
+/// Like a shadow's, the sink is locked for as long as a send to it is in-flight.
+enum EncodedOutput {
+    /// Delivered each array encoded as bytes.
+    Bytes {
+        sink: Arc<Mutex<Box<dyn BytesSink>>>,
+        encoder: Encoder,
+    },
+    /// Delivered each array shared with every other shared output.
+    Shared(Arc<Mutex<Box<dyn SharedSink>>>),
+}
+
+impl EncodedOutput {
+    fn bytes(sink: Box<dyn BytesSink>, encoder: Encoder) -> Self {
+        Self::Bytes {
+            sink: Arc::new(Mutex::new(sink)),
+            encoder,
+        }
+    }
+
+    fn shared(sink: Box<dyn SharedSink>) -> Self {
+        Self::Shared(Arc::new(Mutex::new(sink)))
+    }
+}
+
+/// The send of a single array to an encoded output.
+type EncodedSend = BoxFuture<'static, crate::Result<()>>;
+
+/// An output which receives a copy of every array sent to another output, without affecting it.
+///
+/// If the output it shadows is removed, the shadow simply stops receiving arrays. A shadow without
+/// a target is the overflow output, which instead receives the arrays other outputs failed to.
+struct Shadow {
+    target: Option<ComponentKey>,
+    /// The sender for this shadow, which is locked for as long as a send to it is in-flight.
+    sender: Arc<Mutex<BufferSender<EventArray>>>,
+    counters: Arc<ShadowCounters>,
+}
+
+#[derive(Default)]
+struct ShadowCounters {
+    sent: AtomicU64,
+    errors: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Shadow {
+    fn new(sink: BufferSender<EventArray>, target: Option<ComponentKey>) -> Self {
+        Self {
+            target,
+            sender: Arc::new(Mutex::new(sink)),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Sends the given events to this shadow in the background, or drops them if a previous send
+    /// is still in-flight.
+    fn send(&self, events: EventArray) {
+        if let Ok(mut sender) = Arc::clone(&self.sender).try_lock_owned() {
+            let counters = Arc::clone(&self.counters);
+            tokio::spawn(async move {
+                let result = match sender.send(events).await {
+                    Ok(()) => sender.flush().await,
+                    Err(()) => Err(()),
+                };
+                let counter = if result.is_ok() {
+                    &counters.sent
+                } else {
+                    &counters.errors
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        } else {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn stats(&self) -> ShadowStats {
+        ShadowStats {
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            errors: self.counters.errors.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the counters of this shadow. Sends still in-flight are counted once they complete.
+    fn reset_stats(&self) {
+        self.counters.sent.store(0, Ordering::Relaxed);
+        self.counters.errors.store(0, Ordering::Relaxed);
+        self.counters.dropped.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The time constant over which the throughput estimate decays.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+
+/// A cheap, exponentially smoothed estimate of the rate at which events are sent.
+///
+/// Every event sent adds to the rate, which otherwise decays over time, such that a steady rate of
+/// events converges on that rate within a few multiples of `THROUGHPUT_WINDOW`.
+#[derive(Debug, Default)]
+struct ThroughputTracker {
+    /// The estimated rate, in events per second, as of the last update.
+    rate: f64,
+    last_update: Option<Instant>,
+}
+
+impl ThroughputTracker {
+    fn rate_at(&self, now: Instant) -> f64 {
+        self.last_update.map_or(0.0, |last_update| {
+            let elapsed = now.saturating_duration_since(last_update).as_secs_f64();
+            self.rate * (-elapsed / THROUGHPUT_WINDOW.as_secs_f64()).exp()
+        })
+    }
+
+    #[allow(clippy::cast_precision_loss)] // the rate is only an estimate
+    fn record(&mut self, events: usize) {
+        let now = Instant::now();
+        self.rate = self.rate_at(now) + events as f64 / THROUGHPUT_WINDOW.as_secs_f64();
+        self.last_update = Some(now);
+    }
+}
+
+/// Limits how often errors are logged, independently for each output.
+struct ErrorLogLimiter {
+    interval: Duration,
+    /// When an error was last logged for each output, and how many have been suppressed since.
+    outputs: HashMap<ComponentKey, (Instant, u64)>,
+}
+
+impl ErrorLogLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            outputs: HashMap::new(),
+        }
+    }
+
+    /// Records an error for the given output.
+    ///
+    /// If the error should be logged, returns the number of errors suppressed for the output since
+    /// one was last logged. Otherwise, returns `None`.
+    fn record(&mut self, id: &ComponentKey) -> Option<u64> {
+        let now = Instant::now();
+        if let Some((last_logged, suppressed)) = self.outputs.get_mut(id) {
+            if now.duration_since(*last_logged) < self.interval {
+                *suppressed += 1;
+                None
+            } else {
+                *last_logged = now;
+                Some(mem::take(suppressed))
+            }
+        } else {
+            self.outputs.insert(id.clone(), (now, 0));
+            Some(0)
+        }
+    }
+}
+
+struct Sender {
+    inner: BufferSender<EventArray>,
+    input: Option<EventArray>,
+}
+
+impl Sender {
+    fn new(inner: BufferSender<EventArray>) -> Self {
+        Self { inner, input: None }
+    }
+
+    /// Sends the armed input, if any, returning whether it was dropped because the buffer was full.
+    ///
+    /// If the buffer is closed before the input is sent, the input is handed back as the error.
+    async fn flush(&mut self) -> Result<bool, Option<EventArray>> {
+        let mut dropped = false;
+        if let Some(input) = self.input.take() {
+            dropped = self.inner.send_reporting_drop(input).await.map_err(Some)?;
+            self.inner.flush().await.map_err(|()| None)?;
+        }
+        Ok(dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::mem;
+    use std::num::NonZeroUsize;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::{future::BoxFuture, poll, stream, StreamExt};
+    use smallvec::smallvec;
+    use tokio::{
+        sync::{mpsc::error::SendError, watch},
+        task::JoinHandle,
+        time::{advance, sleep, timeout, Instant},
+    };
+    use tokio_test::{assert_pending, assert_ready, task::spawn};
+    use tracing::{
+        field::{Field, Visit},
+        Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        Layer, Registry,
+    };
+    use value::Value;
+    use vector_buffers::{
+        topology::{
+            builder::TopologyBuilder,
+            channel::{BufferReceiver, BufferSender},
+        },
+        WhenFull,
+    };
+
+    use super::{
+        AcceptCallback, AsyncTransform, BytesSink, ControlChannel, ControlClosedBehavior,
+        ControlMessage, ControlMessageDescriptor, DropReason, Encoder, ErrorLogLimiter,
+        EventArrayCounts, Fanout, FanoutControl, FanoutError, FanoutErrorPolicy, FanoutMode,
+        FanoutSnapshot, Filter, RoundRobin, RoutingConfig, RoutingSelection, RoutingStrategy,
+        ShadowStats, SharedSink, SingleSinkErrorPolicy, SinkFactory, StatsSnapshot, TierPolicy,
+        Weighted, WorkStealing, DEFAULT_PRIORITY, DEFAULT_WEIGHT,
+    };
+    use crate::event::{BatchStatus, Event, EventArray, LogEvent, Metric, MetricKind, MetricValue};
+    use crate::test_util::{collect_ready, collect_ready_events};
+    use crate::{
+        config::{ComponentKey, DataType},
+        event::EventContainer,
+    };
+
+    /// Records the fields of every event, formatted with their `Debug` implementation unless they're
+    /// strings.
+    struct EventRecorder(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    impl<S: Subscriber> Layer<S> for EventRecorder {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            let mut visitor = FieldVisitor::default();
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.0);
+        }
+    }
+
+    #[derive(Default)]
+    struct FieldVisitor(HashMap<String, String>);
+
+    impl Visit for FieldVisitor {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    async fn build_sender_pair(
+        capacity: usize,
+    ) -> (BufferSender<EventArray>, BufferReceiver<EventArray>) {
+        TopologyBuilder::standalone_memory(
+            NonZeroUsize::new(capacity).expect("capacity must be nonzero"),
+            WhenFull::Block,
+        )
+        .await
+    }
+
+    async fn build_sender_pairs(
+        capacities: &[usize],
+    ) -> Vec<(BufferSender<EventArray>, BufferReceiver<EventArray>)> {
+        let mut pairs = Vec::new();
+        for capacity in capacities {
+            pairs.push(build_sender_pair(*capacity).await);
+        }
+        pairs
+    }
+
+    async fn fanout_from_senders(
+        capacities: &[usize],
+    ) -> (Fanout, ControlChannel, Vec<BufferReceiver<EventArray>>) {
+        let pairs = build_sender_pairs(capacities).await;
+
+        let mut sinks = Vec::new();
+        let mut receivers = Vec::new();
+        for (i, (sender, receiver)) in pairs.into_iter().enumerate() {
+            sinks.push((ComponentKey::from(i.to_string()), sender));
+            receivers.push(receiver);
+        }
+
+        let (fanout, control) = Fanout::with_sinks(sinks).expect("output IDs should be unique");
+        (fanout, control, receivers)
+    }
+
+    async fn add_sender_to_fanout(
+        fanout: &mut Fanout,
+        receivers: &mut Vec<BufferReceiver<EventArray>>,
+        sender_id: usize,
+        capacity: usize,
+    ) {
+        let (sender, receiver) = build_sender_pair(capacity).await;
+        receivers.push(receiver);
+
+        fanout.add(ComponentKey::from(sender_id.to_string()), sender);
+    }
+
+    async fn add_control_sender(
+        control: &ControlChannel,
+        receivers: &mut Vec<BufferReceiver<EventArray>>,
+        sender_id: usize,
+        capacity: usize,
+    ) {
+        let (sender, receiver) = build_sender_pair(capacity).await;
+        receivers.push(receiver);
+
+        control
+            .send(ControlMessage::Add(
+                ComponentKey::from(sender_id.to_string()),
+                sender,
+            ))
+            .expect("sending control message should not fail");
+    }
+
+    fn remove_sender_from_fanout(control: &ControlChannel, sender_id: usize) {
+        control
+            .send(ControlMessage::Remove(ComponentKey::from(
+                sender_id.to_string(),
+            )))
+            .expect("sending control message should not fail");
+    }
+
+    async fn replace_sender_in_fanout(
+        control: &ControlChannel,
+        receivers: &mut Vec<BufferReceiver<EventArray>>,
+        sender_id: usize,
+        capacity: usize,
+    ) -> BufferReceiver<EventArray> {
+        let (sender, receiver) = build_sender_pair(capacity).await;
+        let old_receiver = mem::replace(&mut receivers[sender_id], receiver);
+
+        control
+            .send(ControlMessage::Replace(
+                ComponentKey::from(sender_id.to_string()),
+                None,
+            ))
+            .expect("sending control message should not fail");
+
+        control
+            .send(ControlMessage::Replace(
+                ComponentKey::from(sender_id.to_string()),
+                Some(sender),
+            ))
+            .expect("sending control message should not fail");
+
+        old_receiver
+    }
+
+    async fn start_sender_replace(
+        control: &ControlChannel,
+        receivers: &mut Vec<BufferReceiver<EventArray>>,
+        sender_id: usize,
+        capacity: usize,
+    ) -> (BufferReceiver<EventArray>, BufferSender<EventArray>) {
+        let (sender, receiver) = build_sender_pair(capacity).await;
+        let old_receiver = mem::replace(&mut receivers[sender_id], receiver);
+
+        control
+            .send(ControlMessage::Replace(
+                ComponentKey::from(sender_id.to_string()),
+                None,
+            ))
+            .expect("sending control message should not fail");
+
+        (old_receiver, sender)
+    }
+
+    fn finish_sender_replace(
+        control: &ControlChannel,
+        sender_id: usize,
+        sender: BufferSender<EventArray>,
+    ) {
+        control
+            .send(ControlMessage::Replace(
+                ComponentKey::from(sender_id.to_string()),
+                Some(sender),
+            ))
+            .expect("sending control message should not fail");
+    }
+
+    fn unwrap_log_event_message<E>(event: E) -> String
+    where
+        E: EventContainer,
+    {
+        let event = event
+            .into_events()
+            .next()
+            .expect("must have at least one event");
+        let event = event.into_log();
+        event
+            .get("message")
+            .and_then(Value::as_bytes)
+            .and_then(|b| String::from_utf8(b.to_vec()).ok())
+            .expect("must be valid log event with `message` field")
+    }
+
+    #[tokio::test]
+    async fn fanout_writes_to_all() {
+        let (mut fanout, _, receivers) = fanout_from_senders(&[2, 2]).await;
+        let events = make_event_array(2);
+
+        let clones = events.clone();
+        fanout.send(clones).await;
+
+        for receiver in receivers {
+            assert_eq!(collect_ready(receiver.into_stream()), &[events.clone()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_notready() {
+        let (mut fanout, _, mut receivers) = fanout_from_senders(&[2, 1, 2]).await;
+        let events = make_events(2);
+
+        // First send should immediately complete because all senders have capacity:
+        let mut first_send = spawn(fanout.send(events[0].clone().into()));
+        assert_ready!(first_send.poll());
+        drop(first_send);
+
+        // Second send should return pending because sender B is now full:
+        let mut second_send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(second_send.poll());
+
+        // Now read an item from each receiver to free up capacity for the second sender:
+        for receiver in &mut receivers {
+            assert_eq!(Some(events[0].clone().into()), receiver.next().await);
+        }
+
+        // Now our second send should actually be able to complete:
+        assert_ready!(second_send.poll());
+        drop(second_send);
+
+        // And make sure the second item comes through:
+        for receiver in &mut receivers {
+            assert_eq!(Some(events[1].clone().into()), receiver.next().await);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_backpressure_durations() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[1, 4]).await;
+        let events = make_event_array(1);
+        fanout.send(events.clone()).await;
+
+        // The second send waits on the first output, which is full, until it's read from:
+        let mut send = spawn(fanout.send(events.clone()));
+        assert_pending!(send.poll());
+        advance(Duration::from_secs(3)).await;
+        assert_eq!(receivers[0].next().await, Some(events.clone()));
+        assert_ready!(send.poll());
+        drop(send);
+        assert_eq!(
+            fanout.backpressure_durations(),
+            vec![
+                (ComponentKey::from("0"), Duration::from_secs(3)),
+                (ComponentKey::from("1"), Duration::ZERO),
+            ]
+        );
+        assert_eq!(
+            fanout.stats_guard().backpressure,
+            HashMap::from([
+                (ComponentKey::from("0"), Duration::from_secs(3)),
+                (ComponentKey::from("1"), Duration::ZERO),
+            ])
+        );
+
+        // Resetting the stats resets every output's time:
+        fanout.reset_stats();
+        assert_eq!(
+            fanout.backpressure_durations(),
+            vec![
+                (ComponentKey::from("0"), Duration::ZERO),
+                (ComponentKey::from("1"), Duration::ZERO),
+            ]
+        );
+
+        // As does replacing the first output's sink, once it's held up another send:
+        let mut send = spawn(fanout.send(events.clone()));
+        assert_pending!(send.poll());
+        advance(Duration::from_secs(2)).await;
+        assert_eq!(receivers[0].next().await, Some(events.clone()));
+        assert_ready!(send.poll());
+        drop(send);
+        assert_eq!(
+            fanout.backpressure_durations()[0],
+            (ComponentKey::from("0"), Duration::from_secs(2))
+        );
+        let (sender, _receiver) = build_sender_pair(1).await;
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("0"), None))
+            .unwrap();
+        control
+            .send(ControlMessage::Replace(
+                ComponentKey::from("0"),
+                Some(sender),
+            ))
+            .unwrap();
+        fanout.control_quiescent().await;
+        assert_eq!(
+            fanout.backpressure_durations(),
+            vec![
+                (ComponentKey::from("0"), Duration::ZERO),
+                (ComponentKey::from("1"), Duration::ZERO),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_grow() {
+        let (mut fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(3);
+
+        // Send in the first two events to our initial two senders:
+        fanout.send(events[0].clone().into()).await;
+        fanout.send(events[1].clone().into()).await;
+
+        // Now add a third sender:
+        add_sender_to_fanout(&mut fanout, &mut receivers, 2, 4).await;
+
+        // Send in the last event which all three senders will now get:
+        fanout.send(events[2].clone().into()).await;
+
+        // Make sure the first two senders got all three events, but the third sender only got the
+        // last event:
+        let expected_events = [&events, &events, &events[2..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_shrink() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(3);
+
+        // Send in the first two events to our initial two senders:
+        fanout.send(events[0].clone().into()).await;
+        fanout.send(events[1].clone().into()).await;
+
+        // Now remove the second sender:
+        remove_sender_from_fanout(&control, 1);
+
+        // Send in the last event which only the first sender will get:
+        fanout.send(events[2].clone().into()).await;
+
+        // Make sure the first sender got all three events, but the second sender only got the first two:
+        let expected_events = [&events, &events[..2]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_control_on_flush() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_control_on_flush(true);
+        let events = make_events(3);
+
+        fanout.send(events[0].clone().into()).await;
+
+        // Remove the second sender mid-batch, which shouldn't take effect until the flush:
+        remove_sender_from_fanout(&control, 1);
+        fanout.send(events[1].clone().into()).await;
+        fanout.flush();
+
+        // Only the first sender gets the last event, sent after the flush:
+        fanout.send(events[2].clone().into()).await;
+
+        let expected_events = [&events, &events[..2]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_cooperative_send() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[4; 64]).await;
+        let mut fanout = fanout.with_cooperative_send(8);
+        let events = make_events(2);
+        let mut streams = receivers
+            .into_iter()
+            .map(|receiver| Box::pin(receiver.into_stream()))
+            .collect::<Vec<_>>();
+
+        // Each poll of the send only delivers the array to the next chunk of outputs, and the send
+        // completes with the last chunk:
+        let mut send = spawn(fanout.send(events[0].clone().into()));
+        for chunk in 0..8 {
+            if chunk < 7 {
+                assert_pending!(send.poll());
+            } else {
+                assert_ready!(send.poll());
+            }
+            for (i, stream) in streams.iter_mut().enumerate() {
+                let received = poll!(stream.next());
+                assert_eq!(
+                    received.is_ready(),
+                    i / 8 == chunk,
+                    "output {i} after {chunk}"
+                );
+            }
+        }
+        drop(send);
+
+        // Every output gets the rest of the arrays as usual:
+        fanout.send(events[1].clone().into()).await;
+        for stream in streams {
+            assert_eq!(collect_ready_events(stream), &events[1..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_cooperative_send_rotates() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[8; 4]).await;
+        let mut fanout = fanout.with_cooperative_send(1);
+        let mut streams = receivers
+            .into_iter()
+            .map(|receiver| Box::pin(receiver.into_stream()))
+            .collect::<Vec<_>>();
+
+        // Each send polls one output further along first, so the first output isn't always the
+        // first to be handed the array:
+        for first in 0..6 {
+            let mut send = spawn(fanout.send(make_event_array(1)));
+            assert_pending!(send.poll());
+            for (i, stream) in streams.iter_mut().enumerate() {
+                let received = poll!(stream.next());
+                assert_eq!(
+                    received.is_ready(),
+                    i == first % 4,
+                    "output {i} on send {first}"
                 );
             }
-            None => panic!("Pausing unknown sink from fanout: {id}"),
+            while send.poll().is_pending() {}
+            for stream in &mut streams {
+                let _ = poll!(stream.next());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_cooperative_send_early_chunk_woken() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[1, 1]).await;
+        let mut fanout = fanout.with_cooperative_send(1);
+        let events = make_events(2);
+        let mut streams = receivers
+            .into_iter()
+            .map(|receiver| Box::pin(receiver.into_stream()))
+            .collect::<Vec<_>>();
+
+        // Fill both outputs, so the next send has to wait on them:
+        fanout.send(events[0].clone().into()).await;
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+
+        // The output polled in the first chunk becomes ready while the send is yielding between
+        // chunks, and the other output completes with the last chunk. The first output's wake-up
+        // was absorbed by the yield, so the send must wake itself rather than wait for another:
+        for stream in &mut streams {
+            assert!(poll!(stream.next()).is_ready());
+        }
+        assert_pending!(send.poll());
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+
+        for stream in streams {
+            assert_eq!(collect_ready_events(stream), &events[1..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_coalesced_wakeups() {
+        let (mut fanout, _control, receivers) = fanout_from_senders(&[1; 64]).await;
+        let events = make_events(2);
+        let mut streams = receivers
+            .into_iter()
+            .map(|receiver| Box::pin(receiver.into_stream()))
+            .collect::<Vec<_>>();
+
+        // Fill every output, so the next send has to wait on all of them:
+        fanout.send(events[0].clone().into()).await;
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+
+        // Every output becoming ready at once wakes the send, but a single poll is enough to
+        // complete it, rather than one per output:
+        for stream in &mut streams {
+            assert!(poll!(stream.next()).is_ready());
+        }
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+
+        for stream in streams {
+            assert_eq!(collect_ready_events(stream), &events[1..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_control_quiescent() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+
+        // Control messages sent from another task are all applied, without sending anything:
+        let (sender, receiver) = build_sender_pair(4).await;
+        receivers.push(receiver);
+        let reconfigure = tokio::spawn(async move {
+            for message in [
+                ControlMessage::Add(ComponentKey::from("2"), sender),
+                ControlMessage::Remove(ComponentKey::from("0")),
+                ControlMessage::Reorder(vec![ComponentKey::from("2")]),
+            ] {
+                control
+                    .send(message)
+                    .expect("sending control message should not fail");
+                tokio::task::yield_now().await;
+            }
+            control
+        });
+        fanout.control_quiescent().await;
+        let _control = reconfigure.await.unwrap();
+
+        let outputs = fanout
+            .occupancy()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            vec![ComponentKey::from("2"), ComponentKey::from("1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_len() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4, 4]).await;
+        assert_eq!(fanout.len(), 2);
+
+        // Outputs awaiting replacement are still counted:
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("0"), None))
+            .expect("sending control message should not fail");
+        fanout.control_quiescent().await;
+        assert_eq!(fanout.len(), 2);
+
+        for id in ["0", "1"] {
+            control
+                .send(ControlMessage::Remove(ComponentKey::from(id)))
+                .expect("sending control message should not fail");
+        }
+        fanout.control_quiescent().await;
+        assert_eq!(fanout.len(), 0);
+        assert!(fanout.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fanout_output_ids() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        add_control_sender(&control, &mut receivers, 2, 4).await;
+        remove_sender_from_fanout(&control, 0);
+        add_control_sender(&control, &mut receivers, 0, 4).await;
+        fanout.control_quiescent().await;
+
+        let ids = fanout.output_ids().cloned().collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "0"].map(ComponentKey::from));
+    }
+
+    #[tokio::test]
+    async fn fanout_retire() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(4);
+
+        fanout.send(events[0].clone().into()).await;
+        fanout.send(events[1].clone().into()).await;
+
+        // The retired output misses everything sent from now on, but stays in place while its
+        // buffer still holds the first two arrays:
+        control
+            .send(ControlMessage::Retire(ComponentKey::from("1")))
+            .expect("sending control message should not fail");
+        fanout.send(events[2].clone().into()).await;
+        assert_eq!(fanout.len(), 2);
+
+        // Once they've drained, it's removed, closing its buffer:
+        let mut retired = receivers.pop().unwrap().into_stream();
+        assert_eq!(collect_ready_events(&mut retired), &events[..2]);
+        fanout.send(events[3].clone().into()).await;
+        assert_eq!(
+            fanout.output_ids().collect::<Vec<_>>(),
+            [&ComponentKey::from("0")]
+        );
+        assert_eq!(retired.next().await, None);
+
+        assert_eq!(
+            collect_ready_events(receivers.pop().unwrap().into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_try_add() {
+        let (mut fanout, _control, _receivers) = fanout_from_senders(&[4]).await;
+
+        let id = ComponentKey::from("1");
+        let (sender, _receiver) = build_sender_pair(4).await;
+        assert_eq!(fanout.try_add(id.clone(), sender), Ok(()));
+        let (duplicate_sender, _duplicate_receiver) = build_sender_pair(4).await;
+        assert_eq!(
+            fanout.try_add(id.clone(), duplicate_sender),
+            Err(FanoutError::DuplicateOutput { id })
+        );
+        assert_eq!(fanout.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fanout_contains() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4, 4]).await;
+        let (shadow_sender, _shadow_receiver) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::Replace(ComponentKey::from("0"), None),
+            ControlMessage::AddShadow(
+                ComponentKey::from("shadow"),
+                shadow_sender,
+                ComponentKey::from("1"),
+            ),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        fanout.control_quiescent().await;
+
+        for id in ["0", "1", "shadow"] {
+            assert!(fanout.contains(&ComponentKey::from(id)));
+        }
+        assert!(!fanout.contains(&ComponentKey::from("2")));
+    }
+
+    #[tokio::test]
+    async fn fanout_remove_unknown() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
+
+        // Removing an output twice, as racing reconfigurations might, doesn't stop the others from
+        // getting events, whether or not a send is in-flight:
+        remove_sender_from_fanout(&control, 1);
+        remove_sender_from_fanout(&control, 1);
+        fanout.send(events[0].clone().into()).await;
+        assert!(!fanout.try_remove(&ComponentKey::from("1")));
+        fanout.send(events[1].clone().into()).await;
+
+        assert_eq!(fanout.output_ids().count(), 1);
+        assert_eq!(
+            collect_ready_events(receivers.into_iter().next().unwrap().into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_replace_removed() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
+
+        let removed = ComponentKey::from("1");
+        let (sender, _receiver) = build_sender_pair(4).await;
+        assert_eq!(
+            fanout.try_replace(&ComponentKey::from("9"), sender),
+            Err(FanoutError::UnknownOutput {
+                id: ComponentKey::from("9")
+            })
+        );
+
+        // A reload replacing an output that was concurrently removed doesn't panic, and leaves the
+        // remaining outputs in place:
+        fanout.send(events[0].clone().into()).await;
+        remove_sender_from_fanout(&control, 1);
+        let (sender, _receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Replace(removed.clone(), Some(sender)))
+            .expect("sending control message should not fail");
+        fanout.send(events[1].clone().into()).await;
+
+        assert!(!fanout.contains(&removed));
+        assert_eq!(
+            collect_ready_events(receivers.into_iter().next().unwrap().into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_clear() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let mut fanout = fanout.with_strategy(Box::new(RoundRobin::default()));
+        let events = make_events(3);
+
+        fanout.send(events[0].clone().into()).await;
+        control
+            .send(ControlMessage::Clear)
+            .expect("sending control message should not fail");
+        fanout.send(events[1].clone().into()).await;
+        assert!(fanout.is_empty());
+
+        // Only the first output got an array, and none get anything after the clear:
+        let expected_events = [&events[..1], &[], &[]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+
+        // Outputs added afterwards start from the first again:
+        let mut receivers = Vec::new();
+        add_sender_to_fanout(&mut fanout, &mut receivers, 3, 4).await;
+        add_sender_to_fanout(&mut fanout, &mut receivers, 4, 4).await;
+        fanout.send(events[2].clone().into()).await;
+
+        let expected_events = [&events[2..], &[]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_batch() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let events = make_events(2);
+
+        fanout.send(events[0].clone().into()).await;
+
+        // Swapping the first output for a new one in a single batch never sends an array to both or
+        // neither of them:
+        let (sender, receiver) = build_sender_pair(4).await;
+        receivers.push(receiver);
+        let batch = ControlMessage::Batch(vec![
+            ControlMessage::Add(ComponentKey::from("1"), sender),
+            ControlMessage::Remove(ComponentKey::from("0")),
+        ]);
+        assert_eq!(fanout.validate_batch(&[batch.descriptor()]), Ok(()));
+        control
+            .send(batch)
+            .expect("sending control message should not fail");
+        fanout.send(events[1].clone().into()).await;
+
+        assert_eq!(
+            fanout.output_ids().cloned().collect::<Vec<_>>(),
+            vec![ComponentKey::from("1")]
+        );
+        let expected_events = [&events[..1], &events[1..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_from_sinks() {
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            build_sender_pairs(&[4, 4, 4]).await.into_iter().unzip();
+        let (mut fanout, _control) = Fanout::from_sinks(
+            senders
+                .into_iter()
+                .enumerate()
+                .map(|(i, sender)| (ComponentKey::from(i.to_string()), sender)),
+        );
+        let events = make_events(2);
+
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+        }
+
+        assert_eq!(fanout.len(), 3);
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_acked_control() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+        let events = make_events(2);
+
+        // The add is only acknowledged once the fanout has applied it:
+        let (sender, receiver) = build_sender_pair(4).await;
+        receivers.push(receiver);
+        let mut ack = control
+            .send_acked(ControlMessage::Add(ComponentKey::from("1"), sender))
+            .expect("sending control message should not fail");
+        assert!(ack.try_recv().is_err());
+        fanout.send(events[0].clone().into()).await;
+        assert_eq!(ack.try_recv(), Ok(()));
+        assert_eq!(fanout.len(), 2);
+
+        // Adding a duplicate output is acknowledged all the same, once it's been ignored:
+        let (sender, _receiver) = build_sender_pair(4).await;
+        let mut ack = control
+            .send_acked(ControlMessage::Add(ComponentKey::from("1"), sender))
+            .expect("sending control message should not fail");
+        fanout.send(events[1].clone().into()).await;
+        assert_eq!(ack.try_recv(), Ok(()));
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_send_confirmed() {
+        let (mut fanout, _control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(1);
+
+        // Both outputs accept the array straight away, but it's only confirmed once both of their
+        // sinks are done with it:
+        let mut send = spawn(fanout.send_confirmed(events[0].clone().into()));
+        assert_pending!(send.poll());
+
+        let first = receivers[0].next().await;
+        assert!(first.is_some());
+        drop(first);
+        assert_pending!(send.poll());
+
+        let second = receivers[1].next().await;
+        assert!(second.is_some());
+        assert_pending!(send.poll());
+        drop(second);
+        assert!(send.is_woken());
+        assert_eq!(assert_ready!(send.poll()), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn fanout_send_confirmed_failed_output() {
+        let (mut fanout, _control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        drop(receivers.pop());
+
+        assert_eq!(
+            fanout.send_confirmed(make_event_array(1)).await,
+            Err(FanoutError::SendFailed {
+                ids: vec![ComponentKey::from("1")]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_send_confirmed_no_outputs() {
+        let (mut fanout, _control) = Fanout::new();
+
+        assert_eq!(
+            fanout.send_confirmed(make_event_array(1)).await,
+            Err(FanoutError::NotDelivered {
+                status: BatchStatus::Errored
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_pause_resume() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 2]).await;
+        let events = make_events(3);
+
+        fanout.send(events[0].clone().into()).await;
+
+        // Pausing the second output holds up the next send until it's resumed:
+        let pause = ControlMessage::Pause(ComponentKey::from("1"));
+        let resume = ControlMessage::Resume(ComponentKey::from("1"));
+        assert_eq!(
+            fanout.validate_batch(&[pause.descriptor(), resume.descriptor()]),
+            Ok(())
+        );
+        control
+            .send(pause)
+            .expect("sending control message should not fail");
+        {
+            let mut send = spawn(fanout.send(events[1].clone().into()));
+            assert_pending!(send.poll());
+
+            control
+                .send(resume)
+                .expect("sending control message should not fail");
+            assert!(send.is_woken());
+            assert_ready!(send.poll());
+        }
+
+        // Pausing the second output while a send to it is in-flight takes effect once the send
+        // completes, and once resumed, the sink is the same one, with all of its buffered events:
+        {
+            let mut send = spawn(fanout.send(events[2].clone().into()));
+            assert_pending!(send.poll());
+            control
+                .send(ControlMessage::Pause(ComponentKey::from("1")))
+                .expect("sending control message should not fail");
+            assert_pending!(send.poll());
+
+            assert_eq!(receivers[1].next().await, Some(events[0].clone().into()));
+            assert!(send.is_woken());
+            assert_ready!(send.poll());
+        }
+        assert_eq!(
+            fanout.validate_batch(&[ControlMessage::Pause(ComponentKey::from("1")).descriptor()]),
+            Err(vec!["Pausing non-existent sink is not valid: 1".to_string()])
+        );
+        control
+            .send(ControlMessage::Resume(ComponentKey::from("1")))
+            .expect("sending control message should not fail");
+        fanout.flush();
+
+        let expected_events = [&events[..], &events[1..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+        assert_eq!(fanout.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fanout_shrink_when_notready() {
+        // This test exercises that when we're waiting for a send to complete, we can correctly
+        // remove a sink whether or not it is the one that the send operation is still waiting on.
+        //
+        // This means that if we remove a sink that a current send is blocked on, we should be able
+        // to immediately proceed.
+        let events = make_events(2);
+        let expected_first_event = unwrap_log_event_message(events[0].clone());
+        let expected_second_event = unwrap_log_event_message(events[1].clone());
+
+        let cases = [
+            // Sender ID to drop, whether the second send should succeed after dropping, and the
+            // final "last event" a receiver should see after the second send:
+            (
+                0,
+                false,
+                [
+                    expected_second_event.clone(),
+                    expected_first_event.clone(),
+                    expected_second_event.clone(),
+                ],
+            ),
+            (
+                1,
+                true,
+                [
+                    expected_second_event.clone(),
+                    expected_second_event.clone(),
+                    expected_second_event.clone(),
+                ],
+            ),
+            (
+                2,
+                false,
+                [
+                    expected_second_event.clone(),
+                    expected_first_event.clone(),
+                    expected_second_event.clone(),
+                ],
+            ),
+        ];
+
+        for (sender_id, should_complete, expected_last_seen) in cases {
+            let (mut fanout, control, mut receivers) = fanout_from_senders(&[2, 1, 2]).await;
+
+            // First send should immediately complete because all senders have capacity:
+            let mut first_send = spawn(fanout.send(events[0].clone().into()));
+            assert_ready!(first_send.poll());
+            drop(first_send);
+
+            // Second send should return pending because sender B is now full:
+            let mut second_send = spawn(fanout.send(events[1].clone().into()));
+            assert_pending!(second_send.poll());
+
+            // Now drop our chosen sender and assert that polling the second send behaves as expected:
+            remove_sender_from_fanout(&control, sender_id);
+
+            if should_complete {
+                assert_ready!(second_send.poll());
+            } else {
+                assert_pending!(second_send.poll());
+            }
+            drop(second_send);
+
+            // Now grab the last value available to each receiver and assert it's the second event.
+            drop(fanout);
+
+            let mut last_seen = Vec::new();
+            for receiver in &mut receivers {
+                let mut events = Vec::new();
+                while let Some(event) = receiver.next().await {
+                    events.insert(0, event);
+                }
+
+                last_seen.push(unwrap_log_event_message(events.remove(0)));
+            }
+
+            assert_eq!(&expected_last_seen[..], &last_seen);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_max_inflight() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[1, 4]).await;
+        let mut fanout = fanout.with_max_inflight(1);
+        let events = make_events(3);
+
+        // The first send fills the first output's buffer:
+        fanout.send(events[0].clone().into()).await;
+
+        // So the second send waits on the first output, until it's removed, which leaves its send
+        // to complete in the background:
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        remove_sender_from_fanout(&control, 0);
+        assert_ready!(send.poll());
+        drop(send);
+
+        // That array is still in-flight, so the third send waits, even though the remaining output
+        // has room for it:
+        let mut send = spawn(fanout.send(events[2].clone().into()));
+        assert_pending!(send.poll());
+        assert_pending!(send.poll());
+
+        // Until the removed output's buffer has room, and its send completes:
+        assert!(receivers[0].next().await.is_some());
+        tokio::task::yield_now().await;
+        assert_ready!(send.poll());
+        drop(send);
+
+        assert_eq!(
+            collect_ready_events(receivers.remove(1).into_stream()),
+            events
+        );
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            &events[1..2]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_no_sinks() {
+        let (mut fanout, _) = Fanout::new();
+        let events = make_events(2);
+
+        fanout.send(events[0].clone().into()).await;
+        fanout.send(events[1].clone().into()).await;
+    }
+
+    #[tokio::test]
+    async fn fanout_replace() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let events = make_events(3);
+
+        // First two sends should immediately complete because all senders have capacity:
+        fanout.send(events[0].clone().into()).await;
+        fanout.send(events[1].clone().into()).await;
+
+        // Replace the first sender with a brand new one before polling again:
+        let old_first_receiver = replace_sender_in_fanout(&control, &mut receivers, 0, 4).await;
+
+        // And do the third send which should also complete since all senders still have capacity:
+        fanout.send(events[2].clone().into()).await;
+
+        // Now make sure that the new "first" sender only got the third event, but that the second and
+        // third sender got all three events:
+        let expected_events = [&events[2..], &events, &events];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+
+        // And make sure our original "first" sender got the first two events:
+        assert_eq!(
+            collect_ready_events(old_first_receiver.into_stream()),
+            &events[..2]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_wait() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(3);
+
+        // First two sends should immediately complete because all senders have capacity:
+        let send1 = Box::pin(fanout.send(events[0].clone().into()));
+        assert_ready!(poll!(send1));
+        let send2 = Box::pin(fanout.send(events[1].clone().into()));
+        assert_ready!(poll!(send2));
+
+        // Now do an empty replace on the second sender, which we'll test to make sure that `Fanout`
+        // doesn't let any writes through until we replace it properly.  We get back the receiver
+        // we've replaced, but also the sender that we want to eventually install:
+        let (old_first_receiver, new_first_sender) =
+            start_sender_replace(&control, &mut receivers, 0, 4).await;
+
+        // Third send should return pending because now we have an in-flight replacement:
+        let mut third_send = spawn(fanout.send(events[2].clone().into()));
+        assert_pending!(third_send.poll());
+
+        // Finish our sender replacement, which should wake up the third send and allow it to
+        // actually complete:
+        finish_sender_replace(&control, 0, new_first_sender);
+        assert!(third_send.is_woken());
+        assert_ready!(third_send.poll());
+
+        // Make sure the original first sender got the first two events, the new first sender got
+        // the last event, and the second sender got all three:
+        assert_eq!(
+            collect_ready_events(old_first_receiver.into_stream()),
+            &events[0..2]
+        );
+
+        let expected_events = [&events[2..], &events];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_pause_group() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let events = make_events(2);
+
+        // Add two more senders, both as members of the same group:
+        for sender_id in 1..3 {
+            let (sender, receiver) = build_sender_pair(4).await;
+            receivers.push(receiver);
+
+            control
+                .send(ControlMessage::AddToGroup(
+                    ComponentKey::from(sender_id.to_string()),
+                    sender,
+                    "g1".to_string(),
+                ))
+                .expect("sending control message should not fail");
+        }
+
+        // Pause the group, and send the first event, which only the ungrouped sender should get:
+        control
+            .send(ControlMessage::PauseGroup("g1".to_string()))
+            .expect("sending control message should not fail");
+        fanout.send(events[0].clone().into()).await;
+
+        // Now resume the group and send the second event, which all senders should get:
+        control
+            .send(ControlMessage::ResumeGroup("g1".to_string()))
+            .expect("sending control message should not fail");
+        fanout.send(events[1].clone().into()).await;
+
+        let expected_events = [&events, &events[1..], &events[1..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_sinks_by_priority() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let events = make_events(1);
+
+        for (sender_id, priority) in [1, 3, 2].into_iter().enumerate() {
+            control
+                .send(ControlMessage::SetPriority(
+                    ComponentKey::from(sender_id.to_string()),
+                    priority,
+                ))
+                .expect("sending control message should not fail");
+        }
+
+        // Control messages are applied when sending, so do a send to make sure they've all landed:
+        fanout.send(events[0].clone().into()).await;
+
+        assert_eq!(
+            fanout.sinks_by_priority(),
+            vec![
+                (ComponentKey::from("1"), 3),
+                (ComponentKey::from("2"), 2),
+                (ComponentKey::from("0"), 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_validate_batch() {
+        let (fanout, _, _receivers) = fanout_from_senders(&[4, 4]).await;
+
+        // A valid batch of control messages, which removes a sink, and then adds it back:
+        let batch = [
+            ControlMessageDescriptor::Remove(ComponentKey::from("0")),
+            ControlMessageDescriptor::Add(ComponentKey::from("0")),
+        ];
+        assert_eq!(fanout.validate_batch(&batch), Ok(()));
+
+        // An invalid batch of control messages, which removes a sink that isn't present:
+        let batch = [
+            ControlMessageDescriptor::Remove(ComponentKey::from("1")),
+            ControlMessageDescriptor::Remove(ComponentKey::from("2")),
+        ];
+        assert_eq!(
+            fanout.validate_batch(&batch),
+            Err(vec!["Removing non-existent sink from fanout: 2".to_string()])
+        );
+
+        // Validating doesn't actually apply anything:
+        assert_eq!(
+            fanout.sinks_by_priority(),
+            vec![(ComponentKey::from("0"), 0), (ComponentKey::from("1"), 0)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_send_timeout() {
+        let (fanout, _, receivers) = fanout_from_senders(&[4, 1]).await;
+        let mut fanout = fanout.with_send_timeout(Duration::from_secs(1));
+        let events = make_events(2);
+
+        // First send should immediately complete because all senders have capacity:
+        fanout.send(events[0].clone().into()).await;
+
+        // Second send can't complete because the second sender is now full, so once the timeout
+        // elapses, the second sender should be dropped and the send completes anyway:
+        fanout.send(events[1].clone().into()).await;
+        assert_eq!(
+            fanout.sinks_by_priority(),
+            vec![(ComponentKey::from("0"), 0)]
+        );
+
+        let expected_events = [&events, &events[..1]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_adjust_weight() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(1);
+
+        // Outputs start with a weight of one, which we raise for the first output, and try to lower
+        // below zero for the second output:
+        control
+            .send(ControlMessage::AdjustWeight(ComponentKey::from("0"), 4))
+            .expect("sending control message should not fail");
+        control
+            .send(ControlMessage::AdjustWeight(ComponentKey::from("1"), -3))
+            .expect("sending control message should not fail");
+
+        // Control messages are applied when sending, so do a send to make sure they've all landed:
+        fanout.send(events[0].clone().into()).await;
+
+        assert_eq!(fanout.weight(&ComponentKey::from("0")), Some(5));
+        assert_eq!(fanout.weight(&ComponentKey::from("1")), Some(0));
+        assert_eq!(fanout.weight(&ComponentKey::from("2")), None);
+    }
+
+    #[tokio::test]
+    async fn fanout_wait_woken_by_each_control_message() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
+
+        fanout.send(events[0].clone().into()).await;
+
+        // Start replacing the first sender, which should block our next send:
+        let (_old_first_receiver, new_first_sender) =
+            start_sender_replace(&control, &mut receivers, 0, 4).await;
+
+        let mut second_send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(second_send.poll());
+
+        // Any control message should wake the send, even if it doesn't complete the replacement, in
+        // which case the send must still be pending, but with its waker registered again:
+        add_control_sender(&control, &mut receivers, 2, 4).await;
+        assert!(second_send.is_woken());
+        assert_pending!(second_send.poll());
+
+        // Finishing the replacement should then wake the send again, allowing it to complete:
+        finish_sender_replace(&control, 0, new_first_sender);
+        assert!(second_send.is_woken());
+        assert_ready!(second_send.poll());
+        drop(second_send);
+
+        let expected_events = [&events[1..], &events, &events[1..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_cancel_replace() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
+
+        // Start replacing the first sender, which should block our next send:
+        let (_old_first_receiver, _new_first_sender) =
+            start_sender_replace(&control, &mut receivers, 0, 4).await;
+
+        let mut first_send = spawn(fanout.send(events[0].clone().into()));
+        assert_pending!(first_send.poll());
+
+        // Cancelling the replacement should remove the output, unblocking the send:
+        control
+            .send(ControlMessage::CancelReplace(ComponentKey::from("0")))
+            .expect("sending control message should not fail");
+        assert!(first_send.is_woken());
+        assert_ready!(first_send.poll());
+        drop(first_send);
+
+        fanout.send(events[1].clone().into()).await;
+
+        assert_eq!(fanout.weight(&ComponentKey::from("0")), None);
+        assert_eq!(
+            collect_ready_events(receivers.remove(1).into_stream()),
+            &events[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+        let events = make_events(2);
+
+        // Adding a duplicate output is ignored, leaving the original in place, as are removing,
+        // replacing and pausing unknown outputs:
+        let (duplicate_sender, _duplicate_receiver) = build_sender_pair(4).await;
+        fanout.add(ComponentKey::from("0"), duplicate_sender);
+        let unknown = ComponentKey::from("9");
+        let (unknown_sender, _unknown_receiver) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::Remove(unknown.clone()),
+            ControlMessage::Replace(unknown.clone(), Some(unknown_sender)),
+            ControlMessage::Replace(unknown.clone(), None),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        fanout.send(events[0].clone().into()).await;
+        assert_eq!(fanout.occupancy().len(), 2);
+
+        // Replacing an output that isn't awaiting replacement swaps in the new sink:
+        let (new_first_sender, new_first_receiver) = build_sender_pair(4).await;
+        let old_first_receiver = mem::replace(&mut receivers[0], new_first_receiver);
+        control
+            .send(ControlMessage::Replace(
+                ComponentKey::from("0"),
+                Some(new_first_sender),
+            ))
+            .expect("sending control message should not fail");
+        fanout.send(events[1].clone().into()).await;
+
+        assert_eq!(
+            collect_ready_events(old_first_receiver.into_stream()),
+            &events[..1]
+        );
+        let expected_events = [&events[1..], &events[..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_cancel_replace() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+
+        // Neither an output that isn't awaiting replacement nor an unknown output is removed:
+        for id in ["0", "9"] {
+            control
+                .send(ControlMessage::CancelReplace(ComponentKey::from(id)))
+                .expect("sending control message should not fail");
+        }
+        let events = make_events(1);
+        fanout.send(events[0].clone().into()).await;
+
+        assert_eq!(fanout.len(), 1);
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_add_shadow() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+
+        // A shadow with the ID of an existing output, or of an unknown target, is ignored:
+        let mut ignored = Vec::new();
+        for (id, target) in [("0", "0"), ("1", "9")] {
+            let (sender, receiver) = build_sender_pair(4).await;
+            control
+                .send(ControlMessage::AddShadow(
+                    ComponentKey::from(id),
+                    sender,
+                    ComponentKey::from(target),
+                ))
+                .expect("sending control message should not fail");
+            ignored.push(receiver);
+        }
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(fanout.len(), 1);
+        for receiver in ignored {
+            assert!(collect_ready(receiver.into_stream()).is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_set_overflow() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+        let (overflow_sender, _overflow_receiver) = build_sender_pair(4).await;
+        let overflow = ComponentKey::from("overflow");
+
+        // An overflow output with the ID of an existing output is ignored, leaving the existing
+        // overflow output in place:
+        let (sender, ignored) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::SetOverflow(overflow.clone(), overflow_sender),
+            ControlMessage::SetOverflow(ComponentKey::from("0"), sender),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        let events = make_events(1);
+        fanout.send(events[0].clone().into()).await;
+
+        assert_eq!(fanout.len(), 1);
+        assert!(fanout.shadow_stats(&overflow).is_some());
+        assert!(collect_ready(ignored.into_stream()).is_empty());
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_encoded_keys() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+        let encoded = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::new(Mutex::new(Vec::new()));
+        let encoded_id = ComponentKey::from("encoded");
+        let shared_id = ComponentKey::from("shared");
+        for message in [
+            ControlMessage::AddEncoded(
+                encoded_id.clone(),
+                Box::new(RecordingBytesSink(Arc::clone(&encoded))),
+                Arc::new(|events: &EventArray| Bytes::from(events.len().to_string())),
+            ),
+            ControlMessage::AddShared(
+                shared_id.clone(),
+                Box::new(RecordingSharedSink(Arc::clone(&shared))),
+            ),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        fanout.send(make_event_array(1)).await;
+
+        // A shadow, a set of outputs or an overflow output using the key of an encoded or shared
+        // output is ignored, just as `validate_batch` rejects it:
+        let mut ignored = Vec::new();
+        let mut messages = Vec::new();
+        let (sender, receiver) = build_sender_pair(4).await;
+        messages.push(ControlMessage::AddShadow(
+            encoded_id.clone(),
+            sender,
+            ComponentKey::from("0"),
+        ));
+        ignored.push(receiver);
+        let (sender, receiver) = build_sender_pair(4).await;
+        let (other_sender, other_receiver) = build_sender_pair(4).await;
+        messages.push(ControlMessage::AddMany(vec![
+            (ComponentKey::from("1"), other_sender),
+            (shared_id.clone(), sender),
+        ]));
+        ignored.extend([receiver, other_receiver]);
+        let (sender, receiver) = build_sender_pair(4).await;
+        messages.push(ControlMessage::SetOverflow(shared_id.clone(), sender));
+        ignored.push(receiver);
+        for message in messages {
+            let descriptor = message.descriptor();
+            assert!(fanout.validate_batch(&[descriptor]).is_err());
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(fanout.len(), 1);
+        assert!(!fanout.contains(&ComponentKey::from("1")));
+        assert!(fanout.shadow_stats(&encoded_id).is_none());
+        assert!(fanout.shadow_stats(&shared_id).is_none());
+        for receiver in ignored {
+            assert!(collect_ready(receiver.into_stream()).is_empty());
+        }
+        assert_eq!(encoded.lock().unwrap().len(), 2);
+        assert_eq!(shared.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_set_priority() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+
+        control
+            .send(ControlMessage::SetPriority(ComponentKey::from("9"), 2))
+            .expect("sending control message should not fail");
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(
+            fanout.sinks_by_priority(),
+            vec![(ComponentKey::from("0"), DEFAULT_PRIORITY)]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_adjust_weight() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+
+        control
+            .send(ControlMessage::AdjustWeight(ComponentKey::from("9"), 2))
+            .expect("sending control message should not fail");
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(
+            fanout.weight(&ComponentKey::from("0")),
+            Some(DEFAULT_WEIGHT)
+        );
+        assert_eq!(fanout.weight(&ComponentKey::from("9")), None);
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_set_rate_limit() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+
+        // Rate limiting an unknown output, such as one just removed, is ignored:
+        control
+            .send(ControlMessage::SetSinkRateLimit(
+                ComponentKey::from("9"),
+                1,
+                WhenFull::DropNewest,
+            ))
+            .expect("sending control message should not fail");
+        let events = make_events(2);
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+        }
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_set_flush_interval() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+
+        // Scheduling flushes of an unknown output, such as one just removed, is ignored:
+        control
+            .send(ControlMessage::SetFlushInterval(
+                ComponentKey::from("9"),
+                Duration::from_secs(1),
+            ))
+            .expect("sending control message should not fail");
+        let events = make_events(1);
+        fanout.send(events[0].clone().into()).await;
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_add_many() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+        let events = make_events(2);
+
+        // All three sinks are added together, by the same control message:
+        let mut outputs = Vec::new();
+        for i in 1..=3 {
+            let (sender, receiver) = build_sender_pair(4).await;
+            outputs.push((ComponentKey::from(i.to_string()), sender));
+            receivers.push(receiver);
+        }
+        control
+            .send(ControlMessage::AddMany(outputs))
+            .expect("sending control message should not fail");
+        fanout.send(events[0].clone().into()).await;
+        assert_eq!(fanout.occupancy().len(), 4);
+
+        // A batch with a key that's already an output, or that's given twice, is rejected entirely:
+        let mut rejected = Vec::new();
+        for batch in [["4", "0"], ["5", "5"]] {
+            let mut outputs = Vec::new();
+            for id in batch {
+                let (sender, receiver) = build_sender_pair(4).await;
+                outputs.push((ComponentKey::from(id), sender));
+                rejected.push(receiver);
+            }
+            control
+                .send(ControlMessage::AddMany(outputs))
+                .expect("sending control message should not fail");
+        }
+        fanout.send(events[1].clone().into()).await;
+        assert_eq!(fanout.occupancy().len(), 4);
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+        for receiver in rejected {
+            assert!(collect_ready_events(receiver.into_stream()).is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_panic_free_mid_send() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[1, 4]).await;
+        let mut fanout = fanout.with_panic_free(true);
+        let events = make_events(2);
+
+        // Fill up the first output, so the next send stays in-flight:
+        fanout.send(events[0].clone().into()).await;
+        let mut second_send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(second_send.poll());
+
+        // Invalid operations applied mid-send are ignored, and the send carries on as before:
+        let unknown = ComponentKey::from("9");
+        let (duplicate_sender, _duplicate_receiver) = build_sender_pair(4).await;
+        let (unknown_sender, _unknown_receiver) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::Add(ComponentKey::from("1"), duplicate_sender),
+            ControlMessage::Remove(unknown.clone()),
+            ControlMessage::Replace(unknown.clone(), Some(unknown_sender)),
+            ControlMessage::Replace(unknown.clone(), None),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        assert_pending!(second_send.poll());
+
+        assert_eq!(
+            receivers[0]
+                .next()
+                .await
+                .map(|array| array.into_events().count()),
+            Some(1)
+        );
+        assert_ready!(second_send.poll());
+        drop(second_send);
+
+        assert_eq!(fanout.occupancy().len(), 2);
+        let expected_events = [&events[1..], &events[..]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_keepalive() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_keepalive(Duration::from_secs(5));
+        let events = make_event_array(1);
+
+        // Send a single array and then go idle, long enough for two keepalives to be sent:
+        let input = stream::iter(vec![events.clone()]).chain(stream::pending());
+        let result = timeout(Duration::from_secs(12), fanout.send_stream(input)).await;
+        assert!(result.is_err());
+
+        let keepalive = EventArray::Logs(Vec::new());
+        for receiver in receivers {
+            assert_eq!(
+                collect_ready(receiver.into_stream()),
+                vec![events.clone(), keepalive.clone(), keepalive.clone()]
+            );
+        }
+
+        // Keepalives aren't counted as arrays sent through the fanout:
+        assert_eq!(fanout.stats().by_type.logs, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_flush_interval() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_keepalive(Duration::from_secs(5));
+        let events = make_event_array(1);
+
+        for (id, interval) in [("0", 1), ("1", 2)] {
+            control
+                .send(ControlMessage::SetFlushInterval(
+                    ComponentKey::from(id),
+                    Duration::from_secs(interval),
+                ))
+                .unwrap();
+        }
+
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(EventRecorder(Arc::clone(&recorded)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // Each output is flushed on its own schedule while the stream is idle, without holding up
+        // the keepalive:
+        let input = stream::iter(vec![events.clone()]).chain(stream::pending());
+        let result = timeout(Duration::from_millis(5500), fanout.send_stream(input)).await;
+        assert!(result.is_err());
+
+        let flushes = |id: &str| {
+            recorded
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|fields| fields["message"] == "Flushing fanout output.")
+                .filter(|fields| fields["output"] == id)
+                .count()
+        };
+        assert_eq!(flushes("0"), 5);
+        assert_eq!(flushes("1"), 2);
+
+        let keepalive = EventArray::Logs(Vec::new());
+        for receiver in receivers {
+            assert_eq!(
+                collect_ready(receiver.into_stream()),
+                vec![events.clone(), keepalive.clone()]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_wait_control_closed() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(1);
+
+        // Start replacing the first sender, but then close the control channel, which means the
+        // replacement can never arrive:
+        let _ = start_sender_replace(&control, &mut receivers, 0, 4).await;
+        drop(control);
+
+        // The send should simply stay pending, rather than spinning forever on the closed channel:
+        let mut send = spawn(fanout.send(events[0].clone().into()));
+        assert_pending!(send.poll());
+        assert_pending!(send.poll());
+    }
+
+    #[tokio::test]
+    async fn fanout_is_lossy() {
+        // A plain broadcast to blocking buffers, which keeps sending to outputs that fail, never
+        // drops events:
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(1);
+        assert!(!fanout.is_lossy());
+
+        // Pausing a group means its outputs miss events:
+        control
+            .send(ControlMessage::AddToGroup(
+                ComponentKey::from("2"),
+                build_sender_pair(4).await.0,
+                "g1".to_string(),
+            ))
+            .expect("sending control message should not fail");
+        control
+            .send(ControlMessage::PauseGroup("g1".to_string()))
+            .expect("sending control message should not fail");
+        fanout.send(events[0].clone().into()).await;
+        assert!(fanout.is_lossy());
+
+        // As does a send timeout, even with nothing paused:
+        let (fanout, _, _receivers) = fanout_from_senders(&[4, 4]).await;
+        let fanout = fanout.with_send_timeout(Duration::from_secs(1));
+        assert!(fanout.is_lossy());
+
+        // As does an error policy which drops or quarantines outputs that fail, but not one which
+        // halts the fanout:
+        for (policy, lossy) in [
+            (FanoutErrorPolicy::DropErroredSink, true),
+            (
+                FanoutErrorPolicy::Quarantine {
+                    initial_backoff: Duration::from_secs(1),
+                    max_backoff: Duration::from_secs(1),
+                },
+                true,
+            ),
+            (FanoutErrorPolicy::FailFast, false),
+        ] {
+            let (fanout, _, _receivers) = fanout_from_senders(&[4, 4]).await;
+            assert_eq!(fanout.with_error_policy(policy).is_lossy(), lossy);
+        }
+
+        // As does an output whose buffer drops the newest arrays when full:
+        let (mut fanout, _, _receivers) = fanout_from_senders(&[4, 4]).await;
+        let (sender, _receiver) = TopologyBuilder::standalone_memory(
+            NonZeroUsize::new(4).expect("capacity must be nonzero"),
+            WhenFull::DropNewest,
+        )
+        .await;
+        fanout.add(ComponentKey::from("2"), sender);
+        assert!(fanout.is_lossy());
+    }
+
+    #[tokio::test]
+    async fn fanout_sink_is_lossy() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let mut fanout = fanout.with_tier_policies(HashMap::from([(1, TierPolicy::BestEffort)]));
+
+        // Rate limiting an output makes it lossy, unless it blocks rather than dropping:
+        for (id, on_exceed) in [("1", WhenFull::DropNewest), ("2", WhenFull::Block)] {
+            control
+                .send(ControlMessage::SetSinkRateLimit(
+                    ComponentKey::from(id),
+                    10,
+                    on_exceed,
+                ))
+                .unwrap();
+        }
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("0")), Some(false));
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("1")), Some(true));
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("2")), Some(false));
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("3")), None);
+        assert!(fanout.is_lossy());
+
+        // Outputs in a best-effort tier are lossy, but those in other tiers aren't:
+        for (id, tier) in [("3", 1), ("4", 2)] {
+            control
+                .send(ControlMessage::AddWithTier(
+                    ComponentKey::from(id),
+                    build_sender_pair(4).await.0,
+                    tier,
+                ))
+                .unwrap();
+        }
+        fanout.send(make_event_array(1)).await;
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("3")), Some(true));
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("4")), Some(false));
+
+        // So are outputs under an error policy which drops outputs that fail:
+        let fanout = fanout.with_error_policy(FanoutErrorPolicy::DropErroredSink);
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("0")), Some(true));
+
+        // A send timeout makes every output lossy:
+        let fanout = fanout
+            .with_error_policy(FanoutErrorPolicy::DropEventContinue)
+            .with_send_timeout(Duration::from_secs(1));
+        assert_eq!(fanout.sink_is_lossy(&ComponentKey::from("0")), Some(true));
+    }
+
+    #[tokio::test]
+    async fn fanout_reorder() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[4, 4, 4, 4]).await;
+        let events = make_events(1);
+
+        // Reorder the first three senders, with an unknown key and a duplicate key thrown in for
+        // good measure, leaving the last sender unlisted:
+        control
+            .send(ControlMessage::Reorder(vec![
+                ComponentKey::from("2"),
+                ComponentKey::from("missing"),
+                ComponentKey::from("0"),
+                ComponentKey::from("2"),
+                ComponentKey::from("1"),
+            ]))
+            .expect("sending control message should not fail");
+
+        fanout.send(events[0].clone().into()).await;
+
+        let order = fanout
+            .sinks_by_priority()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                ComponentKey::from("2"),
+                ComponentKey::from("0"),
+                ComponentKey::from("1"),
+                ComponentKey::from("3"),
+            ]
+        );
+
+        // Reordering doesn't change who receives events:
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), &events[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_stats_by_type() {
+        let (mut fanout, _, _receivers) = fanout_from_senders(&[4, 4]).await;
+
+        fanout.send(make_event_array(2)).await;
+        fanout
+            .send(
+                Event::from(Metric::new(
+                    "counter",
+                    MetricKind::Incremental,
+                    MetricValue::Counter { value: 1.0 },
+                ))
+                .into(),
+            )
+            .await;
+
+        assert_eq!(
+            fanout.stats().by_type,
+            EventArrayCounts {
+                logs: 1,
+                metrics: 1,
+                traces: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_control_closed_fail_closed() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_control_closed_behavior(ControlClosedBehavior::FailClosed);
+        let events = make_events(2);
+
+        fanout.send(events[0].clone().into()).await;
+
+        // Once the control channel is closed, the fanout should close all of its outputs, so the
+        // receivers see the end of their streams after the first event:
+        drop(control);
+        fanout.send(events[1].clone().into()).await;
+
+        for receiver in &mut receivers {
+            assert_eq!(Some(events[0].clone().into()), receiver.next().await);
+            assert_eq!(None, receiver.next().await);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_output_error_not_fatal() {
+        let (mut fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
+
+        // Dropping the receiver for the second sender makes every send to it fail, which shouldn't
+        // stop the first sender from getting events:
+        drop(receivers.pop());
+        fanout.send(events[0].clone().into()).await;
+        fanout.send(events[1].clone().into()).await;
+
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_single_sink_drop_to_empty() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_single_sink_error_policy(SingleSinkErrorPolicy::DropToEmpty);
+        let events = make_events(2);
+
+        // The sole output failing leaves the fanout empty, with sends doing nothing:
+        drop(receivers.pop());
+        fanout.send(events[0].clone().into()).await;
+        assert!(fanout.is_empty());
+        fanout.send(events[0].clone().into()).await;
+
+        // Until another output is added:
+        add_control_sender(&control, &mut receivers, 1, 4).await;
+        fanout.send(events[1].clone().into()).await;
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            &events[1..]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_single_sink_propagate_if_last() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout =
+            fanout.with_single_sink_error_policy(SingleSinkErrorPolicy::PropagateIfLast);
+
+        // The sole output failing is reported, and stops the fanout, keeping the output:
+        drop(receivers.pop());
+        assert_eq!(
+            fanout.try_send(make_event_array(1)).await,
+            Err(FanoutError::SendFailed {
+                ids: vec![ComponentKey::from("0")]
+            })
+        );
+        assert!(fanout.is_halted());
+        assert_eq!(fanout.len(), 1);
+        assert_eq!(
+            fanout.try_send(make_event_array(1)).await,
+            Err(FanoutError::Halted)
+        );
+
+        // Until it's unhalted:
+        control.send(ControlMessage::Unhalt).unwrap();
+        fanout.apply_pending_control_messages();
+        assert!(!fanout.is_halted());
+    }
+
+    #[tokio::test]
+    async fn fanout_single_sink_propagate_ignores_others() {
+        let (fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout =
+            fanout.with_single_sink_error_policy(SingleSinkErrorPolicy::PropagateIfLast);
+
+        // With other outputs left, a failed output is only reported:
+        drop(receivers.pop());
+        assert!(fanout.try_send(make_event_array(1)).await.is_err());
+        assert!(!fanout.is_halted());
+        assert!(fanout.try_send(make_event_array(1)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fanout_single_sink_drop_logged() {
+        let (fanout, _, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_single_sink_error_policy(SingleSinkErrorPolicy::DropToEmpty);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(EventRecorder(Arc::clone(&events)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        drop(receivers.pop());
+        fanout.send(make_event_array(1)).await;
+
+        let events = events.lock().unwrap();
+        let fields = events
+            .iter()
+            .find(|fields| {
+                fields["message"] == "Sole fanout output failed to accept events, removing it."
+            })
+            .expect("removal should be logged");
+        assert_eq!(fields["output"], "0");
+        assert_eq!(fields["phase"], "send");
+        assert_eq!(fields["index"], "0");
+        assert_eq!(fields["remaining"], "0");
+    }
+
+    /// Sends two arrays through a fanout with the given error policy, with the last of its outputs
+    /// closed, returning the fanout, its remaining receivers, and how many arrays it took from the
+    /// stream.
+    async fn send_with_error_policy(
+        capacities: &[usize],
+        policy: FanoutErrorPolicy,
+    ) -> (Fanout, Vec<BufferReceiver<EventArray>>, usize) {
+        let (fanout, _, mut receivers) = fanout_from_senders(capacities).await;
+        let mut fanout = fanout.with_error_policy(policy);
+        drop(receivers.pop());
+
+        let mut taken = 0;
+        let input = stream::iter(make_events(2))
+            .map(EventArray::from)
+            .inspect(|_| taken += 1);
+        fanout.send_stream(input).await;
+        (fanout, receivers, taken)
+    }
+
+    #[tokio::test]
+    async fn fanout_error_policy_fail_fast() {
+        // Nothing more is taken from the stream after a failure until the fanout is unhalted, with
+        // every output kept:
+        for capacities in [&[4][..], &[4, 4]] {
+            let (fanout, control, mut receivers) = fanout_from_senders(capacities).await;
+            let mut fanout = fanout.with_error_policy(FanoutErrorPolicy::FailFast);
+            drop(receivers.pop());
+
+            let taken = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&taken);
+            let input = stream::iter(make_events(2))
+                .map(EventArray::from)
+                .inspect(move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                });
+            let mut send = spawn(fanout.send_stream(input));
+            assert_pending!(send.poll());
+            assert_eq!(taken.load(Ordering::SeqCst), 1);
+
+            control.send(ControlMessage::Unhalt).unwrap();
+            assert_pending!(send.poll());
+            assert_eq!(taken.load(Ordering::SeqCst), 2);
+            drop(send);
+
+            assert!(fanout.is_halted());
+            assert_eq!(fanout.len(), capacities.len());
+            for receiver in receivers {
+                assert_eq!(collect_ready_events(receiver.into_stream()).len(), 2);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_error_policy_fail_fast_send() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_error_policy(FanoutErrorPolicy::FailFast);
+        drop(receivers.pop());
+        let events = make_events(2);
+
+        // Once an output fails, `try_send` fails straight away, without sending anything:
+        fanout.send(events[0].clone().into()).await;
+        assert!(fanout.is_halted());
+        assert_eq!(
+            fanout.try_send(events[1].clone().into()).await,
+            Err(FanoutError::Halted)
+        );
+
+        // While `send` holds on to its array until the fanout is unhalted:
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        assert_pending!(send.poll());
+        control.send(ControlMessage::Unhalt).unwrap();
+        assert_ready!(send.poll());
+        drop(send);
+
+        // The output failing again halts the fanout again:
+        assert!(fanout.is_halted());
+        assert_eq!(fanout.len(), 2);
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_error_policy_fail_fast_control_closed() {
+        // A halted fanout can't be unhalted once its control channel is closed, so failing closed
+        // drops every output, and the held array with them, rather than waiting forever:
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout
+            .with_error_policy(FanoutErrorPolicy::FailFast)
+            .with_control_closed_behavior(ControlClosedBehavior::FailClosed);
+        drop(receivers.pop());
+        let events = make_events(2);
+        fanout.send(events[0].clone().into()).await;
+        assert!(fanout.is_halted());
+
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        drop(control);
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+        assert!(fanout.is_empty());
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            &events[..1]
+        );
+
+        // While failing open lifts the halt, and keeps sending to the current outputs:
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout
+            .with_error_policy(FanoutErrorPolicy::FailFast)
+            .with_control_closed_behavior(ControlClosedBehavior::FailOpen);
+        drop(receivers.pop());
+        fanout.send(events[0].clone().into()).await;
+        assert!(fanout.is_halted());
+
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        drop(control);
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+        assert_eq!(fanout.len(), 2);
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_error_policy_drop_errored_sink() {
+        // The failed output is removed, even if it's the only one:
+        let (fanout, _, taken) =
+            send_with_error_policy(&[4], FanoutErrorPolicy::DropErroredSink).await;
+        assert_eq!(taken, 2);
+        assert!(fanout.is_empty());
+
+        let (fanout, receivers, taken) =
+            send_with_error_policy(&[4, 4], FanoutErrorPolicy::DropErroredSink).await;
+        assert_eq!(taken, 2);
+        assert_eq!(
+            fanout.output_ids().cloned().collect::<Vec<_>>(),
+            vec![ComponentKey::from("0")]
+        );
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()).len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_error_policy_drop_event_continue() {
+        // The failed output is kept, with only the events it failed to accept dropped:
+        let (fanout, _, taken) =
+            send_with_error_policy(&[4], FanoutErrorPolicy::DropEventContinue).await;
+        assert_eq!(taken, 2);
+        assert_eq!(fanout.len(), 1);
+
+        let (fanout, receivers, taken) =
+            send_with_error_policy(&[4, 4], FanoutErrorPolicy::DropEventContinue).await;
+        assert_eq!(taken, 2);
+        assert_eq!(fanout.len(), 2);
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()).len(), 2);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_send_with_deadline() {
+        let (mut fanout, _, receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_event_array(2);
+
+        // Every event sent should carry the deadline through to the sinks:
+        let deadline = Instant::now() + Duration::from_secs(10);
+        fanout.send_with_deadline(events.clone(), deadline).await;
+
+        // Once the deadline has passed, events sent with it are dropped entirely:
+        advance(Duration::from_secs(11)).await;
+        fanout.send_with_deadline(events, deadline).await;
+
+        for receiver in receivers {
+            let received = collect_ready_events(receiver.into_stream());
+            assert_eq!(received.len(), 2);
+            for event in received {
+                assert_eq!(event.metadata().deadline(), Some(deadline));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_to_openmetrics() {
+        let (mut fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
+
+        // Dropping the receiver for the second sender makes every send to it fail:
+        drop(receivers.pop());
+        fanout.send(make_event_array(2)).await;
+
+        let expected = [
+            "# TYPE fanout_outputs gauge",
+            "fanout_outputs 2",
+            "# TYPE fanout_event_arrays_sent counter",
+            r#"fanout_event_arrays_sent_total{type="logs"} 1"#,
+            r#"fanout_event_arrays_sent_total{type="metrics"} 0"#,
+            r#"fanout_event_arrays_sent_total{type="traces"} 0"#,
+            "# TYPE fanout_output_errors counter",
+            r#"fanout_output_errors_total{output="0"} 0"#,
+            r#"fanout_output_errors_total{output="1"} 1"#,
+            "# TYPE fanout_output_buffer_events gauge",
+            r#"fanout_output_buffer_events{output="0"} 2"#,
+            r#"fanout_output_buffer_events{output="1"} 0"#,
+            "# TYPE fanout_output_buffer_max_events gauge",
+            r#"fanout_output_buffer_max_events{output="0"} 4"#,
+            r#"fanout_output_buffer_max_events{output="1"} 4"#,
+            "# EOF",
+            "",
+        ];
+        assert_eq!(fanout.to_openmetrics(), expected.join("\n"));
+    }
+
+    #[tokio::test]
+    async fn fanout_to_dot() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_name("router");
+        let (shadow_sender, _shadow_receiver) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::AdjustWeight(ComponentKey::from("1"), 2),
+            ControlMessage::AddShadow(
+                ComponentKey::from("2"),
+                shadow_sender,
+                ComponentKey::from("0"),
+            ),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(fanout.graph().edges[1].weight, Some(3));
+        let expected = [
+            r#"digraph "router" {"#,
+            r#"    "router" [shape=box];"#,
+            r#"    "0" [label="0\nweight: 1\nstate: ready"];"#,
+            r#"    "router" -> "0" [label="routed"];"#,
+            r#"    "1" [label="1\nweight: 3\nstate: ready"];"#,
+            r#"    "router" -> "1" [label="routed"];"#,
+            r#"    "2" [label="2"];"#,
+            r#"    "0" -> "2" [label="shadow", style=dashed];"#,
+            "}",
+            "",
+        ];
+        assert_eq!(fanout.to_dot(), expected.join("\n"));
+    }
+
+    #[tokio::test]
+    async fn fanout_delivery_counts() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
+
+        fanout.send(events[0].clone().into()).await;
+        assert_eq!(
+            fanout.delivery_counts(),
+            [(ComponentKey::from("0"), 1), (ComponentKey::from("1"), 1)]
+        );
+
+        // Replacing the first output's sink keeps its count, while removing the second output and
+        // adding it again starts its count over:
+        let _ = replace_sender_in_fanout(&control, &mut receivers, 0, 4).await;
+        remove_sender_from_fanout(&control, 1);
+        add_control_sender(&control, &mut receivers, 1, 4).await;
+        fanout.send(events[1].clone().into()).await;
+        assert_eq!(
+            fanout.delivery_counts(),
+            [(ComponentKey::from("0"), 2), (ComponentKey::from("1"), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_shadow() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let events = make_events(2);
+
+        let (shadow_sender, shadow_receiver) = build_sender_pair(4).await;
+        let shadow = ComponentKey::from("shadow");
+        control
+            .send(ControlMessage::AddShadow(
+                shadow.clone(),
+                shadow_sender,
+                ComponentKey::from("0"),
+            ))
+            .expect("sending control message should not fail");
+
+        // Sends to the shadow happen in the background, so let them run before checking on them:
+        fanout.send(events[0].clone().into()).await;
+        tokio::task::yield_now().await;
+        assert_eq!(
+            collect_ready_events(shadow_receiver.into_stream()),
+            &events[..1]
+        );
+
+        // Now that the shadow's receiver is gone, sends to it fail, but that shouldn't affect the
+        // output it shadows:
+        fanout.send(events[1].clone().into()).await;
+        tokio::task::yield_now().await;
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+        assert_eq!(
+            fanout.shadow_stats(&shadow),
+            Some(ShadowStats {
+                sent: 1,
+                errors: 1,
+                dropped: 0
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_tiers() {
+        let (fanout, control) = Fanout::new();
+        let mut fanout = fanout.with_tier_policies(HashMap::from([
+            (1, TierPolicy::AtLeastOnce { max_queued: 4 }),
+            (2, TierPolicy::BestEffort),
+        ]));
+        let events = make_events(3);
+        for (id, tier) in [("0", 1), ("1", 2)] {
+            let (sender, _) = build_sender_pair(4).await;
+            control
+                .send(ControlMessage::AddWithTier(
+                    ComponentKey::from(id),
+                    sender,
+                    tier,
+                ))
+                .unwrap();
+        }
+
+        // Both outputs fail, since their receivers are already gone:
+        fanout.send(events[0].clone().into()).await;
+
+        // Once their sinks are replaced, the at-least-once output gets the array it failed to
+        // accept ahead of the next one, while the best-effort output only gets the next one:
+        let mut receivers = Vec::new();
+        for id in ["0", "1"] {
+            let (sender, receiver) = build_sender_pair(4).await;
+            receivers.push(receiver);
+            control
+                .send(ControlMessage::Replace(ComponentKey::from(id), None))
+                .unwrap();
+            control
+                .send(ControlMessage::Replace(
+                    ComponentKey::from(id),
+                    Some(sender),
+                ))
+                .unwrap();
+        }
+        fanout.send(events[1].clone().into()).await;
+        fanout.send(events[2].clone().into()).await;
+
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            &events[1..]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_freeze_and_snapshot() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_tier_policies(HashMap::from([(
+            1,
+            TierPolicy::AtLeastOnce { max_queued: 4 },
+        )]));
+        let events = make_events(2);
+
+        // The tiered output fails, leaving the array queued for retrying:
+        let (sender, _) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::SetPriority(ComponentKey::from("0"), 3),
+            ControlMessage::SetSinkRateLimit(ComponentKey::from("1"), 5, WhenFull::Block),
+            ControlMessage::AddWithTier(ComponentKey::from("2"), sender, 1),
+        ] {
+            control.send(message).unwrap();
+        }
+        fanout.send(events[0].clone().into()).await;
+
+        let snapshot = fanout.freeze_and_snapshot();
+        assert_eq!(
+            snapshot
+                .outputs
+                .iter()
+                .map(|output| (
+                    output.key.id(),
+                    output.state,
+                    output.priority,
+                    output.rate_limit,
+                    output.tier
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("0", "ready", 3, None, None),
+                ("1", "ready", 0, Some((5, WhenFull::Block)), None),
+                ("2", "ready", 0, None, Some(1)),
+            ]
+        );
+        assert!(snapshot.outputs[..2]
+            .iter()
+            .all(|output| output.buffered.is_empty()));
+        assert_eq!(
+            snapshot.outputs[2].buffered,
+            vec![EventArray::from(events[0].clone())]
+        );
+
+        // Delivery is paused until the outputs are resumed:
+        {
+            let mut send = spawn(fanout.send(events[1].clone().into()));
+            assert_pending!(send.poll());
+            for id in ["0", "1", "2"] {
+                control
+                    .send(ControlMessage::Resume(ComponentKey::from(id)))
+                    .unwrap();
+            }
+            assert_ready!(send.poll());
+        }
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_dead_letter() {
+        let (mut fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let (dead_letter_sender, dead_letter_receiver) = build_sender_pair(4).await;
+        fanout.set_dead_letter(dead_letter_sender);
+        let events = make_events(3);
+
+        // Arrays delivered to every output never reach the dead-letter sink:
+        fanout.send(events[0].clone().into()).await;
+
+        // Once an output fails, each array it fails to accept is sent there instead, in the
+        // background:
+        drop(receivers.pop());
+        fanout.send(events[1].clone().into()).await;
+        fanout.send(events[2].clone().into()).await;
+        tokio::task::yield_now().await;
+        assert_eq!(
+            collect_ready_events(dead_letter_receiver.into_stream()),
+            &events[1..]
+        );
+
+        // A failing dead-letter sink is only logged, with the rest of the fanout unaffected:
+        fanout.send(events[0].clone().into()).await;
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            vec![
+                events[0].clone(),
+                events[1].clone(),
+                events[2].clone(),
+                events[0].clone()
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_dead_letter_blocked() {
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_send_timeout(Duration::from_secs(1));
+        let (dead_letter_sender, dead_letter_receiver) = build_sender_pair(1).await;
+        fanout.set_dead_letter(dead_letter_sender);
+        let events = make_events(3);
+        drop(receivers.pop());
+
+        // A dead-letter sink with a full buffer doesn't hold up sends:
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+        }
+        tokio::task::yield_now().await;
+
+        // Arrays it doesn't accept before the send timeout elapses are dropped:
+        advance(Duration::from_secs(1)).await;
+        tokio::task::yield_now().await;
+        let mut dead_letter = Box::pin(dead_letter_receiver.into_stream());
+        assert_eq!(dead_letter.next().await, Some(events[0].clone().into()));
+        tokio::task::yield_now().await;
+        assert!(poll!(dead_letter.next()).is_pending());
+
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_overflow_and_dead_letter() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let (dead_letter_sender, dead_letter_receiver) = build_sender_pair(4).await;
+        fanout.set_dead_letter(dead_letter_sender);
+        let (overflow_sender, overflow_receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::SetOverflow(
+                ComponentKey::from("overflow"),
+                overflow_sender,
+            ))
+            .expect("sending control message should not fail");
+        let events = make_events(2);
+
+        // Neither gets an array every output accepted, while both get one an output failed to:
+        fanout.send(events[0].clone().into()).await;
+        drop(receivers.pop());
+        fanout.send(events[1].clone().into()).await;
+        tokio::task::yield_now().await;
+
+        assert_eq!(
+            collect_ready_events(overflow_receiver.into_stream()),
+            &events[1..]
+        );
+        assert_eq!(
+            collect_ready_events(dead_letter_receiver.into_stream()),
+            &events[1..]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_overflow_round_robin_paused() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_strategy(Box::new(RoundRobin::default()));
+        let (paused_sender, paused_receiver) = build_sender_pair(4).await;
+        let (overflow_sender, overflow_receiver) = build_sender_pair(4).await;
+        for message in [
+            ControlMessage::AddToGroup(ComponentKey::from("2"), paused_sender, "g1".to_string()),
+            ControlMessage::PauseGroup("g1".to_string()),
+            ControlMessage::SetOverflow(ComponentKey::from("overflow"), overflow_sender),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+
+        // Round-robin takes turns between the outputs that aren't paused, so the paused output
+        // never misses an array, and the overflow isn't sent any:
+        let events = make_events(2);
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+            tokio::task::yield_now().await;
+        }
+
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(collect_ready_events(receiver.into_stream()), &events[i..=i]);
+        }
+        assert!(collect_ready(paused_receiver.into_stream()).is_empty());
+        assert!(collect_ready(overflow_receiver.into_stream()).is_empty());
+        assert!(fanout.stats().drops_by_reason.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fanout_overflow() {
+        let (mut fanout, control) = Fanout::new();
+        let (sender, receiver) = TopologyBuilder::standalone_memory(
+            NonZeroUsize::new(1).expect("capacity must be nonzero"),
+            WhenFull::DropNewest,
+        )
+        .await;
+        fanout.add(ComponentKey::from("0"), sender);
+
+        let (overflow_sender, overflow_receiver) = build_sender_pair(4).await;
+        let overflow = ComponentKey::from("overflow");
+        control
+            .send(ControlMessage::SetOverflow(
+                overflow.clone(),
+                overflow_sender,
+            ))
+            .expect("sending control message should not fail");
+
+        // The output only has room for the first array, so the rest land in the overflow instead.
+        // Sends to the overflow happen in the background, so let each run before the next:
+        let events = make_events(3);
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(collect_ready_events(receiver.into_stream()), &events[..1]);
+        assert_eq!(
+            collect_ready_events(overflow_receiver.into_stream()),
+            &events[1..]
+        );
+        assert_eq!(
+            fanout.shadow_stats(&overflow),
+            Some(ShadowStats {
+                sent: 2,
+                errors: 0,
+                dropped: 0
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_routing_strategy() {
+        /// Sends single events to the first output, and larger arrays to the last output.
+        struct BySize;
+
+        impl RoutingStrategy for BySize {
+            fn select(&mut self, sinks: &[ComponentKey], array: &EventArray) -> RoutingSelection {
+                if array.len() > 1 {
+                    smallvec![sinks.len() - 1]
+                } else {
+                    smallvec![0]
+                }
+            }
+        }
+
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_strategy(Box::new(BySize));
+        let events = make_events(5);
+
+        fanout.send(events[0].clone().into()).await;
+        let logs = events[1..4].iter().cloned().map(Event::into_log);
+        fanout.send(EventArray::Logs(logs.collect())).await;
+        fanout.send(events[4].clone().into()).await;
+
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            vec![events[0].clone(), events[4].clone()]
+        );
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            &events[1..4]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_avg_fanout_by_type() {
+        /// Sends logs to every output, and metrics to the first output only.
+        struct ByType;
+
+        impl RoutingStrategy for ByType {
+            fn select(&mut self, sinks: &[ComponentKey], array: &EventArray) -> RoutingSelection {
+                match array {
+                    EventArray::Metrics(_) => smallvec![0],
+                    _ => (0..sinks.len()).collect(),
+                }
+            }
+        }
+
+        let (fanout, _control, _receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let mut fanout = fanout.with_strategy(Box::new(ByType));
+
+        for _ in 0..2 {
+            fanout.send(make_event_array(1)).await;
+            fanout
+                .send(
+                    Event::from(Metric::new(
+                        "counter",
+                        MetricKind::Incremental,
+                        MetricValue::Counter { value: 1.0 },
+                    ))
+                    .into(),
+                )
+                .await;
+        }
+
+        assert_eq!(
+            fanout.avg_fanout_by_type(),
+            HashMap::from([(DataType::Log, 3.0), (DataType::Metric, 1.0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_drops_by_reason() {
+        let (mut fanout, _control) = Fanout::new();
+        let (sender, receiver) = TopologyBuilder::standalone_memory(
+            NonZeroUsize::new(1).expect("capacity must be nonzero"),
+            WhenFull::DropNewest,
+        )
+        .await;
+        let id = ComponentKey::from("0");
+        fanout.add(id.clone(), sender);
+
+        // The buffer only has room for the first array, so the rest are dropped:
+        let events = make_events(3);
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+        }
+
+        assert_eq!(collect_ready_events(receiver.into_stream()), &events[..1]);
+        assert_eq!(
+            fanout.stats().drops_by_reason[&id],
+            HashMap::from([(DropReason::BufferFull, 2)])
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_sink_rate_limit() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[8, 8]).await;
+        let limited = ComponentKey::from("1");
+        control
+            .send(ControlMessage::SetSinkRateLimit(
+                limited.clone(),
+                1,
+                WhenFull::DropNewest,
+            ))
+            .expect("sending control message should not fail");
+
+        // Only the first array of a burst gets through to the limited output, until another second
+        // has passed:
+        let events = make_events(4);
+        for event in &events[..3] {
+            fanout.send(event.clone().into()).await;
+        }
+        advance(Duration::from_secs(1)).await;
+        fanout.send(events[3].clone().into()).await;
+
+        assert_eq!(
+            fanout.stats().drops_by_reason[&limited],
+            HashMap::from([(DropReason::RateLimited, 2)])
+        );
+        let expected_events = [events.clone(), vec![events[0].clone(), events[3].clone()]];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_sink_rate_limit_block() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[8, 8]).await;
+        control
+            .send(ControlMessage::SetSinkRateLimit(
+                ComponentKey::from("1"),
+                1,
+                WhenFull::Block,
+            ))
+            .expect("sending control message should not fail");
+
+        // Every array gets through, but the sends are paced to one per second:
+        let start = Instant::now();
+        let events = make_events(3);
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fanout_concurrent_reconfiguration() {
+        const KEYS: usize = 4;
+        const ITERATIONS: usize = 4_000;
+
+        fn spawn_reader(receiver: BufferReceiver<EventArray>) -> JoinHandle<Vec<EventArray>> {
+            tokio::spawn(receiver.into_stream().collect())
+        }
+
+        let (mut fanout, control) = Fanout::new();
+        let done = Arc::new(AtomicBool::new(false));
+        let (progress_tx, mut progress) = watch::channel(0);
+
+        // Keep sending until all reconfiguration is done, at which point one more send picks up
+        // any control messages still waiting to be applied.
+        let sending = {
+            let done = Arc::clone(&done);
+            tokio::spawn(async move {
+                let mut line = 0;
+                loop {
+                    let finished = done.load(Ordering::SeqCst);
+                    let event = Event::from(LogEvent::from(format!("line {}", line)));
+                    fanout.send(event.into()).await;
+                    line += 1;
+                    progress_tx.send_replace(line);
+                    if finished {
+                        break;
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        // Meanwhile, rapidly add, remove, and replace outputs across a rotating set of keys, with
+        // a reader for each output that collects everything it receives.
+        let mut present = [false; KEYS];
+        let mut readers = Vec::new();
+        for i in 0..ITERATIONS {
+            // Let at least one send through between each reconfiguration, so the two are actually
+            // interleaved rather than one racing ahead of the other.
+            progress
+                .changed()
+                .await
+                .expect("sending task should still be running");
+
+            let key = i % KEYS;
+            let id = ComponentKey::from(key.to_string());
+            if !present[key] {
+                let (sender, receiver) = build_sender_pair(4).await;
+                readers.push(spawn_reader(receiver));
+                control
+                    .send(ControlMessage::Add(id, sender))
+                    .expect("sending control message should not fail");
+                present[key] = true;
+            } else if (i / KEYS) % 2 == 0 {
+                control
+                    .send(ControlMessage::Remove(id))
+                    .expect("sending control message should not fail");
+                present[key] = false;
+            } else {
+                let (sender, receiver) = build_sender_pair(4).await;
+                readers.push(spawn_reader(receiver));
+                control
+                    .send(ControlMessage::Replace(id.clone(), None))
+                    .expect("sending control message should not fail");
+                tokio::task::yield_now().await;
+                control
+                    .send(ControlMessage::Replace(id, Some(sender)))
+                    .expect("sending control message should not fail");
+            }
+        }
+        done.store(true, Ordering::SeqCst);
+        sending.await.expect("sending task should not panic");
+
+        // Every output must have received a contiguous run of the stream, for as long as it was
+        // present, with nothing skipped or reordered.
+        let mut received = 0;
+        for reader in readers {
+            let lines = reader
+                .await
+                .expect("reader task should not panic")
+                .into_iter()
+                .map(|events| {
+                    unwrap_log_event_message(events)
+                        .trim_start_matches("line ")
+                        .parse::<usize>()
+                        .expect("must be a line number")
+                })
+                .collect::<Vec<_>>();
+            assert!(
+                lines.windows(2).all(|pair| pair[0] + 1 == pair[1]),
+                "events received out of order or with gaps: {:?}",
+                lines
+            );
+            received += lines.len();
+        }
+        assert!(received > 0);
+    }
+
+    #[tokio::test]
+    async fn fanout_async_transformed() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let (sender, receiver) = build_sender_pair(4).await;
+        let transform: AsyncTransform = Box::new(|events: EventArray| {
+            Box::pin(async move {
+                sleep(Duration::from_millis(10)).await;
+                let logs = events
+                    .into_events()
+                    .map(|event| {
+                        let message = unwrap_log_event_message(event);
+                        LogEvent::from(format!("transformed {}", message))
+                    })
+                    .collect::<Vec<_>>();
+                logs.into()
+            })
+        });
+        control
+            .send(ControlMessage::AddAsyncTransformed(
+                ComponentKey::from("transformed"),
+                sender,
+                transform,
+            ))
+            .expect("sending control message should not fail");
+
+        let events = make_events(2);
+        fanout.send(make_event_array(2)).await;
+
+        // The untransformed output gets the original array, while only the transformed output
+        // gets the transformed array:
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+        let transformed = collect_ready_events(receiver.into_stream())
+            .into_iter()
+            .map(unwrap_log_event_message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transformed,
+            vec!["transformed line 0", "transformed line 1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_accept_callback() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[16]).await;
+        let mut fanout = fanout.with_strategy(Box::new(RoundRobin::default()));
+        let accepted = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let on_accept: AcceptCallback = {
+            let accepted = Arc::clone(&accepted);
+            Box::new(move |id, count| accepted.lock().unwrap().push((id.clone(), count)))
+        };
+        let (sender, _receiver) = build_sender_pair(16).await;
+        control
+            .send(ControlMessage::AddWithAcceptCallback(
+                ComponentKey::from("1"),
+                sender,
+                on_accept,
+            ))
+            .expect("sending control message should not fail");
+
+        // Arrays alternate between the two outputs, so the callback only fires for every other one:
+        for count in 1..=4 {
+            fanout.send(make_event_array(count)).await;
+        }
+
+        assert_eq!(
+            *accepted.lock().unwrap(),
+            vec![(ComponentKey::from("1"), 2), (ComponentKey::from("1"), 4)]
+        );
+    }
+
+    /// A `BytesSink` which records everything it's sent.
+    struct RecordingBytesSink(Arc<Mutex<Vec<Bytes>>>);
+
+    impl BytesSink for RecordingBytesSink {
+        fn send(&mut self, bytes: Bytes) -> BoxFuture<'_, crate::Result<()>> {
+            self.0.lock().unwrap().push(bytes);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    /// A `SharedSink` which records every array it's sent.
+    struct RecordingSharedSink(Arc<Mutex<Vec<Arc<EventArray>>>>);
+
+    impl SharedSink for RecordingSharedSink {
+        fn send(&mut self, events: Arc<EventArray>) -> BoxFuture<'_, crate::Result<()>> {
+            self.0.lock().unwrap().push(events);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    /// A `SharedSink` which never finishes accepting anything it's sent.
+    struct BlockedSharedSink;
+
+    impl SharedSink for BlockedSharedSink {
+        fn send(&mut self, _events: Arc<EventArray>) -> BoxFuture<'_, crate::Result<()>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    /// A `BytesSink` which fails to accept anything it's sent.
+    struct FailingBytesSink;
+
+    impl BytesSink for FailingBytesSink {
+        fn send(&mut self, _bytes: Bytes) -> BoxFuture<'_, crate::Result<()>> {
+            Box::pin(async { Err("connection refused".into()) })
+        }
+    }
+
+    /// Builds an encoder which encodes each array as its length, counting how often it's invoked.
+    fn counting_encoder(prefix: &'static str, invocations: &Arc<AtomicUsize>) -> Encoder {
+        let invocations = Arc::clone(invocations);
+        Arc::new(move |events| {
+            invocations.fetch_add(1, Ordering::SeqCst);
+            Bytes::from(format!("{}{}", prefix, events.len()))
+        })
+    }
+
+    #[tokio::test]
+    async fn fanout_encoded() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        let shared_invocations = Arc::new(AtomicUsize::new(0));
+        let shared = counting_encoder("shared:", &shared_invocations);
+        let distinct_invocations = Arc::new(AtomicUsize::new(0));
+        let distinct = counting_encoder("distinct:", &distinct_invocations);
+
+        let mut received = Vec::new();
+        for (id, encoder) in [("1", &shared), ("2", &shared), ("3", &distinct)] {
+            let sent = Arc::new(Mutex::new(Vec::new()));
+            control
+                .send(ControlMessage::AddEncoded(
+                    ComponentKey::from(id),
+                    Box::new(RecordingBytesSink(Arc::clone(&sent))),
+                    Arc::clone(encoder),
+                ))
+                .expect("sending control message should not fail");
+            received.push(sent);
+        }
+
+        fanout.send(make_event_array(2)).await;
+
+        // Each encoder ran once, however many outputs use it:
+        assert_eq!(shared_invocations.load(Ordering::SeqCst), 1);
+        assert_eq!(distinct_invocations.load(Ordering::SeqCst), 1);
+        let received = received
+            .iter()
+            .map(|sent| sent.lock().unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            received,
+            [
+                vec![Bytes::from("shared:2")],
+                vec![Bytes::from("shared:2")],
+                vec![Bytes::from("distinct:2")],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_validate_batch_encoded() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        control
+            .send(ControlMessage::AddEncoded(
+                ComponentKey::from("1"),
+                Box::new(RecordingBytesSink(Arc::default())),
+                Arc::new(|events: &EventArray| Bytes::from(events.len().to_string())),
+            ))
+            .expect("sending control message should not fail");
+        fanout.send(make_event_array(1)).await;
+
+        // An encoded output can be removed, but its key can't be added again while it exists:
+        let id = ComponentKey::from("1");
+        assert_eq!(
+            fanout.validate_batch(&[ControlMessageDescriptor::Add(id.clone())]),
+            Err(vec!["Adding duplicate output id to fanout: 1".to_string()])
+        );
+        assert_eq!(
+            fanout.validate_batch(&[
+                ControlMessageDescriptor::Remove(id.clone()),
+                ControlMessageDescriptor::Add(id),
+            ]),
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_validate_batch_shared() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4]).await;
+        control
+            .send(ControlMessage::AddShared(
+                ComponentKey::from("1"),
+                Box::new(RecordingSharedSink(Arc::default())),
+            ))
+            .expect("sending control message should not fail");
+        fanout.send(make_event_array(1)).await;
+
+        // A shared output can be removed and added back, but not added twice, including within the
+        // same batch:
+        let id = ComponentKey::from("1");
+        assert_eq!(
+            fanout.validate_batch(&[
+                ControlMessageDescriptor::Remove(id.clone()),
+                ControlMessageDescriptor::AddShared(id.clone()),
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            fanout.validate_batch(&[
+                ControlMessageDescriptor::Remove(id.clone()),
+                ControlMessageDescriptor::AddShared(id.clone()),
+                ControlMessageDescriptor::Add(id),
+            ]),
+            Err(vec!["Adding duplicate output id to fanout: 1".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_shared() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[4]).await;
+        let events = make_event_array(2);
+
+        let mut received = Vec::new();
+        for id in ["1", "2"] {
+            let sent = Arc::new(Mutex::new(Vec::new()));
+            control
+                .send(ControlMessage::AddShared(
+                    ComponentKey::from(id),
+                    Box::new(RecordingSharedSink(Arc::clone(&sent))),
+                ))
+                .expect("sending control message should not fail");
+            received.push(sent);
+        }
+
+        fanout.send(events.clone()).await;
+
+        // Shared outputs get the same copy of the array, alongside the usual outputs:
+        let mut first = received[0].lock().unwrap().pop().unwrap();
+        let second = received[1].lock().unwrap().pop().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, events);
+        for receiver in receivers {
+            assert_eq!(collect_ready(receiver.into_stream()), vec![events.clone()]);
+        }
+
+        // Modifying the array only copies it for the output doing so:
+        Arc::make_mut(&mut first).for_each_event(|mut event| {
+            event
+                .metadata_mut()
+                .set_datadog_api_key(Some(Arc::from("key")));
+        });
+        assert_ne!(*first, *second);
+        assert_eq!(*second, events);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_shared_blocked() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_send_timeout(Duration::from_secs(1));
+        let events = make_events(3);
+        let add_blocked = |id: &str| {
+            control
+                .send(ControlMessage::AddShared(
+                    ComponentKey::from(id),
+                    Box::new(BlockedSharedSink),
+                ))
+                .expect("sending control message should not fail");
+        };
+
+        // A blocked shared output holds up the send like any other output, but control messages
+        // are still applied in the meantime, so removing it lets the send complete:
+        add_blocked("1");
+        let mut send = spawn(fanout.send(events[0].clone().into()));
+        assert_pending!(send.poll());
+        control
+            .send(ControlMessage::Remove(ComponentKey::from("1")))
+            .expect("sending control message should not fail");
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+
+        // Likewise, it's dropped once the send timeout elapses:
+        add_blocked("2");
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        advance(Duration::from_secs(1)).await;
+        assert_ready!(send.poll());
+        drop(send);
+        assert!(!fanout.contains(&ComponentKey::from("2")));
+
+        fanout.send(events[2].clone().into()).await;
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_cow_threshold() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[8]).await;
+        let mut fanout = fanout.with_cow_threshold(3);
+
+        let mut received = Vec::new();
+        for id in ["1", "2"] {
+            let sent = Arc::new(Mutex::new(Vec::new()));
+            control
+                .send(ControlMessage::AddShared(
+                    ComponentKey::from(id),
+                    Box::new(RecordingSharedSink(Arc::clone(&sent))),
+                ))
+                .expect("sending control message should not fail");
+            received.push(sent);
+        }
+
+        // An array below the threshold is copied for each shared output:
+        let small = make_event_array(2);
+        fanout.send(small.clone()).await;
+        let first = received[0].lock().unwrap().pop().unwrap();
+        let second = received[1].lock().unwrap().pop().unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, small);
+        assert_eq!(*second, small);
+
+        // While one at or above it is shared between them:
+        let large = make_event_array(3);
+        fanout.send(large.clone()).await;
+        let first = received[0].lock().unwrap().pop().unwrap();
+        let second = received[1].lock().unwrap().pop().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, large);
+    }
+
+    #[tokio::test]
+    async fn fanout_try_send() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let events = make_events(4);
+        let failed = |id: &str| {
+            Err(FanoutError::SendFailed {
+                ids: vec![ComponentKey::from(id)],
+            })
+        };
+
+        // Any output failing to accept the array fails the send, even with others that did:
+        drop(receivers.remove(1));
+        assert_eq!(fanout.try_send(events[0].clone().into()).await, failed("1"));
+
+        // Including failing to accept the snapshot sent ahead of it:
+        let (sender, receiver) = build_sender_pair(4).await;
+        drop(receiver);
+        for message in [
+            ControlMessage::Remove(ComponentKey::from("1")),
+            ControlMessage::AddWithSnapshot(
+                ComponentKey::from("3"),
+                sender,
+                events[0].clone().into(),
+            ),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        assert_eq!(fanout.try_send(events[1].clone().into()).await, failed("3"));
+
+        // Or its encoded form:
+        for message in [
+            ControlMessage::Remove(ComponentKey::from("3")),
+            ControlMessage::AddEncoded(
+                ComponentKey::from("4"),
+                Box::new(FailingBytesSink),
+                Arc::new(|events: &EventArray| Bytes::from(events.len().to_string())),
+            ),
+        ] {
+            control
+                .send(message)
+                .expect("sending control message should not fail");
+        }
+        assert_eq!(fanout.try_send(events[2].clone().into()).await, failed("4"));
+
+        control
+            .send(ControlMessage::Remove(ComponentKey::from("4")))
+            .expect("sending control message should not fail");
+        assert_eq!(fanout.try_send(events[3].clone().into()).await, Ok(()));
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_sink_error_cause_logged() {
+        let (mut fanout, control) = Fanout::new();
+        control
+            .send(ControlMessage::AddEncoded(
+                ComponentKey::from("encoded"),
+                Box::new(FailingBytesSink),
+                Arc::new(|events: &EventArray| Bytes::from(events.len().to_string())),
+            ))
+            .unwrap();
+        let (sender, receiver) = build_sender_pair(4).await;
+        drop(receiver);
+        control
+            .send(ControlMessage::Add(ComponentKey::from("buffered"), sender))
+            .unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(EventRecorder(Arc::clone(&events)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        fanout.send(make_event_array(1)).await;
+
+        // The encoded output reports why it failed, which is logged along with the failure, while
+        // the buffered output has no cause to report:
+        let events = events.lock().unwrap();
+        let failures = events
+            .iter()
+            .filter(|fields| {
+                fields["message"] == "Fanout output failed to accept events. Events dropped."
+            })
+            .map(|fields| (fields["output"].clone(), fields.get("error").cloned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            vec![
+                ("buffered".to_string(), None),
+                (
+                    "encoded".to_string(),
+                    Some("connection refused".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_with_sinks() {
+        let (first, _) = build_sender_pair(4).await;
+        let (second, _) = build_sender_pair(4).await;
+        let (third, _) = build_sender_pair(4).await;
+
+        // A duplicate ID anywhere among the sinks means no fanout is built at all:
+        let result = Fanout::with_sinks([
+            (ComponentKey::from("a"), first.clone()),
+            (ComponentKey::from("b"), second.clone()),
+            (ComponentKey::from("a"), third),
+        ]);
+        assert_eq!(
+            result.err(),
+            Some(FanoutError::DuplicateOutput {
+                id: ComponentKey::from("a")
+            })
+        );
+
+        let (fanout, _control) = Fanout::with_sinks([
+            (ComponentKey::from("a"), first),
+            (ComponentKey::from("b"), second),
+        ])
+        .unwrap();
+        assert_eq!(
+            fanout.output_ids().cloned().collect::<Vec<_>>(),
+            vec![ComponentKey::from("a"), ComponentKey::from("b")]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_add_all() {
+        let (mut fanout, _control, mut receivers) = fanout_from_senders(&[4]).await;
+        let (first, _) = build_sender_pair(4).await;
+        let (second, _) = build_sender_pair(4).await;
+
+        // Neither a key that's already taken nor one repeated within the batch adds anything:
+        for ids in [["1", "0"], ["1", "1"]] {
+            let result = fanout.add_all([
+                (ComponentKey::from(ids[0]), first.clone()),
+                (ComponentKey::from(ids[1]), second.clone()),
+            ]);
+            assert_eq!(
+                result,
+                Err(FanoutError::DuplicateOutput {
+                    id: ComponentKey::from(ids[1])
+                })
+            );
+            assert_eq!(fanout.len(), 1);
+        }
+
+        let (sender, receiver) = build_sender_pair(4).await;
+        receivers.push(receiver);
+        let (other, other_receiver) = build_sender_pair(4).await;
+        receivers.push(other_receiver);
+        fanout
+            .add_all([
+                (ComponentKey::from("1"), sender),
+                (ComponentKey::from("2"), other),
+            ])
+            .unwrap();
+
+        let events = make_event_array(1);
+        fanout.send(events.clone()).await;
+        for receiver in receivers {
+            assert_eq!(collect_ready(receiver.into_stream()), vec![events.clone()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_add_with_snapshot() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let events = make_events(3);
+
+        fanout.send(events[0].clone().into()).await;
+
+        let (sender, receiver) = build_sender_pair(4).await;
+        receivers.push(receiver);
+        control
+            .send(ControlMessage::AddWithSnapshot(
+                ComponentKey::from("1"),
+                sender,
+                events[1].clone().into(),
+            ))
+            .expect("sending control message should not fail");
+        fanout.send(events[2].clone().into()).await;
+
+        // The new output gets the snapshot first, followed by everything sent after it was added:
+        let expected_events = [
+            vec![events[0].clone(), events[2].clone()],
+            events[1..].to_vec(),
+        ];
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            assert_eq!(
+                collect_ready_events(receiver.into_stream()),
+                expected_events[i]
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_throughput_eps() {
+        let (mut fanout, _control, _receivers) = fanout_from_senders(&[1024]).await;
+        assert_eq!(fanout.throughput_eps(), 0.0);
+
+        // Send two events every tenth of a second, for long enough that the estimate settles:
+        for _ in 0..300 {
+            fanout.send(make_event_array(2)).await;
+            advance(Duration::from_millis(100)).await;
+        }
+
+        let throughput = fanout.throughput_eps();
+        assert!((19.0..21.0).contains(&throughput), "{}", throughput);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_stats_guard() {
+        let (mut fanout, _control, _receivers) = fanout_from_senders(&[4]).await;
+        fanout.send(make_event_array(1)).await;
+
+        let snapshot = fanout.stats_guard();
+        let expected = StatsSnapshot {
+            by_type: EventArrayCounts {
+                logs: 1,
+                metrics: 0,
+                traces: 0,
+            },
+            routed_by_type: EventArrayCounts {
+                logs: 1,
+                metrics: 0,
+                traces: 0,
+            },
+            drops_by_reason: HashMap::new(),
+            errors: HashMap::from([(ComponentKey::from("0"), 0)]),
+            deliveries: HashMap::from([(ComponentKey::from("0"), 1)]),
+            backpressure: HashMap::from([(ComponentKey::from("0"), Duration::ZERO)]),
+            throughput_eps: fanout.throughput_eps(),
+            shadows: HashMap::new(),
+            clone_time: Duration::ZERO,
+            clone_count: 0,
+        };
+        assert_eq!(snapshot, expected);
+
+        // Further sends show up in new snapshots, but not in the one already taken:
+        fanout.send(make_event_array(1)).await;
+        fanout.send(make_event_array(1)).await;
+        assert_eq!(snapshot, expected);
+        let snapshot = fanout.stats_guard();
+        assert_eq!(snapshot.by_type.logs, 3);
+        assert_eq!(snapshot.deliveries[&ComponentKey::from("0")], 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_reset_stats() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[8]).await;
+
+        // Outputs in a paused group miss every array sent, which is counted as a drop:
+        control
+            .send(ControlMessage::AddToGroup(
+                ComponentKey::from("1"),
+                build_sender_pair(8).await.0,
+                "g1".to_string(),
+            ))
+            .expect("sending control message should not fail");
+        control
+            .send(ControlMessage::PauseGroup("g1".to_string()))
+            .expect("sending control message should not fail");
+        fanout.send(make_event_array(1)).await;
+        fanout.send(make_event_array(1)).await;
+        assert_eq!(fanout.stats().by_type.logs, 2);
+
+        fanout.reset_stats();
+        assert_eq!(fanout.throughput_eps(), 0.0);
+        fanout.send(make_event_array(1)).await;
+
+        assert_eq!(
+            fanout.stats_guard(),
+            StatsSnapshot {
+                by_type: EventArrayCounts {
+                    logs: 1,
+                    metrics: 0,
+                    traces: 0,
+                },
+                routed_by_type: EventArrayCounts {
+                    logs: 1,
+                    metrics: 0,
+                    traces: 0,
+                },
+                drops_by_reason: HashMap::from([(
+                    ComponentKey::from("1"),
+                    HashMap::from([(DropReason::Paused, 1)]),
+                )]),
+                errors: HashMap::from([(ComponentKey::from("0"), 0), (ComponentKey::from("1"), 0)]),
+                deliveries: HashMap::from([
+                    (ComponentKey::from("0"), 1),
+                    (ComponentKey::from("1"), 0),
+                ]),
+                backpressure: HashMap::from([
+                    (ComponentKey::from("0"), Duration::ZERO),
+                    (ComponentKey::from("1"), Duration::ZERO),
+                ]),
+                throughput_eps: fanout.throughput_eps(),
+                shadows: HashMap::new(),
+                clone_time: Duration::ZERO,
+                clone_count: 0,
+            }
+        );
+
+        // The outputs themselves are untouched:
+        assert_eq!(collect_ready(receivers.remove(0).into_stream()).len(), 3);
+        assert_eq!(
+            fanout.delivery_counts(),
+            vec![(ComponentKey::from("0"), 1), (ComponentKey::from("1"), 0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_min_ready_sinks() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_min_ready_sinks(2);
+        let events = make_events(1);
+
+        // With only one output, the send can't go anywhere until a second output is added:
+        {
+            let mut send = spawn(fanout.send(events[0].clone().into()));
+            assert_pending!(send.poll());
+
+            add_control_sender(&control, &mut receivers, 1, 4).await;
+            assert!(send.is_woken());
+            assert_ready!(send.poll());
+        }
+
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_min_ready_sinks_full_buffer() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[1, 4]).await;
+        let mut fanout = fanout.with_min_ready_sinks(2);
+        let events = make_events(2);
+        let mut streams = receivers
+            .into_iter()
+            .map(|receiver| Box::pin(receiver.into_stream()))
+            .collect::<Vec<_>>();
+        fanout.send(events[0].clone().into()).await;
+
+        // With the first output's buffer full, only one output is ready, so the send doesn't go to
+        // either output until the first output's buffer is read from:
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        assert_eq!(streams[1].next().await, Some(events[0].clone().into()));
+        assert!(poll!(streams[1].next()).is_pending());
+
+        assert_eq!(streams[0].next().await, Some(events[0].clone().into()));
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+
+        for stream in streams {
+            assert_eq!(collect_ready_events(stream), &events[1..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_min_ready_sinks_full_buffer_control_closed() {
+        let (fanout, control, receivers) = fanout_from_senders(&[1, 4]).await;
+        let mut fanout = fanout.with_min_ready_sinks(2);
+        let events = make_events(2);
+        let mut streams = receivers
+            .into_iter()
+            .map(|receiver| Box::pin(receiver.into_stream()))
+            .collect::<Vec<_>>();
+        fanout.send(events[0].clone().into()).await;
+
+        // Closing the control channel doesn't stop the send from waiting for the first output's
+        // buffer to be read from:
+        drop(control);
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        assert_eq!(streams[0].next().await, Some(events[0].clone().into()));
+        assert!(send.is_woken());
+        assert_ready!(send.poll());
+        drop(send);
+
+        assert_eq!(collect_ready_events(streams.remove(1)), &events[..]);
+        assert_eq!(collect_ready_events(streams.remove(0)), &events[1..]);
+    }
+
+    #[tokio::test]
+    async fn fanout_key_mapper() {
+        let (fanout, control) = Fanout::new();
+        let mut fanout =
+            fanout.with_key_mapper(|id: ComponentKey| ComponentKey::from(format!("ns.{}", id)));
+        let mut receivers = Vec::new();
+
+        // Outputs added directly or by control message both end up under the rewritten key:
+        add_sender_to_fanout(&mut fanout, &mut receivers, 0, 4).await;
+        add_control_sender(&control, &mut receivers, 1, 4).await;
+        fanout.send(make_event_array(1)).await;
+
+        let ids = fanout
+            .occupancy()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![ComponentKey::from("ns.0"), ComponentKey::from("ns.1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_clone_timing() {
+        let (fanout, _control, _receivers) = fanout_from_senders(&[1000, 1000, 1000]).await;
+        let mut fanout = fanout.with_clone_timing(true);
+
+        // A large array takes long enough to clone that its clones take a measurable amount of time:
+        fanout.send(make_event_array(1000)).await;
+        assert_eq!(fanout.stats().clone_count, 2);
+        let avg_clone_time = fanout
+            .stats()
+            .avg_clone_time()
+            .expect("clones must have been timed");
+        assert!(avg_clone_time > Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_clones_only_armed_outputs() {
+        let (fanout, control, receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let mut fanout = fanout.with_clone_timing(true);
+        let events = make_events(2);
+        control
+            .send(ControlMessage::SetSinkRateLimit(
+                ComponentKey::from("2"),
+                1,
+                WhenFull::DropNewest,
+            ))
+            .unwrap();
+
+        fanout.send(events[0].clone().into()).await;
+        assert_eq!(fanout.stats().clone_count, 2);
+
+        // With the last output over its rate limit, the output before it takes the original array
+        // rather than a clone:
+        fanout.send(events[1].clone().into()).await;
+        assert_eq!(fanout.stats().clone_count, 3);
+
+        let expected = [&events[..], &events[..], &events[..1]];
+        for (receiver, expected) in receivers.into_iter().zip(expected) {
+            assert_eq!(collect_ready_events(receiver.into_stream()), expected);
         }
     }
 
-    async fn send(&mut self) {
-        // Right now, we do a linear scan of all sends, polling each send once in order to avoid
-        // waiting forever, such that we can let our control messages get picked up while sends are
-        // waiting.
-        loop {
-            if self.sends.is_empty() {
-                break;
-            }
+    #[tokio::test]
+    async fn fanout_single_output_no_clone() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_clone_timing(true);
+        let events = make_events(3);
 
-            let mut done = Vec::new();
-            for (key, send) in &mut self.sends {
-                if let Poll::Ready(sender) = poll!(send.get_pin()) {
-                    // The send completed, so we restore the sender and mark ourselves so that this
-                    // future gets dropped.
-                    done.push((key.clone(), sender));
-                }
-            }
+        for event in &events {
+            fanout.send(event.clone().into()).await;
+        }
 
-            for (key, sender) in done {
-                self.sends.remove(&key);
-                self.replace(&key, sender);
-            }
+        // The sole output always takes the original array, so nothing is ever cloned:
+        assert_eq!(fanout.stats().clone_count, 0);
+        let received = collect_ready_events(receivers.into_iter().next().unwrap().into_stream());
+        assert_eq!(received, events);
+    }
 
-            if !self.sends.is_empty() {
-                // We manually yield ourselves because we've polled all of the sends at this point,
-                // so if any are left, then we're scheduled for a wake-up... this is a really poor
-                // approximation of what `FuturesUnordered` is doing.
-                pending!();
-            }
+    #[tokio::test]
+    async fn fanout_global_sequence() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[8, 8]).await;
+        let mut fanout = fanout.with_global_sequence(true);
+
+        for _ in 0..3 {
+            fanout.send(make_event_array(2)).await;
+        }
+
+        // Every output sees the same sequence number for the same array, increasing with each
+        // array sent:
+        for receiver in receivers {
+            let sequences = collect_ready(receiver.into_stream())
+                .into_iter()
+                .map(|array| {
+                    array
+                        .into_events()
+                        .map(|event| event.metadata().sequence())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                sequences,
+                vec![vec![Some(0); 2], vec![Some(1); 2], vec![Some(2); 2]]
+            );
         }
     }
-}
 
-struct Sender {
-    inner: BufferSender<EventArray>,
-    input: Option<EventArray>,
-}
+    #[tokio::test]
+    async fn fanout_path_stamping() {
+        let (first, _first_control, first_receivers) = fanout_from_senders(&[4]).await;
+        let mut first = first.with_name("first").with_path_stamping(true);
+        let (second, _second_control, second_receivers) = fanout_from_senders(&[4]).await;
+        let mut second = second.with_name("second").with_path_stamping(true);
 
-impl Sender {
-    fn new(inner: BufferSender<EventArray>) -> Self {
-        Self { inner, input: None }
+        // Chain the two fanouts, by sending whatever the first delivers through the second:
+        first.send(make_event_array(2)).await;
+        for receiver in first_receivers {
+            second
+                .send_all_arrays(collect_ready(receiver.into_stream()))
+                .await;
+        }
+
+        // Each fanout appended its name to the path, in the order the array passed through them:
+        let paths = second_receivers
+            .into_iter()
+            .flat_map(|receiver| collect_ready(receiver.into_stream()))
+            .flat_map(EventArray::into_events)
+            .map(|event| {
+                event
+                    .metadata()
+                    .path()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec![vec!["first", "second"]; 2]);
     }
 
-    async fn flush(&mut self) {
-        if let Some(input) = self.input.take() {
-            self.inner.send(input).await.expect("unit error");
-            self.inner.flush().await.expect("unit error");
+    #[tokio::test]
+    async fn fanout_exactly_once() {
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[4]).await;
+        let mut fanout = fanout.with_exactly_once(8);
+        let (sender, receiver) = TopologyBuilder::standalone_memory(
+            NonZeroUsize::new(1).expect("capacity must be nonzero"),
+            WhenFull::DropNewest,
+        )
+        .await;
+        fanout.add(ComponentKey::from("1"), sender);
+        receivers.push(receiver);
+        let events = make_events(2);
+
+        // The second output only has room for the first array, so it drops the second one:
+        fanout.send_with_token(events[0].clone().into(), 0).await;
+        fanout.send_with_token(events[1].clone().into(), 1).await;
+        assert_eq!(
+            receivers[1]
+                .next()
+                .await
+                .map(|array| array.into_events().count()),
+            Some(1)
+        );
+
+        // Retrying the second array only delivers it to the output that dropped it:
+        fanout.send_with_token(events[1].clone().into(), 1).await;
+        assert_eq!(
+            collect_ready_events(receivers.remove(1).into_stream()),
+            &events[1..]
+        );
+        assert_eq!(
+            collect_ready_events(receivers.remove(0).into_stream()),
+            events
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_oldest_pending_control_age() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        assert_eq!(fanout.oldest_pending_control_age(), None);
+
+        add_control_sender(&control, &mut receivers, 1, 4).await;
+        advance(Duration::from_secs(3)).await;
+        remove_sender_from_fanout(&control, 0);
+        advance(Duration::from_secs(2)).await;
+        assert_eq!(
+            fanout.oldest_pending_control_age(),
+            Some(Duration::from_secs(5))
+        );
+
+        // Once the control messages are applied, nothing is left pending:
+        fanout.send(make_event_array(1)).await;
+        assert_eq!(fanout.oldest_pending_control_age(), None);
+    }
+
+    #[tokio::test]
+    async fn fanout_pending_control_messages() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4]).await;
+        assert_eq!(fanout.pending_control_messages(), 0);
+
+        add_control_sender(&control, &mut receivers, 1, 4).await;
+        remove_sender_from_fanout(&control, 0);
+        assert_eq!(fanout.pending_control_messages(), 2);
+
+        fanout.send(make_event_array(1)).await;
+        assert_eq!(fanout.pending_control_messages(), 0);
+    }
+
+    #[tokio::test]
+    async fn fanout_reconfigure_routing() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[8, 8, 8]).await;
+        fanout.send(make_event_array(1)).await;
+
+        // Every output but the last gets a specific weight, while the last is left with the default:
+        control
+            .send(ControlMessage::ReconfigureRouting(RoutingConfig {
+                strategy: Box::new(Weighted::default()),
+                weights: HashMap::from([
+                    (ComponentKey::from("0"), 3),
+                    (ComponentKey::from("1"), 0),
+                    (ComponentKey::from("9"), 5),
+                ]),
+                priorities: HashMap::from([(ComponentKey::from("1"), 2)]),
+            }))
+            .expect("sending control message should not fail");
+        for _ in 0..4 {
+            fanout.send(make_event_array(1)).await;
         }
+
+        assert_eq!(
+            fanout.sinks_by_priority(),
+            vec![
+                (ComponentKey::from("1"), 2),
+                (ComponentKey::from("0"), 0),
+                (ComponentKey::from("2"), 0),
+            ]
+        );
+        let counts = receivers
+            .into_iter()
+            .map(|receiver| collect_ready(receiver.into_stream()).len())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![1 + 3, 1, 1 + 1]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::mem;
-    use std::num::NonZeroUsize;
+    #[tokio::test]
+    async fn fanout_load_balance_mode() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[1000, 1000, 1000]).await;
+        let mut fanout = fanout.with_mode(FanoutMode::LoadBalance(vec![1, 2, 5]));
 
-    use futures::poll;
-    use tokio::sync::mpsc::UnboundedSender;
-    use tokio_test::{assert_pending, assert_ready, task::spawn};
-    use value::Value;
-    use vector_buffers::{
-        topology::{
-            builder::TopologyBuilder,
-            channel::{BufferReceiver, BufferSender},
-        },
-        WhenFull,
-    };
+        for _ in 0..800 {
+            fanout.send(make_event_array(1)).await;
+        }
 
-    use super::{ControlMessage, Fanout};
-    use crate::event::{Event, EventArray, LogEvent};
-    use crate::test_util::{collect_ready, collect_ready_events};
-    use crate::{config::ComponentKey, event::EventContainer};
+        // Each output receives its share of the arrays in proportion to its weight, and no more:
+        let counts = receivers
+            .into_iter()
+            .map(|receiver| collect_ready(receiver.into_stream()).len())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![100, 200, 500]);
+    }
 
-    async fn build_sender_pair(
-        capacity: usize,
-    ) -> (BufferSender<EventArray>, BufferReceiver<EventArray>) {
-        TopologyBuilder::standalone_memory(
-            NonZeroUsize::new(capacity).expect("capacity must be nonzero"),
-            WhenFull::Block,
-        )
-        .await
+    #[tokio::test]
+    async fn fanout_load_balance_waits_on_chosen_output_only() {
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[1, 4]).await;
+        let mut fanout = fanout.with_mode(FanoutMode::LoadBalance(vec![1, 3]));
+
+        // The first array fills the first output, but the next three go to the second output alone,
+        // so none of them wait on the first output to be drained:
+        for _ in 0..4 {
+            fanout.send(make_event_array(1)).await;
+        }
+
+        let second = receivers.pop().unwrap();
+        assert_eq!(collect_ready(second.into_stream()).len(), 3);
+        let first = receivers.pop().unwrap();
+        assert_eq!(collect_ready(first.into_stream()).len(), 1);
     }
 
-    async fn build_sender_pairs(
-        capacities: &[usize],
-    ) -> Vec<(BufferSender<EventArray>, BufferReceiver<EventArray>)> {
-        let mut pairs = Vec::new();
-        for capacity in capacities {
-            pairs.push(build_sender_pair(*capacity).await);
+    #[tokio::test]
+    async fn fanout_round_robin_mode() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[200, 200, 200]).await;
+        let mut fanout = fanout.with_mode(FanoutMode::RoundRobin);
+
+        for _ in 0..300 {
+            fanout.send(make_event_array(1)).await;
         }
-        pairs
+
+        let counts = receivers
+            .into_iter()
+            .map(|receiver| collect_ready(receiver.into_stream()).len())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![100, 100, 100]);
     }
 
-    async fn fanout_from_senders(
-        capacities: &[usize],
-    ) -> (
-        Fanout,
-        UnboundedSender<ControlMessage>,
-        Vec<BufferReceiver<EventArray>>,
-    ) {
-        let (mut fanout, control) = Fanout::new();
-        let pairs = build_sender_pairs(capacities).await;
+    #[tokio::test]
+    async fn fanout_round_robin_mode_skips_pending_output() {
+        let (fanout, _control, receivers) = fanout_from_senders(&[1, 4, 4]).await;
+        let mut fanout = fanout.with_mode(FanoutMode::RoundRobin);
 
-        let mut receivers = Vec::new();
-        for (i, (sender, receiver)) in pairs.into_iter().enumerate() {
-            fanout.add(ComponentKey::from(i.to_string()), sender);
-            receivers.push(receiver);
+        // Once the first output is full, its turns are handed to the next ready output:
+        for _ in 0..6 {
+            fanout.send(make_event_array(1)).await;
         }
 
-        (fanout, control, receivers)
+        let counts = receivers
+            .into_iter()
+            .map(|receiver| collect_ready(receiver.into_stream()).len())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 3, 2]);
     }
 
-    async fn add_sender_to_fanout(
-        fanout: &mut Fanout,
-        receivers: &mut Vec<BufferReceiver<EventArray>>,
-        sender_id: usize,
-        capacity: usize,
-    ) {
-        let (sender, receiver) = build_sender_pair(capacity).await;
-        receivers.push(receiver);
+    #[tokio::test]
+    async fn fanout_filtered() {
+        let (mut fanout, control, receivers) = fanout_from_senders(&[8]).await;
+        let (sender, filtered_receiver) = build_sender_pair(8).await;
+        let only_pairs: Filter = Arc::new(|events: &EventArray| events.len() == 2);
+        control
+            .send(ControlMessage::AddFiltered(
+                ComponentKey::from("filtered"),
+                sender,
+                only_pairs,
+            ))
+            .unwrap();
 
-        fanout.add(ComponentKey::from(sender_id.to_string()), sender);
+        let arrays = [
+            make_event_array(1),
+            make_event_array(2),
+            make_event_array(3),
+        ];
+        for array in arrays.clone() {
+            fanout.send(array).await;
+        }
+
+        // Unfiltered outputs get every array, while the filtered output only gets the one its
+        // filter accepts, with the rest recorded as dropped:
+        let unfiltered = receivers.into_iter().next().unwrap();
+        assert_eq!(collect_ready(unfiltered.into_stream()), arrays);
+        assert_eq!(
+            collect_ready(filtered_receiver.into_stream()),
+            vec![arrays[1].clone()]
+        );
+        assert_eq!(
+            fanout.stats().drops_by_reason[&ComponentKey::from("filtered")],
+            HashMap::from([(DropReason::Filtered, 2)])
+        );
     }
 
-    fn remove_sender_from_fanout(control: &UnboundedSender<ControlMessage>, sender_id: usize) {
+    #[tokio::test]
+    async fn fanout_for_types() {
+        let (mut fanout, control) = Fanout::new();
+        let (logs, logs_receiver) = build_sender_pair(8).await;
+        let (metrics, metrics_receiver) = build_sender_pair(8).await;
         control
-            .send(ControlMessage::Remove(ComponentKey::from(
-                sender_id.to_string(),
-            )))
-            .expect("sending control message should not fail");
+            .send(ControlMessage::AddForTypes(
+                ComponentKey::from("logs"),
+                logs,
+                DataType::Log,
+            ))
+            .unwrap();
+        control
+            .send(ControlMessage::AddForTypes(
+                ComponentKey::from("metrics"),
+                metrics,
+                DataType::Metric | DataType::Trace,
+            ))
+            .unwrap();
+
+        let log_array = make_event_array(2);
+        let metric_array: EventArray = Event::from(Metric::new(
+            "counter",
+            MetricKind::Incremental,
+            MetricValue::Counter { value: 1.0 },
+        ))
+        .into();
+        fanout.send(log_array.clone()).await;
+        fanout.send(metric_array.clone()).await;
+        fanout.send(EventArray::Logs(Vec::new())).await;
+
+        // Each output only gets the arrays of its own types, and never the empty array:
+        assert_eq!(collect_ready(logs_receiver.into_stream()), vec![log_array]);
+        assert_eq!(
+            collect_ready(metrics_receiver.into_stream()),
+            vec![metric_array]
+        );
     }
 
-    async fn replace_sender_in_fanout(
-        control: &UnboundedSender<ControlMessage>,
-        receivers: &mut Vec<BufferReceiver<EventArray>>,
-        sender_id: usize,
-        capacity: usize,
-    ) -> BufferReceiver<EventArray> {
-        let (sender, receiver) = build_sender_pair(capacity).await;
-        let old_receiver = mem::replace(&mut receivers[sender_id], receiver);
+    /// Builds a fanout with a single at-least-once output, holding on to one array for a retry
+    /// after failing to accept it, along with the array.
+    async fn fanout_with_retry() -> (Fanout, ControlChannel, EventArray) {
+        let (fanout, control) = Fanout::new();
+        let mut fanout = fanout.with_tier_policies(HashMap::from([(
+            1,
+            TierPolicy::AtLeastOnce { max_queued: 4 },
+        )]));
+        let (sender, _) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::AddWithTier(
+                ComponentKey::from("0"),
+                sender,
+                1,
+            ))
+            .unwrap();
+
+        // The output fails, since its receiver is already gone:
+        let events = make_event_array(1);
+        fanout.send(events.clone()).await;
+        (fanout, control, events)
+    }
+
+    #[tokio::test]
+    async fn fanout_flush_all() {
+        let (mut fanout, _control, receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_event_array(1);
+        fanout.send(events.clone()).await;
+
+        assert_eq!(fanout.flush_all().await, Ok(()));
+        for receiver in receivers {
+            assert_eq!(collect_ready(receiver.into_stream()), vec![events.clone()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_spill_depth() {
+        let (mut fanout, control, events) = fanout_with_retry().await;
+        let id = ComponentKey::from("0");
+
+        // The array held for a retry only waits on a replacement once one is started:
+        assert_eq!(fanout.spill_depth(&id), 0);
+        control
+            .send(ControlMessage::Replace(id.clone(), None))
+            .unwrap();
+        fanout.control_quiescent().await;
+        assert_eq!(fanout.spill_depth(&id), 1);
+
+        // Once the replacement arrives, the next send hands it the held array first:
+        let (sender, receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Replace(id.clone(), Some(sender)))
+            .unwrap();
+        fanout.send(events.clone()).await;
+        assert_eq!(fanout.spill_depth(&id), 0);
+        assert_eq!(
+            collect_ready(receiver.into_stream()),
+            vec![events.clone(), events]
+        );
+    }
 
+    #[tokio::test]
+    async fn fanout_drain() {
+        let (mut fanout, control, events) = fanout_with_retry().await;
+        let (sender, retry_receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("0"), None))
+            .unwrap();
         control
             .send(ControlMessage::Replace(
-                ComponentKey::from(sender_id.to_string()),
-                None,
+                ComponentKey::from("0"),
+                Some(sender),
             ))
-            .expect("sending control message should not fail");
+            .unwrap();
+        let (sender, paused_receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Add(ComponentKey::from("1"), sender))
+            .unwrap();
+        control
+            .send(ControlMessage::Pause(ComponentKey::from("1")))
+            .unwrap();
+        fanout.control_quiescent().await;
 
+        assert_eq!(fanout.drain(Some(Duration::from_secs(1))).await, Ok(()));
+
+        // The array held for a retry is delivered, and every output, paused or not, is closed once
+        // it has been drained:
+        let mut receivers = [retry_receiver, paused_receiver];
+        assert_eq!(receivers[0].next().await, Some(events));
+        for receiver in &mut receivers {
+            assert!(receiver.next().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn fanout_drain_failed_output() {
+        let (mut fanout, control, _events) = fanout_with_retry().await;
+        let (sender, mut receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Add(ComponentKey::from("1"), sender))
+            .unwrap();
+        fanout.control_quiescent().await;
+
+        // The output that failed before fails again to take its retry, failing the drain as a
+        // whole, while the other output is still drained and closed:
+        assert_eq!(fanout.drain(None).await, Err(()));
+        assert!(receiver.next().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fanout_drain_timeout() {
+        let (mut fanout, control, _events) = fanout_with_retry().await;
+        let (mut sender, mut receiver) = build_sender_pair(1).await;
+        sender.send(make_event_array(1)).await.unwrap();
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("0"), None))
+            .unwrap();
         control
             .send(ControlMessage::Replace(
-                ComponentKey::from(sender_id.to_string()),
+                ComponentKey::from("0"),
                 Some(sender),
             ))
-            .expect("sending control message should not fail");
+            .unwrap();
+        fanout.control_quiescent().await;
 
-        old_receiver
+        // The replacement sink is full, so the retry never fits, and the output is dropped once the
+        // timeout elapses, without failing the drain:
+        assert_eq!(fanout.drain(Some(Duration::from_millis(10))).await, Ok(()));
+        assert!(receiver.next().await.is_some());
+        assert!(receiver.next().await.is_none());
     }
 
-    async fn start_sender_replace(
-        control: &UnboundedSender<ControlMessage>,
-        receivers: &mut Vec<BufferReceiver<EventArray>>,
-        sender_id: usize,
-        capacity: usize,
-    ) -> (BufferReceiver<EventArray>, BufferSender<EventArray>) {
-        let (sender, receiver) = build_sender_pair(capacity).await;
-        let old_receiver = mem::replace(&mut receivers[sender_id], receiver);
-
+    #[tokio::test(start_paused = true)]
+    async fn fanout_close_timeout() {
+        let (fanout, control, _events) = fanout_with_retry().await;
+        let mut fanout = fanout.with_close_timeout(Duration::from_millis(10));
+        let (mut sender, _receiver) = build_sender_pair(1).await;
+        sender.send(make_event_array(1)).await.unwrap();
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("0"), None))
+            .unwrap();
         control
             .send(ControlMessage::Replace(
-                ComponentKey::from(sender_id.to_string()),
-                None,
+                ComponentKey::from("0"),
+                Some(sender),
             ))
-            .expect("sending control message should not fail");
+            .unwrap();
+        let (sender, _other_receiver) = build_sender_pair(1).await;
+        control
+            .send(ControlMessage::Add(ComponentKey::from("1"), sender))
+            .unwrap();
+        fanout.control_quiescent().await;
 
-        (old_receiver, sender)
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(EventRecorder(Arc::clone(&events)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // Without a timeout of its own, the drain uses the close timeout. Outputs are drained in
+        // turn, so both the output it was stuck on and the one after it are logged as dropped:
+        assert_eq!(fanout.drain(None).await, Ok(()));
+        let dropped = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|fields| fields["message"] == "Timed out draining fanout output, dropping it.")
+            .map(|fields| fields["output"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(dropped, vec!["0".to_string(), "1".to_string()]);
     }
 
-    fn finish_sender_replace(
-        control: &UnboundedSender<ControlMessage>,
-        sender_id: usize,
-        sender: BufferSender<EventArray>,
-    ) {
+    #[tokio::test(start_paused = true)]
+    async fn fanout_drain_errored_and_timed_out() {
+        let (fanout, control) = Fanout::new();
+        let mut fanout = fanout.with_tier_policies(HashMap::from([(
+            1,
+            TierPolicy::AtLeastOnce { max_queued: 4 },
+        )]));
+        for id in ["0", "1"] {
+            let (sender, _) = build_sender_pair(4).await;
+            control
+                .send(ControlMessage::AddWithTier(
+                    ComponentKey::from(id),
+                    sender,
+                    1,
+                ))
+                .unwrap();
+        }
+
+        // Both outputs fail, since their receivers are already gone, and hold on to the array for a
+        // retry. The second one's replacement is full, so its retry never fits:
+        fanout.send(make_event_array(1)).await;
+        let (mut sender, _receiver) = build_sender_pair(1).await;
+        sender.send(make_event_array(1)).await.unwrap();
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("1"), None))
+            .unwrap();
         control
             .send(ControlMessage::Replace(
-                ComponentKey::from(sender_id.to_string()),
+                ComponentKey::from("1"),
                 Some(sender),
             ))
-            .expect("sending control message should not fail");
+            .unwrap();
+        fanout.control_quiescent().await;
+        let fanout = fanout.with_error_policy(FanoutErrorPolicy::DropErroredSink);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(EventRecorder(Arc::clone(&events)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // The first output fails to drain, and is removed for it, while only the second is logged as
+        // dropped once the timeout elapses:
+        assert_eq!(fanout.drain(Some(Duration::from_millis(10))).await, Err(()));
+        let dropped = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|fields| fields["message"] == "Timed out draining fanout output, dropping it.")
+            .map(|fields| fields["output"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(dropped, vec!["1".to_string()]);
     }
 
-    fn unwrap_log_event_message<E>(event: E) -> String
-    where
-        E: EventContainer,
-    {
-        let event = event
-            .into_events()
-            .next()
-            .expect("must have at least one event");
-        let event = event.into_log();
-        event
-            .get("message")
-            .and_then(Value::as_bytes)
-            .and_then(|b| String::from_utf8(b.to_vec()).ok())
-            .expect("must be valid log event with `message` field")
+    #[tokio::test(start_paused = true)]
+    async fn fanout_quarantine() {
+        let (fanout, control) = Fanout::new();
+        let mut fanout = fanout.with_error_policy(FanoutErrorPolicy::Quarantine {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+        });
+
+        // The first sink fails, since its receiver is already gone, while the one that replaces it
+        // succeeds:
+        let (failing, _) = build_sender_pair(4).await;
+        let (working, receiver) = build_sender_pair(4).await;
+        let mut sinks = vec![working, failing];
+        let factory: SinkFactory = Box::new(move || sinks.pop().expect("too many sinks created"));
+        control
+            .send(ControlMessage::AddWithFactory(
+                ComponentKey::from("0"),
+                factory,
+            ))
+            .unwrap();
+        let (sender, other_receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Add(ComponentKey::from("1"), sender))
+            .unwrap();
+
+        let events = make_events(3);
+        fanout.send(events[0].clone().into()).await;
+
+        // While quarantined, the output is skipped, without holding up the other output, and
+        // isn't counted as ready:
+        assert!(fanout.sink_is_lossy(&ComponentKey::from("0")).unwrap());
+        assert_eq!(fanout.ready_sink_count(), 1);
+        fanout.send(events[1].clone().into()).await;
+
+        // Once the backoff has elapsed, it's reinstated with a new sink from its factory:
+        advance(Duration::from_secs(1)).await;
+        fanout.send(events[2].clone().into()).await;
+
+        assert_eq!(collect_ready_events(receiver.into_stream()), &events[2..]);
+        assert_eq!(collect_ready_events(other_receiver.into_stream()), events);
+        assert_eq!(fanout.len(), 2);
+        assert_eq!(fanout.ready_sink_count(), 2);
     }
 
-    #[tokio::test]
-    async fn fanout_writes_to_all() {
-        let (mut fanout, _, receivers) = fanout_from_senders(&[2, 2]).await;
-        let events = make_event_array(2);
+    #[tokio::test(start_paused = true)]
+    async fn fanout_quarantine_min_ready_sinks() {
+        let (fanout, control) = Fanout::new();
+        let mut fanout = fanout
+            .with_error_policy(FanoutErrorPolicy::Quarantine {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(10),
+            })
+            .with_min_ready_sinks(1);
+        let (failing, _) = build_sender_pair(4).await;
+        let (working, receiver) = build_sender_pair(4).await;
+        let mut sinks = vec![working, failing];
+        let factory: SinkFactory = Box::new(move || sinks.pop().expect("too many sinks created"));
+        control
+            .send(ControlMessage::AddWithFactory(
+                ComponentKey::from("0"),
+                factory,
+            ))
+            .unwrap();
+        let events = make_events(2);
+        fanout.send(events[0].clone().into()).await;
 
-        let clones = events.clone();
-        fanout.send(clones).await;
+        // The only output is quarantined, so the next send waits until it's reinstated:
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        advance(Duration::from_secs(1)).await;
+        assert_ready!(send.poll());
+        drop(send);
 
-        for receiver in receivers {
-            assert_eq!(collect_ready(receiver.into_stream()), &[events.clone()]);
-        }
+        assert_eq!(collect_ready_events(receiver.into_stream()), &events[1..]);
     }
 
-    #[tokio::test]
-    async fn fanout_notready() {
-        let (mut fanout, _, mut receivers) = fanout_from_senders(&[2, 1, 2]).await;
+    #[tokio::test(start_paused = true)]
+    async fn fanout_quarantine_min_ready_sinks_control_closed() {
+        let (fanout, control) = Fanout::new();
+        let mut fanout = fanout
+            .with_error_policy(FanoutErrorPolicy::Quarantine {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(10),
+            })
+            .with_min_ready_sinks(1);
+        let (failing, _) = build_sender_pair(4).await;
+        let (working, receiver) = build_sender_pair(4).await;
+        let mut sinks = vec![working, failing];
+        let factory: SinkFactory = Box::new(move || sinks.pop().expect("too many sinks created"));
+        control
+            .send(ControlMessage::AddWithFactory(
+                ComponentKey::from("0"),
+                factory,
+            ))
+            .unwrap();
         let events = make_events(2);
+        fanout.send(events[0].clone().into()).await;
 
-        // First send should immediately complete because all senders have capacity:
-        let mut first_send = spawn(fanout.send(events[0].clone().into()));
-        assert_ready!(first_send.poll());
-        drop(first_send);
+        // Closing the control channel while the only output is quarantined doesn't keep it from
+        // being reinstated:
+        drop(control);
+        let mut send = spawn(fanout.send(events[1].clone().into()));
+        assert_pending!(send.poll());
+        advance(Duration::from_secs(1)).await;
+        assert_ready!(send.poll());
+        drop(send);
 
-        // Second send should return pending because sender B is now full:
-        let mut second_send = spawn(fanout.send(events[1].clone().into()));
-        assert_pending!(second_send.poll());
+        assert_eq!(collect_ready_events(receiver.into_stream()), &events[1..]);
+    }
 
-        // Now read an item from each receiver to free up capacity for the second sender:
-        for receiver in &mut receivers {
-            assert_eq!(Some(events[0].clone().into()), receiver.next().await);
+    #[tokio::test]
+    async fn fanout_current_rr_target() {
+        let (fanout, control, _receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        let mut fanout = fanout.with_strategy(Box::new(RoundRobin::default()));
+        let events = make_events(5);
+        let target = |id: &str| Some(ComponentKey::from(id));
+
+        assert_eq!(fanout.current_rr_target(), target("0"));
+        for event in &events[..3] {
+            fanout.send(event.clone().into()).await;
         }
+        // After a full turn, the target wraps around to the first output:
+        assert_eq!(fanout.current_rr_target(), target("0"));
+        fanout.send(events[3].clone().into()).await;
+        assert_eq!(fanout.current_rr_target(), target("1"));
 
-        // Now our second send should actually be able to complete:
-        assert_ready!(second_send.poll());
-        drop(second_send);
+        // Once the second output is removed, the third takes its turn, and the target then wraps
+        // around the remaining outputs:
+        remove_sender_from_fanout(&control, 1);
+        fanout.send(events[4].clone().into()).await;
+        assert_eq!(fanout.current_rr_target(), target("0"));
 
-        // And make sure the second item comes through:
-        for receiver in &mut receivers {
-            assert_eq!(Some(events[1].clone().into()), receiver.next().await);
+        let fanout = Fanout::new().0;
+        assert_eq!(fanout.current_rr_target(), None);
+    }
+
+    #[tokio::test]
+    async fn fanout_work_stealing() {
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[1, 1, 1]).await;
+        let mut fanout = fanout.with_strategy(Box::new(WorkStealing::default()));
+        let events = make_events(4);
+
+        // Each output takes its turn, until all of their buffers are full:
+        for event in &events[..3] {
+            fanout.send(event.clone().into()).await;
         }
+
+        // It's the first output's turn again, but it's still full, so the next array goes to the
+        // next output that has room for it instead:
+        assert_eq!(receivers[1].next().await, Some(events[1].clone().into()));
+        fanout.send(events[3].clone().into()).await;
+        assert_eq!(receivers[1].next().await, Some(events[3].clone().into()));
     }
 
     #[tokio::test]
-    async fn fanout_grow() {
-        let (mut fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
-        let events = make_events(3);
+    async fn fanout_sink_ready_observer() {
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[1, 4]).await;
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        let mut fanout = {
+            let observed = Arc::clone(&observed);
+            fanout.with_sink_ready_observer(move |id| observed.lock().unwrap().push(id.clone()))
+        };
+        let events = make_events(2);
 
-        // Send in the first two events to our initial two senders:
+        // Both outputs accept the first array straight away, so neither becomes ready:
         fanout.send(events[0].clone().into()).await;
-        fanout.send(events[1].clone().into()).await;
-
-        // Now add a third sender:
-        add_sender_to_fanout(&mut fanout, &mut receivers, 2, 4).await;
+        assert!(observed.lock().unwrap().is_empty());
 
-        // Send in the last event which all three senders will now get:
-        fanout.send(events[2].clone().into()).await;
+        // The first output is full, so the next send has to wait for it:
+        {
+            let mut send = spawn(fanout.send(events[1].clone().into()));
+            assert_pending!(send.poll());
+            assert!(observed.lock().unwrap().is_empty());
 
-        // Make sure the first two senders got all three events, but the third sender only got the
-        // last event:
-        let expected_events = [&events, &events, &events[2..]];
-        for (i, receiver) in receivers.into_iter().enumerate() {
-            assert_eq!(
-                collect_ready_events(receiver.into_stream()),
-                expected_events[i]
-            );
+            assert_eq!(receivers[0].next().await, Some(events[0].clone().into()));
+            assert!(send.is_woken());
+            assert_ready!(send.poll());
         }
+        assert_eq!(*observed.lock().unwrap(), vec![ComponentKey::from("0")]);
     }
 
-    #[tokio::test]
-    async fn fanout_shrink() {
-        let (mut fanout, control, receivers) = fanout_from_senders(&[4, 4]).await;
-        let events = make_events(3);
+    #[tokio::test(start_paused = true)]
+    async fn fanout_latency_alert() {
+        let (fanout, _control, mut receivers) = fanout_from_senders(&[1, 4]).await;
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let mut fanout = {
+            let alerts = Arc::clone(&alerts);
+            fanout.with_latency_alert(Duration::from_secs(1), move |id, latency| {
+                alerts.lock().unwrap().push((id.clone(), latency));
+            })
+        };
+        let events = make_events(2);
 
-        // Send in the first two events to our initial two senders:
+        // Both outputs accept the first array straight away:
         fanout.send(events[0].clone().into()).await;
-        fanout.send(events[1].clone().into()).await;
-
-        // Now remove the second sender:
-        remove_sender_from_fanout(&control, 1);
+        assert!(alerts.lock().unwrap().is_empty());
 
-        // Send in the last event which only the first sender will get:
-        fanout.send(events[2].clone().into()).await;
+        // The first output is full, and is only drained once the threshold has passed, while the
+        // second still accepts the array straight away:
+        {
+            let mut send = spawn(fanout.send(events[1].clone().into()));
+            assert_pending!(send.poll());
+            advance(Duration::from_secs(2)).await;
+            assert!(alerts.lock().unwrap().is_empty());
 
-        // Make sure the first sender got all three events, but the second sender only got the first two:
-        let expected_events = [&events, &events[..2]];
-        for (i, receiver) in receivers.into_iter().enumerate() {
-            assert_eq!(
-                collect_ready_events(receiver.into_stream()),
-                expected_events[i]
-            );
+            assert_eq!(receivers[0].next().await, Some(events[0].clone().into()));
+            assert_ready!(send.poll());
         }
+        assert_eq!(
+            *alerts.lock().unwrap(),
+            vec![(ComponentKey::from("0"), Duration::from_secs(2))]
+        );
     }
 
     #[tokio::test]
-    async fn fanout_shrink_when_notready() {
-        // This test exercises that when we're waiting for a send to complete, we can correctly
-        // remove a sink whether or not it is the one that the send operation is still waiting on.
-        //
-        // This means that if we remove a sink that a current send is blocked on, we should be able
-        // to immediately proceed.
-        let events = make_events(2);
-        let expected_first_event = unwrap_log_event_message(events[0].clone());
-        let expected_second_event = unwrap_log_event_message(events[1].clone());
-
-        let cases = [
-            // Sender ID to drop, whether the second send should succeed after dropping, and the
-            // final "last event" a receiver should see after the second send:
-            (
-                0,
-                false,
-                [
-                    expected_second_event.clone(),
-                    expected_first_event.clone(),
-                    expected_second_event.clone(),
-                ],
-            ),
-            (
-                1,
-                true,
-                [
-                    expected_second_event.clone(),
-                    expected_second_event.clone(),
-                    expected_second_event.clone(),
-                ],
-            ),
-            (
-                2,
-                false,
-                [
-                    expected_second_event.clone(),
-                    expected_first_event.clone(),
-                    expected_second_event.clone(),
-                ],
-            ),
-        ];
+    async fn fanout_send_all_arrays() {
+        let (mut fanout, _control, receivers) = fanout_from_senders(&[8, 8]).await;
+        let events = make_events(5);
 
-        for (sender_id, should_complete, expected_last_seen) in cases {
-            let (mut fanout, control, mut receivers) = fanout_from_senders(&[2, 1, 2]).await;
+        fanout
+            .send_all_arrays(events.iter().cloned().map(Into::into).collect())
+            .await;
 
-            // First send should immediately complete because all senders have capacity:
-            let mut first_send = spawn(fanout.send(events[0].clone().into()));
-            assert_ready!(first_send.poll());
-            drop(first_send);
+        for receiver in receivers {
+            assert_eq!(collect_ready_events(receiver.into_stream()), events);
+        }
+    }
 
-            // Second send should return pending because sender B is now full:
-            let mut second_send = spawn(fanout.send(events[1].clone().into()));
-            assert_pending!(second_send.poll());
+    #[tokio::test]
+    async fn fanout_trace_state() {
+        let (fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let mut fanout = fanout.with_name("router");
+        let (sender, _filtered_receiver) = build_sender_pair(4).await;
+        let reject_all: Filter = Arc::new(|_: &EventArray| false);
+        control
+            .send(ControlMessage::AddFiltered(
+                ComponentKey::from("2"),
+                sender,
+                reject_all,
+            ))
+            .unwrap();
+        drop(receivers.pop());
+        fanout.send(make_event_array(1)).await;
+        fanout.pause(&ComponentKey::from("1"));
 
-            // Now drop our chosen sender and assert that polling the second send behaves as expected:
-            remove_sender_from_fanout(&control, sender_id);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Registry::default().with(EventRecorder(Arc::clone(&events)));
+        tracing::subscriber::with_default(subscriber, || fanout.trace_state());
 
-            if should_complete {
-                assert_ready!(second_send.poll());
-            } else {
-                assert_pending!(second_send.poll());
-            }
-            drop(second_send);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let fields = &events[0];
+        assert_eq!(fields["message"], "Fanout state.");
+        assert_eq!(fields["name"], "router");
+        assert_eq!(fields["output_count"], "3");
+        assert_eq!(
+            fields["outputs"],
+            r#"[("0", "ready", 1, 0, 0), ("1", "awaiting_replacement", 0, 1, 0), ("2", "ready", 0, 0, 1)]"#
+        );
+        assert_eq!(fields["logs_sent"], "1");
+        assert_eq!(fields["metrics_sent"], "0");
+        assert_eq!(fields["traces_sent"], "0");
+    }
 
-            // Now grab the last value available to each receiver and assert it's the second event.
-            drop(fanout);
+    #[tokio::test]
+    async fn fanout_preserves_per_output_ordering() {
+        let (mut fanout, control, mut receivers) = fanout_from_senders(&[64, 64, 64, 64]).await;
+        let events = make_events(40);
 
-            let mut last_seen = Vec::new();
-            for receiver in &mut receivers {
-                let mut events = Vec::new();
-                while let Some(event) = receiver.next().await {
-                    events.insert(0, event);
+        let mut old_receivers = Vec::new();
+        let mut errored_events = Vec::new();
+        for (i, event) in events.iter().enumerate() {
+            match i {
+                10 => add_control_sender(&control, &mut receivers, 4, 64).await,
+                15 => remove_sender_from_fanout(&control, 1),
+                20 => {
+                    let old_receiver =
+                        replace_sender_in_fanout(&control, &mut receivers, 2, 64).await;
+                    old_receivers.push(old_receiver);
                 }
-
-                last_seen.push(unwrap_log_event_message(events.remove(0)));
+                25 => {
+                    let (old_receiver, new_sender) =
+                        start_sender_replace(&control, &mut receivers, 3, 64).await;
+                    old_receivers.push(old_receiver);
+                    finish_sender_replace(&control, 3, new_sender);
+                }
+                30 => {
+                    // Dropping the receiver makes every send to the output fail from here on:
+                    let (_, placeholder) = build_sender_pair(1).await;
+                    let receiver = mem::replace(&mut receivers[0], placeholder);
+                    errored_events = collect_ready_events(receiver.into_stream());
+                }
+                _ => {}
             }
 
-            assert_eq!(&expected_last_seen[..], &last_seen);
+            fanout.send(event.clone().into()).await;
+        }
+
+        assert_eq!(errored_events, &events[..30]);
+        for receiver in receivers.into_iter().chain(old_receivers) {
+            assert_in_send_order(collect_ready_events(receiver.into_stream()));
         }
     }
 
-    #[tokio::test]
-    async fn fanout_no_sinks() {
-        let (mut fanout, _) = Fanout::new();
-        let events = make_events(2);
+    #[tokio::test(start_paused = true)]
+    async fn error_log_limiter_suppresses_repeated_errors() {
+        let interval = Duration::from_secs(10);
+        let mut limiter = ErrorLogLimiter::new(interval);
+        let first = ComponentKey::from("0");
+        let second = ComponentKey::from("1");
 
-        fanout.send(events[0].clone().into()).await;
-        fanout.send(events[1].clone().into()).await;
+        // Only the first of many rapid errors for an output gets logged:
+        let logged = (0..5).map(|_| limiter.record(&first)).collect::<Vec<_>>();
+        assert_eq!(logged, vec![Some(0), None, None, None, None]);
+
+        // Outputs are limited independently of each other:
+        assert_eq!(limiter.record(&second), Some(0));
+
+        // Once the interval has passed, the next error is logged along with the suppressed count:
+        tokio::time::advance(interval).await;
+        assert_eq!(limiter.record(&first), Some(4));
+        assert_eq!(limiter.record(&first), None);
     }
 
     #[tokio::test]
-    async fn fanout_replace() {
-        let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4, 4]).await;
-        let events = make_events(3);
+    async fn fanout_occupancy() {
+        let (mut fanout, _, mut receivers) = fanout_from_senders(&[4, 4]).await;
+        let events = make_events(2);
 
-        // First two sends should immediately complete because all senders have capacity:
         fanout.send(events[0].clone().into()).await;
         fanout.send(events[1].clone().into()).await;
 
-        // Replace the first sender with a brand new one before polling again:
-        let old_first_receiver = replace_sender_in_fanout(&control, &mut receivers, 0, 4).await;
-
-        // And do the third send which should also complete since all senders still have capacity:
-        fanout.send(events[2].clone().into()).await;
-
-        // Now make sure that the new "first" sender only got the third event, but that the second and
-        // third sender got all three events:
-        let expected_events = [&events[2..], &events, &events];
-        for (i, receiver) in receivers.into_iter().enumerate() {
-            assert_eq!(
-                collect_ready_events(receiver.into_stream()),
-                expected_events[i]
-            );
+        // Drain the second sender, so only the first one is still holding events:
+        for event in &events {
+            assert_eq!(Some(event.clone().into()), receivers[1].next().await);
         }
 
-        // And make sure our original "first" sender got the first two events:
         assert_eq!(
-            collect_ready_events(old_first_receiver.into_stream()),
-            &events[..2]
+            fanout.occupancy(),
+            vec![
+                (ComponentKey::from("0"), Some((2, 4))),
+                (ComponentKey::from("1"), Some((0, 4))),
+            ]
         );
     }
 
     #[tokio::test]
-    async fn fanout_wait() {
+    async fn fanout_control() {
         let (mut fanout, control, mut receivers) = fanout_from_senders(&[4, 4]).await;
-        let events = make_events(3);
+        let control = FanoutControl::new(control);
+        let other = control.clone();
+        let (sender, receiver) = build_sender_pair(4).await;
+        control.add(ComponentKey::from("2"), sender).unwrap();
+        other.remove(ComponentKey::from("0")).unwrap();
+        let (sender, replacement) = build_sender_pair(4).await;
+        control.replace(ComponentKey::from("1"), None).unwrap();
+        other
+            .replace(ComponentKey::from("1"), Some(sender))
+            .unwrap();
 
-        // First two sends should immediately complete because all senders have capacity:
-        let send1 = Box::pin(fanout.send(events[0].clone().into()));
-        assert_ready!(poll!(send1));
-        let send2 = Box::pin(fanout.send(events[1].clone().into()));
-        assert_ready!(poll!(send2));
+        let events = make_event_array(1);
+        fanout.send(events.clone()).await;
+        for receiver in [replacement, receiver] {
+            assert_eq!(collect_ready(receiver.into_stream()), vec![events.clone()]);
+        }
+        assert!(collect_ready(receivers.remove(0).into_stream()).is_empty());
 
-        // Now do an empty replace on the second sender, which we'll test to make sure that `Fanout`
-        // doesn't let any writes through until we replace it properly.  We get back the receiver
-        // we've replaced, but also the sender that we want to eventually install:
-        let (old_first_receiver, new_first_sender) =
-            start_sender_replace(&control, &mut receivers, 0, 4).await;
+        // Once the fanout is dropped, the message is handed back:
+        drop(fanout);
+        assert!(matches!(
+            control.remove(ComponentKey::from("2")),
+            Err(SendError(ControlMessage::Remove(_)))
+        ));
+    }
 
-        // Third send should return pending because now we have an in-flight replacement:
-        let mut third_send = spawn(fanout.send(events[2].clone().into()));
-        assert_pending!(third_send.poll());
+    #[tokio::test]
+    async fn fanout_bounded_control() {
+        let (mut fanout, control) = Fanout::with_bounded_control(1);
+        let (sender, receiver) = build_sender_pair(4).await;
+        control
+            .send(ControlMessage::Add(ComponentKey::from("0"), sender))
+            .unwrap();
 
-        // Finish our sender replacement, which should wake up the third send and allow it to
-        // actually complete:
-        finish_sender_replace(&control, 0, new_first_sender);
-        assert!(third_send.is_woken());
-        assert_ready!(third_send.poll());
+        // The channel is full, so a further message is handed back, or waited on:
+        let (sender, other_receiver) = build_sender_pair(4).await;
+        let result = control.send(ControlMessage::Add(ComponentKey::from("1"), sender));
+        let sender = match result {
+            Err(SendError(ControlMessage::Add(_, sender))) => sender,
+            _ => panic!("full control channel should hand back the message"),
+        };
+        let mut waiting =
+            spawn(control.send_waiting(ControlMessage::Add(ComponentKey::from("1"), sender)));
+        assert_pending!(waiting.poll());
+
+        // Sending isn't held up by the full channel, and makes room in it:
+        let events = make_event_array(1);
+        fanout.send(events.clone()).await;
+        assert_ready!(waiting.poll()).unwrap();
+        fanout.send(events.clone()).await;
 
-        // Make sure the original first sender got the first two events, the new first sender got
-        // the last event, and the second sender got all three:
         assert_eq!(
-            collect_ready_events(old_first_receiver.into_stream()),
-            &events[0..2]
+            collect_ready(receiver.into_stream()),
+            vec![events.clone(), events.clone()]
         );
+        assert_eq!(collect_ready(other_receiver.into_stream()), vec![events]);
+    }
 
-        let expected_events = [&events[2..], &events];
-        for (i, receiver) in receivers.into_iter().enumerate() {
-            assert_eq!(
-                collect_ready_events(receiver.into_stream()),
-                expected_events[i]
-            );
+    #[tokio::test]
+    async fn fanout_snapshot() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[4, 4, 4]).await;
+        for message in [
+            ControlMessage::Pause(ComponentKey::from("0")),
+            ControlMessage::Replace(ComponentKey::from("2"), None),
+        ] {
+            control.send(message).unwrap();
         }
+        fanout.control_quiescent().await;
+
+        assert_eq!(
+            fanout.snapshot(),
+            FanoutSnapshot {
+                outputs: vec![
+                    (ComponentKey::from("0"), false),
+                    (ComponentKey::from("1"), true),
+                    (ComponentKey::from("2"), false),
+                ],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn fanout_ready_status() {
+        let (mut fanout, control, _receivers) = fanout_from_senders(&[1, 4, 4]).await;
+        fanout.send(make_event_array(1)).await;
+        control
+            .send(ControlMessage::Replace(ComponentKey::from("2"), None))
+            .unwrap();
+        fanout.control_quiescent().await;
+
+        // The first output is full, and the last is awaiting replacement, so only the second is
+        // ready:
+        assert_eq!(
+            fanout.ready_status(),
+            vec![
+                (ComponentKey::from("0"), false),
+                (ComponentKey::from("1"), true),
+                (ComponentKey::from("2"), false),
+            ]
+        );
+    }
+
+    /// Asserts that the given events, as made by `make_events`, are in the order they were made.
+    fn assert_in_send_order(events: Vec<Event>) {
+        let lines = events
+            .into_iter()
+            .map(|event| {
+                unwrap_log_event_message(event)
+                    .trim_start_matches("line ")
+                    .parse::<usize>()
+                    .expect("must be a line number")
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            lines.windows(2).all(|pair| pair[0] < pair[1]),
+            "events received out of order: {:?}",
+            lines
+        );
     }
 
     fn _make_events(count: usize) -> impl Iterator<Item = LogEvent> {